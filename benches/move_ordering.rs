@@ -0,0 +1,23 @@
+use cozy_chess::Board;
+use eccat::bench::{ordered_moves, time_per_call, TACTICAL_POSITIONS};
+
+const ITERATIONS: u32 = 100_000;
+
+fn main() {
+    let boards = TACTICAL_POSITIONS
+        .iter()
+        .map(|fen| fen.parse::<Board>().unwrap())
+        .collect::<Vec<_>>();
+
+    println!("ordering a node's full move list, {ITERATIONS} iterations per position");
+
+    for (fen, board) in TACTICAL_POSITIONS.iter().zip(&boards) {
+        let time = time_per_call(ITERATIONS, || ordered_moves(board));
+
+        println!(
+            "{:>8} ns  {:>3} moves  {fen}",
+            time.as_nanos(),
+            ordered_moves(board).len()
+        );
+    }
+}
@@ -0,0 +1,19 @@
+use cozy_chess::Board;
+use eccat::bench::{see_captures, time_per_call, TACTICAL_POSITIONS};
+
+const ITERATIONS: u32 = 100_000;
+
+fn main() {
+    let boards = TACTICAL_POSITIONS
+        .iter()
+        .map(|fen| fen.parse::<Board>().unwrap())
+        .collect::<Vec<_>>();
+
+    println!("see over every capture, {ITERATIONS} iterations per position");
+
+    for (fen, board) in TACTICAL_POSITIONS.iter().zip(&boards) {
+        let time = time_per_call(ITERATIONS, || see_captures(board));
+
+        println!("{:>8} ns  {fen}", time.as_nanos());
+    }
+}
@@ -0,0 +1,137 @@
+use crate::{
+    search::{fixed_depth_search, is_capture, order_moves, Heuristic, MAX_MOVES},
+    see::see,
+};
+use arrayvec::ArrayVec;
+use core::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+use cozy_chess::{Board, Move};
+use std::time::Instant;
+
+pub const DEFAULT_DEPTH: u8 = 10;
+
+pub const BENCH_POSITIONS: [&str; 5] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R w KQ - 0 8",
+    "2r3k1/pp3ppp/4p3/3pP3/3P1P2/1P3QP1/P5KP/2q5 b - - 0 1",
+];
+
+pub const TACTICAL_POSITIONS: [&str; 8] = [
+    "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1",
+    "1k1r3q/1ppn3p/p4b2/4p3/8/P2N2P1/1PP1R1BP/2K1Q3 w - - 0 1",
+    "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+    "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - 0 1",
+    "3r1rk1/p1q2ppp/1pn1pn2/2b5/2P1P3/P1N1BN2/1P2BPPP/R2Q1RK1 w - - 0 1",
+    "r2q1rk1/1b1nbppp/p3pn2/1p1pN3/3P4/P1NBP3/1P1BQPPP/R4RK1 w - - 0 1",
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct BenchResult {
+    pub nodes: u64,
+    pub time: Duration,
+}
+
+impl BenchResult {
+    #[must_use]
+    pub fn nps(&self) -> u64 {
+        #[allow(
+            clippy::cast_precision_loss,
+            clippy::cast_possible_truncation,
+            clippy::cast_sign_loss
+        )]
+        let nps = (self.nodes as f64 / self.time.as_secs_f64().max(f64::EPSILON)) as u64;
+
+        nps
+    }
+}
+
+/// The line `OpenBench` reads the bench result from.
+impl Display for BenchResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} nodes {} nps", self.nodes, self.nps())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ablation {
+    pub disabled: Option<&'static str>,
+    pub result: BenchResult,
+}
+
+#[must_use]
+pub fn run(depth: u8) -> BenchResult {
+    run_without(depth, &[])
+}
+
+/// The baseline, with everything enabled, is reported first.
+pub fn ablate(depth: u8, mut on_result: impl FnMut(&Ablation)) {
+    on_result(&Ablation {
+        disabled: None,
+        result: run(depth),
+    });
+
+    for heuristic in Heuristic::ALL {
+        on_result(&Ablation {
+            disabled: Some(heuristic.name()),
+            result: run_without(depth, &[heuristic]),
+        });
+    }
+}
+
+fn run_without(depth: u8, disabled: &[Heuristic]) -> BenchResult {
+    let start = Instant::now();
+
+    let nodes = BENCH_POSITIONS
+        .iter()
+        .map(|fen| {
+            let board = fen.parse::<Board>().unwrap();
+
+            fixed_depth_search(&board, depth, disabled)
+        })
+        .sum();
+
+    BenchResult {
+        nodes,
+        time: start.elapsed(),
+    }
+}
+
+#[must_use]
+pub fn see_captures(board: &Board) -> i64 {
+    let mut total = 0;
+
+    board.generate_moves(|mvs| {
+        for mv in mvs {
+            if is_capture(board, mv) {
+                total += i64::from(see(board, mv));
+            }
+        }
+
+        false
+    });
+
+    total
+}
+
+#[must_use]
+pub fn ordered_moves(board: &Board) -> ArrayVec<Move, MAX_MOVES> {
+    order_moves(board)
+}
+
+pub fn time_per_call<T>(iterations: u32, mut f: impl FnMut() -> T) -> Duration {
+    core::hint::black_box(f());
+
+    let start = Instant::now();
+
+    for _ in 0..iterations {
+        core::hint::black_box(f());
+    }
+
+    start.elapsed() / iterations.max(1)
+}
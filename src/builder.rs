@@ -0,0 +1,137 @@
+use crate::{
+    io::{Input, Output},
+    uci::Uci,
+    Engine, EngineError, HashOption, SharedHash, SpinOption, ThreadsOption,
+};
+use core::fmt::Debug;
+
+/// The options are set when the engine starts, as if sent with `setoption`.
+#[derive(Default)]
+pub struct EngineBuilder {
+    options: Vec<(String, String)>,
+    hash: Option<usize>,
+    threads: Option<usize>,
+    shared_hash: Option<SharedHash>,
+    input: Option<Box<dyn Input>>,
+    output: Option<Box<dyn Output>>,
+}
+
+impl Debug for EngineBuilder {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EngineBuilder")
+            .field("options", &self.options)
+            .field("hash", &self.hash)
+            .field("threads", &self.threads)
+            .field("shared_hash", &self.shared_hash)
+            .finish_non_exhaustive()
+    }
+}
+
+impl EngineBuilder {
+    /// In MB.
+    #[must_use]
+    pub const fn hash(mut self, mb_size: usize) -> Self {
+        self.hash = Some(mb_size);
+        self
+    }
+
+    #[must_use]
+    pub const fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    #[must_use]
+    pub fn option(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.options.push((name.into(), value.into()));
+        self
+    }
+
+    #[must_use]
+    pub fn shared_hash(mut self, hash: SharedHash) -> Self {
+        self.shared_hash = Some(hash);
+        self
+    }
+
+    #[must_use]
+    pub fn input(mut self, input: impl Input + 'static) -> Self {
+        self.input = Some(Box::new(input));
+        self
+    }
+
+    #[must_use]
+    pub fn output(mut self, output: impl Output + 'static) -> Self {
+        self.output = Some(Box::new(output));
+        self
+    }
+
+    /// Other options are only checked once the engine starts.
+    pub fn build(self) -> Result<Engine, EngineError> {
+        let mut options = Vec::new();
+
+        if let Some(hash) = self.hash {
+            options.push(spin::<HashOption>(hash)?);
+        }
+
+        if let Some(threads) = self.threads {
+            options.push(spin::<ThreadsOption>(threads)?);
+        }
+
+        options.extend(self.options);
+
+        let mut engine = Engine::new().with_options(options);
+
+        if let Some(hash) = self.shared_hash {
+            engine = engine.with_shared_hash(hash);
+        }
+
+        if self.input.is_some() || self.output.is_some() {
+            engine.uci = Uci::with_io(
+                self.input.unwrap_or_else(|| Box::new(std::io::stdin())),
+                self.output.unwrap_or_else(|| Box::new(std::io::stdout())),
+            );
+        }
+
+        Ok(engine)
+    }
+}
+
+fn spin<O: SpinOption>(value: usize) -> Result<(String, String), EngineError> {
+    let value = i64::try_from(value)?;
+
+    O::check(value)?;
+
+    Ok((O::name().to_string(), value.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build() {
+        let engine = Engine::builder()
+            .hash(4)
+            .threads(2)
+            .option("Contempt", "10")
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            engine.startup_options,
+            [
+                ("Hash".to_string(), "4".to_string()),
+                ("Threads".to_string(), "2".to_string()),
+                ("Contempt".to_string(), "10".to_string()),
+            ]
+        );
+
+        assert!(matches!(
+            Engine::builder().threads(0).build(),
+            Err(EngineError::OutOfRange {
+                option: "Threads",
+                ..
+            })
+        ));
+    }
+}
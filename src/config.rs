@@ -0,0 +1,117 @@
+use std::path::Path;
+
+pub const DEFAULT_CONFIG_FILE: &str = "eccat.toml";
+
+/// Reads option defaults from `Name = value` lines, in the flat subset of TOML:
+///
+/// ```toml
+/// # comments and blank lines are skipped
+/// Hash = 256
+/// Threads = 4
+/// GameDatabase = "/games/eccat.db"
+/// "Eval Noise" = 0
+/// UCI_ShowWDL = true
+/// ```
+///
+/// Names are the UCI option names, quoted if they have spaces.
+pub fn load(path: &Path) -> Result<Vec<(String, String)>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|error| format!("could not read {}: {error}", path.display()))?;
+
+    parse(&text).map_err(|error| format!("{}: {error}", path.display()))
+}
+
+fn parse(text: &str) -> Result<Vec<(String, String)>, String> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(idx, line)| {
+            let line = strip_comment(line).trim();
+
+            (!line.is_empty()).then(|| {
+                parse_line(line).ok_or_else(|| format!("line {}: expected `Name = value`", idx + 1))
+            })
+        })
+        .collect()
+}
+
+fn parse_line(line: &str) -> Option<(String, String)> {
+    let (name, value) = line.split_once('=')?;
+
+    let name = unquote(name.trim())?;
+    let value = unquote(value.trim())?;
+
+    (!name.is_empty()).then_some((name, value))
+}
+
+/// Quotes and backslashes can be escaped inside quotes.
+fn unquote(text: &str) -> Option<String> {
+    let Some(quoted) = text.strip_prefix('"') else {
+        return (!text.contains('"')).then(|| text.to_string());
+    };
+
+    let quoted = quoted.strip_suffix('"')?;
+
+    let mut unquoted = String::new();
+    let mut chars = quoted.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unquoted.push(chars.next()?),
+            '"' => return None,
+            c => unquoted.push(c),
+        }
+    }
+
+    Some(unquoted)
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    let mut escaped = false;
+
+    for (idx, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '#' if !in_quotes => return &line[..idx],
+            _ => {}
+        }
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let options = parse(
+            "# engine defaults\n\
+             Hash = 256\n\
+             \n\
+             GameDatabase = \"C:\\\\games\\\\eccat # 1.db\" # the fast disk\n\
+             \"Eval Noise\" = 0\n",
+        )
+        .unwrap();
+
+        let expected = [
+            ("Hash", "256"),
+            ("GameDatabase", "C:\\games\\eccat # 1.db"),
+            ("Eval Noise", "0"),
+        ];
+
+        assert_eq!(
+            options,
+            expected.map(|(name, value)| (name.to_string(), value.to_string()))
+        );
+
+        assert_eq!(
+            parse("Hash = 1\nThreads 4\n").unwrap_err(),
+            "line 2: expected `Name = value`"
+        );
+        assert!(parse("Hash = \"16\n").is_err());
+    }
+}
@@ -0,0 +1,272 @@
+use crate::{
+    evaluate::{Eval, MATE_BOUND},
+    random_board::{random_board, Rng},
+    search::{is_capture, node_limited_search, History},
+    selfplay::is_insufficient_material,
+    tt::TranspositionTable,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use cozy_chess::{Board, Color, GameStatus};
+use std::{
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    path::Path,
+    sync::Mutex,
+};
+
+pub const DEFAULT_NODES: u64 = 5000;
+pub const DEFAULT_GAMES: usize = 1000;
+
+/// Both counts are used, so either side can be the first to move.
+const OPENING_PLIES: [usize; 2] = [8, 9];
+const MAX_GAME_PLIES: usize = 400;
+const HASH_MB: usize = 16;
+
+/// Both searches agreeing on this for `WIN_PLIES` plies in a row ends a game
+/// as a win.
+const WIN_SCORE: Eval = 2000;
+const WIN_PLIES: usize = 4;
+/// Past `DRAW_START` plies, a score within this of zero for `DRAW_PLIES`
+/// plies in a row ends a game as a draw.
+const DRAW_SCORE: Eval = 10;
+const DRAW_PLIES: usize = 10;
+const DRAW_START: usize = 60;
+
+#[derive(Debug)]
+pub struct DatagenConfig {
+    pub games: usize,
+    pub nodes: u64,
+    pub threads: usize,
+    pub seed: u64,
+}
+
+/// Each position is a 32 byte record, with numbers little-endian:
+///
+/// | bytes  | contents                                                         |
+/// |--------|------------------------------------------------------------------|
+/// | 0..8   | occupied squares, bit 0 is a1                                    |
+/// | 8..24  | a nibble per occupied square from a1, low nibble first: the piece |
+/// |        | from pawn = 0 to king = 5, plus 8 for black                      |
+/// | 24     | side to move, 0 for white and 1 for black                        |
+/// | 25     | halfmove clock                                                   |
+/// | 26..28 | fullmove number                                                  |
+/// | 28..30 | search score in centipawns, from white's side                    |
+/// | 30     | result: 0 if black won, 1 for a draw and 2 if white won          |
+/// | 31     | unused, 0                                                        |
+///
+/// Positions in check, with a capture or promotion as the best move, or with a
+/// mate score are left out.
+pub fn run(
+    path: &Path,
+    config: &DatagenConfig,
+    on_game: impl Fn(usize, usize) + Sync,
+) -> std::io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let output = Mutex::new(BufWriter::new(file));
+
+    // games started, and games finished
+    let started = AtomicUsize::new(0);
+    let finished = AtomicUsize::new(0);
+    let positions = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        let workers = (0..config.threads.max(1))
+            .map(|thread| {
+                let (output, started, finished, positions, on_game) =
+                    (&output, &started, &finished, &positions, &on_game);
+
+                scope.spawn(move || -> std::io::Result<()> {
+                    let mut rng = Rng::new(config.seed.wrapping_add(thread as u64));
+                    let tt = TranspositionTable::new(HASH_MB);
+
+                    while started.fetch_add(1, Ordering::Relaxed) < config.games {
+                        tt.clear();
+
+                        let records = play_game(&mut rng, config.nodes, &tt);
+
+                        output.lock().unwrap().write_all(&records.concat())?;
+
+                        let total =
+                            positions.fetch_add(records.len(), Ordering::Relaxed) + records.len();
+
+                        on_game(finished.fetch_add(1, Ordering::Relaxed) + 1, total);
+                    }
+
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+
+        workers
+            .into_iter()
+            .try_for_each(|worker| worker.join().unwrap())
+    })?;
+
+    output.into_inner().unwrap().flush()
+}
+
+fn play_game(rng: &mut Rng, nodes: u64, tt: &TranspositionTable) -> Vec<[u8; 32]> {
+    let plies = OPENING_PLIES[rng.below(OPENING_PLIES.len())];
+    let mut board = random_board(rng, plies);
+
+    let mut history = vec![History { hash: board.hash() }];
+    // positions kept so far, with their scores from white's side
+    let mut kept = Vec::new();
+    let mut win_plies = 0;
+    let mut draw_plies = 0;
+
+    let result = loop {
+        match board.status() {
+            GameStatus::Won => break result_for(!board.side_to_move()),
+            GameStatus::Drawn => break 1,
+            GameStatus::Ongoing => {}
+        }
+
+        let repetitions = history
+            .iter()
+            .filter(|entry| entry.hash == board.hash())
+            .count();
+
+        if repetitions >= 3 || is_insufficient_material(&board) || history.len() > MAX_GAME_PLIES {
+            break 1;
+        }
+
+        let Some((mv, score)) = node_limited_search(&board, &history, nodes, tt) else {
+            break 1;
+        };
+
+        let white_score = match board.side_to_move() {
+            Color::White => score,
+            Color::Black => -score,
+        };
+
+        win_plies = if white_score.abs() >= WIN_SCORE {
+            win_plies + 1
+        } else {
+            0
+        };
+        draw_plies = if white_score.abs() <= DRAW_SCORE {
+            draw_plies + 1
+        } else {
+            0
+        };
+
+        if win_plies >= WIN_PLIES {
+            break result_for(if white_score > 0 {
+                Color::White
+            } else {
+                Color::Black
+            });
+        }
+
+        if draw_plies >= DRAW_PLIES && history.len() > DRAW_START {
+            break 1;
+        }
+
+        let is_noisy = !board.checkers().is_empty()
+            || is_capture(&board, mv)
+            || mv.promotion.is_some()
+            || score.abs() >= MATE_BOUND;
+
+        if !is_noisy {
+            kept.push((board.clone(), white_score));
+        }
+
+        board.play_unchecked(mv);
+        history.push(History { hash: board.hash() });
+    };
+
+    kept.iter()
+        .map(|(board, score)| encode(board, *score, result))
+        .collect()
+}
+
+const fn result_for(winner: Color) -> u8 {
+    match winner {
+        Color::White => 2,
+        Color::Black => 0,
+    }
+}
+
+fn encode(board: &Board, score: Eval, result: u8) -> [u8; 32] {
+    let mut record = [0; 32];
+    let occupied = board.occupied();
+
+    record[..8].copy_from_slice(&occupied.0.to_le_bytes());
+
+    for (idx, square) in occupied.into_iter().enumerate() {
+        let piece = board.piece_on(square).unwrap() as u8;
+        let color = board.color_on(square).unwrap() as u8;
+
+        record[8 + idx / 2] |= (piece | color << 3) << (idx % 2 * 4);
+    }
+
+    record[24] = board.side_to_move() as u8;
+    record[25] = board.halfmove_clock();
+    record[26..28].copy_from_slice(&board.fullmove_number().to_le_bytes());
+    record[28..30].copy_from_slice(&score.to_le_bytes());
+    record[30] = result;
+
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cozy_chess::{BitBoard, BoardBuilder, Piece};
+
+    /// Castling rights and the en passant square aren't stored.
+    fn decode(record: &[u8; 32]) -> Option<(Board, Eval, u8)> {
+        let occupied = u64::from_le_bytes(record[..8].try_into().unwrap());
+
+        let mut builder = BoardBuilder::empty();
+
+        for (idx, square) in BitBoard(occupied).into_iter().enumerate() {
+            let nibble = record[8 + idx / 2] >> (idx % 2 * 4) & 0xf;
+
+            let piece = Piece::try_index(usize::from(nibble & 7))?;
+            let color = Color::index(usize::from(nibble >> 3));
+
+            *builder.square_mut(square) = Some((piece, color));
+        }
+
+        builder.side_to_move = Color::try_index(usize::from(record[24]))?;
+        builder.halfmove_clock = record[25];
+        builder.fullmove_number = u16::from_le_bytes([record[26], record[27]]);
+
+        let score = Eval::from_le_bytes([record[28], record[29]]);
+
+        Some((builder.build().ok()?, score, record[30]))
+    }
+
+    #[test]
+    fn test_encode() {
+        let board = Board::from_fen(
+            "r1bqk2r/pppp1ppp/2n2n2/2b1p3/2B1P3/3P1N2/PPP2PPP/RNBQK2R b - - 2 5",
+            false,
+        )
+        .unwrap();
+
+        let record = encode(&board, -35, 1);
+        let (decoded, score, result) = decode(&record).unwrap();
+
+        assert_eq!(decoded, board);
+        assert_eq!(score, -35);
+        assert_eq!(result, 1);
+    }
+
+    #[test]
+    fn test_play_game() {
+        let tt = TranspositionTable::new(1);
+        let records = play_game(&mut Rng::new(1), 500, &tt);
+
+        assert!(!records.is_empty());
+
+        let results = records.iter().map(|record| record[30]).collect::<Vec<_>>();
+
+        assert!(results
+            .iter()
+            .all(|&result| result == results[0] && result <= 2));
+        assert!(records.iter().all(|record| decode(record).is_some()));
+    }
+}
@@ -0,0 +1,415 @@
+#[cfg(feature = "online")]
+use crate::md5::{self, Md5};
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+#[cfg(feature = "online")]
+use std::io::Read;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+pub const DEFAULT_MIRROR: &str = "https://tablebase.lichess.ovh/tables/standard/3-4-5/";
+
+pub const DEFAULT_THREADS: usize = 4;
+
+pub const MAX_THREADS: usize = 16;
+
+pub const ATTEMPTS: u32 = 4;
+
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub enum DownloadEvent {
+    Started {
+        total: usize,
+    },
+    FileDone {
+        file: String,
+        completed: usize,
+        total: usize,
+        skipped: bool,
+    },
+    FileRetrying {
+        file: String,
+        attempt: u32,
+        error: String,
+    },
+    FileFailed {
+        file: String,
+        error: String,
+    },
+    Finished {
+        dir: PathBuf,
+        failed: Vec<String>,
+        error: Option<String>,
+    },
+}
+
+/// Files are checked against the `.md5` lists the mirror publishes, if any.
+/// Whole tables already in `dir` are kept, and cut-off downloads resume. A
+/// [`DownloadEvent::Finished`] event is always sent last.
+pub fn download_egtb(
+    mirror: &str,
+    dir: &Path,
+    threads: usize,
+    mut on_event: impl FnMut(DownloadEvent),
+) -> Result<(), String> {
+    let mut failed = Vec::new();
+    let result = download_all(mirror, dir, threads, &mut failed, &mut on_event);
+
+    failed.sort();
+
+    on_event(DownloadEvent::Finished {
+        dir: dir.to_path_buf(),
+        failed,
+        error: result.as_ref().err().cloned(),
+    });
+
+    result
+}
+
+fn download_all(
+    mirror: &str,
+    dir: &Path,
+    threads: usize,
+    failed: &mut Vec<String>,
+    on_event: &mut impl FnMut(DownloadEvent),
+) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|err| format!("could not create {}: {err}", dir.display()))?;
+
+    let listing = http_get_string(mirror)?;
+
+    let files = table_files(&listing);
+    let total = files.len();
+
+    if files.is_empty() {
+        return Err(format!("no tablebase files found at {mirror}"));
+    }
+
+    let checksums = fetch_checksums(mirror, &listing)?;
+
+    on_event(DownloadEvent::Started { total });
+
+    let threads = threads.clamp(1, MAX_THREADS).min(total);
+    let next = AtomicUsize::new(0);
+    let completed = AtomicUsize::new(0);
+
+    let (event_tx, event_rx) = crossbeam_channel::bounded(threads);
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            let event_tx = event_tx.clone();
+            let (files, checksums, next, completed) = (&files, &checksums, &next, &completed);
+
+            scope.spawn(move || {
+                while let Some(file) = files.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let checksum = checksums.get(file).map(String::as_str);
+                    let mut attempt = 1;
+
+                    let event = loop {
+                        match download_file(mirror, dir, file, checksum) {
+                            Ok(skipped) => {
+                                break DownloadEvent::FileDone {
+                                    file: file.clone(),
+                                    completed: completed.fetch_add(1, Ordering::Relaxed) + 1,
+                                    total,
+                                    skipped,
+                                }
+                            }
+                            Err(error) if attempt < ATTEMPTS => {
+                                let _ = event_tx.send(DownloadEvent::FileRetrying {
+                                    file: file.clone(),
+                                    attempt,
+                                    error,
+                                });
+
+                                std::thread::sleep(retry_delay(attempt));
+                                attempt += 1;
+                            }
+                            Err(error) => {
+                                break DownloadEvent::FileFailed {
+                                    file: file.clone(),
+                                    error,
+                                }
+                            }
+                        }
+                    };
+
+                    let _ = event_tx.send(event);
+                }
+            });
+        }
+
+        drop(event_tx);
+
+        for event in event_rx {
+            if let DownloadEvent::FileFailed { file, .. } = &event {
+                failed.push(file.clone());
+            }
+
+            on_event(event);
+        }
+    });
+
+    if failed.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} of {total} files failed to download",
+            failed.len()
+        ))
+    }
+}
+
+fn retry_delay(attempt: u32) -> Duration {
+    RETRY_DELAY * 2_u32.pow(attempt - 1)
+}
+
+fn links(listing: &str) -> impl Iterator<Item = &str> {
+    listing
+        .split("href=\"")
+        .skip(1)
+        .filter_map(|chunk| chunk.split('"').next())
+        .filter(|name| !name.contains('/'))
+}
+
+fn table_files(listing: &str) -> Vec<String> {
+    let mut files = links(listing)
+        .filter(|name| {
+            Path::new(name)
+                .extension()
+                .is_some_and(|ext| ext == "rtbw" || ext == "rtbz")
+        })
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    files.sort();
+    files.dedup();
+
+    files
+}
+
+fn fetch_checksums(mirror: &str, listing: &str) -> Result<HashMap<String, String>, String> {
+    let mut checksums = HashMap::new();
+
+    for name in links(listing).filter(|name| {
+        Path::new(name)
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("md5"))
+    }) {
+        checksums.extend(parse_checksums(&http_get_string(&format!(
+            "{mirror}{name}"
+        ))?));
+    }
+
+    Ok(checksums)
+}
+
+/// Reads `md5sum` output as well as the BSD `MD5 (<file>) = <checksum>` form.
+fn parse_checksums(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+
+            let (checksum, path) = if let Some(rest) = line.strip_prefix("MD5 (") {
+                let (path, checksum) = rest.rsplit_once(") = ")?;
+                (checksum, path)
+            } else {
+                let (checksum, path) = line.split_once(char::is_whitespace)?;
+                // md5sum marks files it read in binary mode with a `*`
+                (checksum, path.trim_start().trim_start_matches('*'))
+            };
+
+            let is_md5 = checksum.len() == 32 && checksum.chars().all(|c| c.is_ascii_hexdigit());
+            let file = Path::new(path).file_name()?.to_str()?;
+
+            is_md5.then(|| (file.to_string(), checksum.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+#[cfg(feature = "online")]
+fn file_md5(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut md5 = Md5::new();
+    let mut buffer = vec![0; 1 << 16];
+
+    loop {
+        match file.read(&mut buffer)? {
+            0 => return Ok(md5::to_hex(&md5.finish())),
+            read => md5.update(&buffer[..read]),
+        }
+    }
+}
+
+#[cfg(feature = "online")]
+fn http_get_string(url: &str) -> Result<String, String> {
+    ureq::get(url)
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|err| format!("{url}: {err}"))
+}
+
+/// A file that doesn't match `checksum` is removed, so the next try starts
+/// over.
+#[cfg(feature = "online")]
+fn download_file(
+    mirror: &str,
+    dir: &Path,
+    file: &str,
+    checksum: Option<&str>,
+) -> Result<bool, String> {
+    let url = format!("{mirror}{file}");
+    let target = dir.join(file);
+    let partial = dir.join(format!("{file}.part"));
+
+    let matches = |path: &Path| {
+        checksum.is_none_or(|checksum| file_md5(path).is_ok_and(|actual| actual == checksum))
+    };
+
+    // without a checksum to go by, a finished download is trusted
+    if target.is_file() {
+        if matches(&target) {
+            return Ok(true);
+        }
+
+        std::fs::remove_file(&target).map_err(|err| format!("{}: {err}", target.display()))?;
+    }
+
+    let resume_from = std::fs::metadata(&partial).map_or(0, |metadata| metadata.len());
+
+    let mut request = ureq::get(&url);
+
+    if resume_from > 0 {
+        request = request.header("Range", format!("bytes={resume_from}-"));
+    }
+
+    match request.call() {
+        Ok(response) => {
+            // a server that ignores the range sends all of it again
+            let append = response.status() == ureq::http::StatusCode::PARTIAL_CONTENT;
+
+            let mut out = std::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(append)
+                .truncate(!append)
+                .open(&partial)
+                .map_err(|err| format!("{}: {err}", partial.display()))?;
+
+            std::io::copy(&mut response.into_body().into_reader(), &mut out)
+                .map_err(|err| format!("{url}: {err}"))?;
+        }
+        // the partial file already has every byte
+        Err(ureq::Error::StatusCode(416)) if resume_from > 0 => {}
+        Err(err) => return Err(format!("{url}: {err}")),
+    }
+
+    if !matches(&partial) {
+        let _ = std::fs::remove_file(&partial);
+
+        return Err(format!("{file} doesn't match its checksum"));
+    }
+
+    std::fs::rename(&partial, &target).map_err(|err| format!("{}: {err}", target.display()))?;
+
+    Ok(false)
+}
+
+#[cfg(not(feature = "online"))]
+fn http_get_string(_url: &str) -> Result<String, String> {
+    Err("eccat was built without the `online` feature".to_string())
+}
+
+#[cfg(not(feature = "online"))]
+fn download_file(
+    _mirror: &str,
+    _dir: &Path,
+    _file: &str,
+    _checksum: Option<&str>,
+) -> Result<bool, String> {
+    Err("eccat was built without the `online` feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_listing() {
+        let listing = r#"<a href="../">../</a>
+            <a href="KBvK.rtbw">KBvK.rtbw</a> <a href="KBvK.rtbz">KBvK.rtbz</a>
+            <a href="KBvK.rtbw">KBvK.rtbw</a> <a href="checksum.md5">checksum.md5</a>
+            <a href="readme.txt">readme.txt</a>"#;
+
+        assert_eq!(table_files(listing), ["KBvK.rtbw", "KBvK.rtbz"]);
+
+        let checksums = parse_checksums(
+            "0123456789abcdef0123456789ABCDEF  KBvK.rtbw\n\
+             fedcba9876543210fedcba9876543210 *3-4-5/KBvK.rtbz\n\
+             MD5 (KNvK.rtbw) = 00112233445566778899aabbccddeeff\n\
+             not a checksum  KQvK.rtbw\n",
+        );
+
+        assert_eq!(checksums.len(), 3);
+        assert_eq!(checksums["KBvK.rtbw"], "0123456789abcdef0123456789abcdef");
+        assert_eq!(checksums["KBvK.rtbz"], "fedcba9876543210fedcba9876543210");
+        assert_eq!(checksums["KNvK.rtbw"], "00112233445566778899aabbccddeeff");
+    }
+
+    #[test]
+    fn test_retry_delay() {
+        let delays = (1..ATTEMPTS).map(retry_delay).collect::<Vec<_>>();
+
+        assert_eq!(
+            delays,
+            [
+                Duration::from_secs(1),
+                Duration::from_secs(2),
+                Duration::from_secs(4)
+            ]
+        );
+    }
+
+    #[cfg(feature = "online")]
+    #[test]
+    fn test_file_md5() {
+        let path = std::env::temp_dir().join(format!("eccat-md5-{}", std::process::id()));
+
+        std::fs::write(&path, "abc").unwrap();
+        let checksum = file_md5(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(checksum.unwrap(), "900150983cd24fb0d6963f7d28e17f72");
+    }
+
+    #[cfg(feature = "online")]
+    #[test]
+    fn test_keeps_whole_files() {
+        let dir = std::env::temp_dir().join(format!("eccat-egtb-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("KBvK.rtbw"), "abc").unwrap();
+
+        // never reaches the mirror, since the file is already whole
+        let kept = download_file(
+            "http://mirror.invalid/",
+            &dir,
+            "KBvK.rtbw",
+            Some("900150983cd24fb0d6963f7d28e17f72"),
+        );
+
+        // and one that doesn't match is thrown away and downloaded again
+        let replaced = download_file("http://mirror.invalid/", &dir, "KBvK.rtbw", Some("0"));
+
+        let exists = dir.join("KBvK.rtbw").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(kept, Ok(true));
+        assert!(replaced.is_err());
+        assert!(!exists);
+    }
+}
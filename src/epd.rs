@@ -0,0 +1,293 @@
+use crate::search::timed_search;
+use chrono::Duration;
+use cozy_chess::{util::display_uci_move, Board, Move, Piece};
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+pub const DEFAULT_MOVE_TIME: i64 = 1000;
+
+#[derive(Debug)]
+pub struct EpdPosition {
+    pub board: Board,
+    pub id: Option<String>,
+    /// From `bm`.
+    pub best_moves: Vec<Move>,
+    /// From `am`.
+    pub avoid_moves: Vec<Move>,
+    /// From `c0` in the STS suites, like `"Nf3=10, Nd2=5"`.
+    pub points: Vec<(Move, u32)>,
+}
+
+impl EpdPosition {
+    #[must_use]
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.splitn(5, ' ');
+        let fen = fields.by_ref().take(4).collect::<Vec<_>>();
+
+        if fen.len() < 4 {
+            return None;
+        }
+
+        let board = Board::from_fen(&format!("{} 0 1", fen.join(" ")), false).ok()?;
+
+        let mut position = Self {
+            board,
+            id: None,
+            best_moves: Vec::new(),
+            avoid_moves: Vec::new(),
+            points: Vec::new(),
+        };
+
+        for operation in fields.next().unwrap_or_default().split(';') {
+            let operation = operation.trim();
+            let (opcode, operand) = operation.split_once(' ').unwrap_or((operation, ""));
+            let operand = operand.trim().trim_matches('"');
+
+            match opcode {
+                "id" => position.id = Some(operand.to_string()),
+                "bm" => position.best_moves = parse_moves(&position.board, operand)?,
+                "am" => position.avoid_moves = parse_moves(&position.board, operand)?,
+                "c0" => position.points = parse_points(&position.board, operand),
+                _ => {}
+            }
+        }
+
+        Some(position)
+    }
+
+    #[must_use]
+    pub fn is_solved(&self, mv: Move) -> bool {
+        (self.best_moves.is_empty() || self.best_moves.contains(&mv))
+            && !self.avoid_moves.contains(&mv)
+    }
+
+    #[must_use]
+    pub fn points(&self, mv: Move) -> u32 {
+        self.points
+            .iter()
+            .find(|&&(scored, _)| scored == mv)
+            .map_or(0, |&(_, points)| points)
+    }
+
+    #[must_use]
+    pub fn max_points(&self) -> u32 {
+        self.points
+            .iter()
+            .map(|&(_, points)| points)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+pub fn load(path: &Path) -> std::io::Result<(Vec<EpdPosition>, usize)> {
+    let mut positions = Vec::new();
+    let mut skipped = 0;
+
+    for line in BufReader::new(std::fs::File::open(path)?).lines() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match EpdPosition::parse(&line) {
+            Some(position) => positions.push(position),
+            None => skipped += 1,
+        }
+    }
+
+    Ok((positions, skipped))
+}
+
+#[derive(Debug)]
+pub struct EpdResult {
+    /// In SAN.
+    pub played: String,
+    pub solved: bool,
+    pub points: u32,
+}
+
+pub fn run(
+    positions: &[EpdPosition],
+    move_time: Duration,
+    mut on_result: impl FnMut(&EpdPosition, &EpdResult),
+) {
+    for position in positions {
+        let Some(mv) = timed_search(&position.board, move_time) else {
+            continue;
+        };
+
+        on_result(
+            position,
+            &EpdResult {
+                played: san(&position.board, mv),
+                solved: position.is_solved(mv),
+                points: position.points(mv),
+            },
+        );
+    }
+}
+
+/// Without a check or mate suffix.
+#[must_use]
+pub fn san(board: &Board, mv: Move) -> String {
+    let piece = board.piece_on(mv.from).unwrap();
+    let is_capture = board.colors(!board.side_to_move()).has(mv.to);
+
+    if piece == Piece::King && board.colors(board.side_to_move()).has(mv.to) {
+        return if mv.to.file() > mv.from.file() {
+            "O-O".to_string()
+        } else {
+            "O-O-O".to_string()
+        };
+    }
+
+    let mut san = String::new();
+
+    if piece == Piece::Pawn {
+        if mv.from.file() != mv.to.file() {
+            san.push(char::from(mv.from.file()));
+            san.push('x');
+        }
+    } else {
+        san.push(char::from(piece).to_ascii_uppercase());
+
+        // other pieces of the same kind that could also go there
+        let mut rivals = Vec::new();
+
+        board.generate_moves_for(board.pieces(piece), |moves| {
+            rivals.extend(
+                moves
+                    .into_iter()
+                    .filter(|other| other.to == mv.to && other.from != mv.from),
+            );
+            false
+        });
+
+        if !rivals.is_empty() {
+            if rivals
+                .iter()
+                .all(|other| other.from.file() != mv.from.file())
+            {
+                san.push(char::from(mv.from.file()));
+            } else if rivals
+                .iter()
+                .all(|other| other.from.rank() != mv.from.rank())
+            {
+                san.push(char::from(mv.from.rank()));
+            } else {
+                san.push_str(&mv.from.to_string());
+            }
+        }
+
+        if is_capture {
+            san.push('x');
+        }
+    }
+
+    san.push_str(&mv.to.to_string());
+
+    if let Some(promotion) = mv.promotion {
+        san.push('=');
+        san.push(char::from(promotion).to_ascii_uppercase());
+    }
+
+    san
+}
+
+fn parse_move(board: &Board, text: &str) -> Option<Move> {
+    let text = text
+        .trim_end_matches(['+', '#', '!', '?'])
+        .replace('0', "O");
+
+    let mut found = None;
+
+    board.generate_moves(|moves| {
+        found = moves
+            .into_iter()
+            .find(|&mv| san(board, mv) == text || display_uci_move(board, mv).to_string() == text);
+        found.is_some()
+    });
+
+    found
+}
+
+fn parse_moves(board: &Board, text: &str) -> Option<Vec<Move>> {
+    text.split_whitespace()
+        .map(|mv| parse_move(board, mv))
+        .collect()
+}
+
+fn parse_points(board: &Board, text: &str) -> Vec<(Move, u32)> {
+    text.split(',')
+        .filter_map(|pair| {
+            let (mv, points) = pair.trim().rsplit_once('=')?;
+
+            Some((parse_move(board, mv)?, points.parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_san() {
+        let board = Board::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            false,
+        )
+        .unwrap();
+
+        let san = |mv: &str| san(&board, mv.parse().unwrap());
+
+        assert_eq!(san("e1h1"), "O-O");
+        assert_eq!(san("e1a1"), "O-O-O");
+        assert_eq!(san("d5e6"), "dxe6");
+        assert_eq!(san("e5f7"), "Nxf7");
+        assert_eq!(san("d2c1"), "Bc1");
+        assert_eq!(san("g2h3"), "gxh3");
+
+        let board = Board::from_fen("4k3/1P6/8/R7/7N/8/8/R3K1N1 w - - 0 1", false).unwrap();
+
+        let san = |mv: &str| super::san(&board, mv.parse().unwrap());
+
+        assert_eq!(san("a1a3"), "R1a3");
+        assert_eq!(san("a5a3"), "R5a3");
+        assert_eq!(san("g1f3"), "Ngf3");
+        assert_eq!(san("b7b8n"), "b8=N");
+    }
+
+    #[test]
+    fn test_parse() {
+        let position = EpdPosition::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+        )
+        .unwrap();
+
+        let best = "g3g6".parse().unwrap();
+
+        assert_eq!(position.id.as_deref(), Some("WAC.001"));
+        assert_eq!(position.best_moves, [best]);
+        assert!(position.is_solved(best));
+        assert!(!position.is_solved("f6h7".parse().unwrap()));
+
+        let position = EpdPosition::parse(
+            "1kr5/3n4/q3p2p/p2n2p1/PppB1P2/5BP1/1P2Q2P/3R2K1 w - - bm f5; \
+             c0 \"f5=10, Be5+=2, Bf2=3, Bg4=2\"; am Bxd5;",
+        )
+        .unwrap();
+
+        assert_eq!(position.points.len(), 4);
+        assert_eq!(position.points("f4f5".parse().unwrap()), 10);
+        assert_eq!(position.points("f3g4".parse().unwrap()), 2);
+        assert_eq!(position.max_points(), 10);
+        assert!(!position.is_solved("f3d5".parse().unwrap()));
+
+        assert!(EpdPosition::parse("8/8/8/8/8/8/8/8 w - - bm e4;").is_none());
+        assert!(EpdPosition::parse("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;").is_none());
+    }
+}
@@ -0,0 +1,98 @@
+use core::num::TryFromIntError;
+use cozy_chess::FenParseError;
+use crossbeam_channel::{RecvError, SendError};
+
+#[derive(Debug, thiserror::Error)]
+pub enum EngineError {
+    #[error("unknown command: {0}")]
+    UnknownCommand(String),
+    #[error("expected {expected} after {command}")]
+    Expected {
+        command: &'static str,
+        expected: &'static str,
+    },
+    #[error("no {0} provided")]
+    MissingArgument(String),
+    #[error("invalid {argument}: {reason}")]
+    InvalidArgument { argument: String, reason: String },
+    #[error("invalid fen: {0}")]
+    InvalidFen(FenParseError),
+    #[error("invalid move: {0}")]
+    InvalidMove(String),
+    #[error("illegal move: {0}")]
+    IllegalMove(String),
+    #[error("missing value for {0} option")]
+    MissingValue(&'static str),
+    #[error("invalid value for {option} option: {reason}")]
+    InvalidValue {
+        option: &'static str,
+        reason: String,
+    },
+    #[error("{option} must be between {min} and {max}, not {value}")]
+    OutOfRange {
+        option: &'static str,
+        value: i64,
+        min: i64,
+        max: i64,
+    },
+    /// An option value that fits its range but not what the search takes.
+    #[error("option value doesn't fit the search: {0}")]
+    Overflow(#[from] TryFromIntError),
+    #[error("search thread panicked: {0}")]
+    SearchPanicked(String),
+    #[error("an engine thread has gone away")]
+    Disconnected,
+}
+
+impl EngineError {
+    /// Anything else is just reported to the gui.
+    #[must_use]
+    pub const fn is_fatal(&self) -> bool {
+        matches!(self, Self::SearchPanicked(_) | Self::Disconnected)
+    }
+}
+
+impl From<FenParseError> for EngineError {
+    fn from(error: FenParseError) -> Self {
+        Self::InvalidFen(error)
+    }
+}
+
+impl From<RecvError> for EngineError {
+    fn from(_: RecvError) -> Self {
+        Self::Disconnected
+    }
+}
+
+impl<T> From<SendError<T>> for EngineError {
+    fn from(_: SendError<T>) -> Self {
+        Self::Disconnected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_engine_error() {
+        let error = EngineError::OutOfRange {
+            option: "Threads",
+            value: 0,
+            min: 1,
+            max: 256,
+        };
+
+        assert_eq!(
+            error.to_string(),
+            "Threads must be between 1 and 256, not 0"
+        );
+        assert!(!error.is_fatal());
+
+        let (_, rx) = crossbeam_channel::bounded::<()>(0);
+        let error = EngineError::from(rx.recv().unwrap_err());
+
+        assert!(matches!(error, EngineError::Disconnected));
+        assert!(error.is_fatal());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::evaluate::Eval;
+
+const ENTRIES: usize = 1 << 16;
+
+/// Every search thread has its own, so there's no locking, and a newer
+/// evaluation replaces whatever shared its slot.
+#[derive(Debug)]
+pub struct EvalCache {
+    entries: Box<[(u64, Eval)]>,
+}
+
+impl EvalCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            entries: vec![(0, 0); ENTRIES].into_boxed_slice(),
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, key: u64) -> Option<Eval> {
+        let (stored, eval) = self.entries[index(key)];
+
+        (stored == key).then_some(eval)
+    }
+
+    pub fn insert(&mut self, key: u64, eval: Eval) {
+        self.entries[index(key)] = (key, eval);
+    }
+}
+
+impl Default for EvalCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[allow(clippy::cast_possible_truncation)]
+const fn index(key: u64) -> usize {
+    key as usize & (ENTRIES - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_cache() {
+        let mut cache = EvalCache::new();
+
+        assert_eq!(cache.get(0x1234), None);
+
+        cache.insert(0x1234, 56);
+        assert_eq!(cache.get(0x1234), Some(56));
+
+        // the same slot, so the older one is gone
+        let clash = 0x1234 + ENTRIES as u64;
+
+        cache.insert(clash, -7);
+        assert_eq!(cache.get(clash), Some(-7));
+        assert_eq!(cache.get(0x1234), None);
+    }
+}
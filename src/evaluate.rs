@@ -1,82 +1,62 @@
-use cozy_chess::{BitBoard, Board, Color, Piece};
+use crate::{position::Position, search::MAX_PLY};
+use core::fmt::{self, Display, Formatter};
+use cozy_chess::{
+    get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, BitBoard,
+    Board, Color, Piece, Square,
+};
 
 #[must_use]
-pub fn evaluate(board: &Board) -> Eval {
-    let mut mg = 0;
-    let mut eg = 0;
-    let mut game_phase = 0;
-
-    for square in board.occupied() {
-        if let (Some(piece), Some(piece_colour)) = (board.piece_on(square), board.color_on(square))
-        {
-            let colour_sign = match piece_colour {
-                Color::White => 1,
-                Color::Black => -1,
-            };
-
-            let (mg_value, endgame_value) = piece_square(piece, piece_colour, square);
+pub fn evaluate(position: &Position) -> Eval {
+    evaluate_traced(position, &mut NoTrace)
+}
 
-            mg += mg_value * colour_sign;
-            eg += endgame_value * colour_sign;
+pub fn evaluate_traced<T: Trace>(position: &Position, trace: &mut T) -> Eval {
+    let board = position.board();
 
-            game_phase += match piece {
-                Piece::Pawn | Piece::King => 0,
-                Piece::Knight | Piece::Bishop => 1,
-                Piece::Rook => 2,
-                Piece::Queen => 4,
-            };
+    let mut score = position.piece_square_score();
 
-            if piece == Piece::Pawn {
-                let pawn_files = pawns_in_front_adjacent_files(square, piece_colour);
+    // the running score is only kept up by the moves played, so make sure it
+    // still agrees with adding every piece up again
+    debug_assert_eq!(
+        score,
+        Position::new(board.clone()).piece_square_score(),
+        "incremental piece-square score is out of date in {board}"
+    );
 
-                let pawns_in_front = pawn_files & board.colored_pieces(!piece_colour, Piece::Pawn);
+    if T::ENABLED {
+        trace_piece_squares(board, trace);
+    }
 
-                if pawns_in_front.is_empty() {
-                    let rank = match piece_colour {
-                        Color::White => square.rank(),
-                        Color::Black => square.rank().flip(),
-                    };
+    let pawns = pawn_structure(board, trace);
 
-                    mg += MG_PASSED_PAWN_BONUS[rank as usize] * colour_sign;
-                    eg += EG_PASSED_PAWN_BONUS[rank as usize] * colour_sign;
-                }
-            }
-        }
-    }
+    score.0 += pawns.0;
+    score.1 += pawns.1;
 
-    if board.colored_pieces(Color::White, Piece::Bishop).len() >= 2 {
-        mg += MG_BISHOP_PAIR_BONUS;
-        eg += EG_BISHOP_PAIR_BONUS;
-    }
+    let king_safety = king_safety(board, trace);
 
-    if board.colored_pieces(Color::Black, Piece::Bishop).len() >= 2 {
-        mg -= MG_BISHOP_PAIR_BONUS;
-        eg -= EG_BISHOP_PAIR_BONUS;
-    }
+    score.0 += king_safety.0;
+    score.1 += king_safety.1;
 
-    for file in cozy_chess::File::ALL {
-        let file = file.bitboard();
+    let shelter = pawn_shelter(board, trace);
 
-        let white_pawns = board.colored_pieces(Color::White, Piece::Pawn) & file;
-        let black_pawns = board.colored_pieces(Color::Black, Piece::Pawn) & file;
+    score.0 += shelter.0;
+    score.1 += shelter.1;
 
-        if white_pawns.len() > 1 {
-            mg += MG_DOUBLED_PAWNS_PENALTY;
-            eg += EG_DOUBLED_PAWNS_PENALTY;
-        }
+    if position.count(Color::White, Piece::Bishop) >= 2 {
+        add(&mut score, trace, Term::BishopPair, 1);
+    }
 
-        if black_pawns.len() > 1 {
-            mg -= MG_DOUBLED_PAWNS_PENALTY;
-            eg -= EG_DOUBLED_PAWNS_PENALTY;
-        }
+    if position.count(Color::Black, Piece::Bishop) >= 2 {
+        add(&mut score, trace, Term::BishopPair, -1);
     }
 
     let tempo = 1 - 2 * (board.side_to_move() as Eval);
 
-    mg += MG_TEMPO * tempo;
-    eg += EG_TEMPO * tempo;
+    add(&mut score, trace, Term::Tempo, tempo);
 
-    let mg_game_phase = core::cmp::min(24, game_phase);
+    let (mg, eg) = score;
+
+    let mg_game_phase = core::cmp::min(24, position.phase());
     let endgame_game_phase = 24 - mg_game_phase;
 
     let eval = mg
@@ -90,30 +70,485 @@ pub fn evaluate(board: &Board) -> Eval {
     }
 }
 
+/// Scored as a `(middlegame, endgame)` pair.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Term {
+    Material(Piece),
+    PieceSquare(Piece, usize),
+    PassedPawn(usize),
+    /// The king's own pawn one or two ranks in front of it, on its file or a
+    /// neighbouring one, or missing from there.
+    PawnShield(usize),
+    /// An enemy pawn one to four ranks in front of the king.
+    PawnStorm(usize),
+    DoubledPawns,
+    IsolatedPawn,
+    BackwardPawn,
+    BishopPair,
+    Tempo,
+    KingSafety(usize),
+}
+
+impl Term {
+    pub const fn value(self) -> (Eval, Eval) {
+        match self {
+            Self::Material(piece) => (
+                MG_PIECE_VALUES[piece as usize],
+                EG_PIECE_VALUES[piece as usize],
+            ),
+            Self::PieceSquare(piece, square_idx) => (
+                MG_TABLES[piece as usize][square_idx],
+                EG_TABLES[piece as usize][square_idx],
+            ),
+            Self::PassedPawn(rank) => (MG_PASSED_PAWN_BONUS[rank], EG_PASSED_PAWN_BONUS[rank]),
+            Self::PawnShield(distance) => (MG_PAWN_SHIELD[distance], EG_PAWN_SHIELD[distance]),
+            Self::PawnStorm(distance) => (MG_PAWN_STORM[distance], EG_PAWN_STORM[distance]),
+            Self::DoubledPawns => (MG_DOUBLED_PAWNS_PENALTY, EG_DOUBLED_PAWNS_PENALTY),
+            Self::IsolatedPawn => (MG_ISOLATED_PAWN_PENALTY, EG_ISOLATED_PAWN_PENALTY),
+            Self::BackwardPawn => (MG_BACKWARD_PAWN_PENALTY, EG_BACKWARD_PAWN_PENALTY),
+            Self::BishopPair => (MG_BISHOP_PAIR_BONUS, EG_BISHOP_PAIR_BONUS),
+            Self::Tempo => (MG_TEMPO, EG_TEMPO),
+            Self::KingSafety(units) => (MG_KING_SAFETY_TABLE[units], EG_KING_SAFETY_TABLE[units]),
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Material(_) => "material",
+            Self::PieceSquare(..) => "piece-square",
+            Self::PassedPawn(_) => "passed pawns",
+            Self::PawnShield(_) => "pawn shield",
+            Self::PawnStorm(_) => "pawn storm",
+            Self::DoubledPawns => "doubled pawns",
+            Self::IsolatedPawn => "isolated pawns",
+            Self::BackwardPawn => "backward pawns",
+            Self::BishopPair => "bishop pair",
+            Self::Tempo => "tempo",
+            Self::KingSafety(_) => "king safety",
+        }
+    }
+}
+
+pub trait Trace {
+    /// Lets the search's evaluation skip finding the terms.
+    const ENABLED: bool = true;
+
+    /// From white's point of view.
+    fn add(&mut self, term: Term, count: Eval);
+}
+
+#[derive(Debug)]
+pub struct NoTrace;
+
+impl Trace for NoTrace {
+    const ENABLED: bool = false;
+
+    #[inline]
+    fn add(&mut self, _term: Term, _count: Eval) {}
+}
+
+#[derive(Debug)]
+pub struct Breakdown {
+    /// White's and black's `(middlegame, endgame)` scores, each from its own
+    /// point of view.
+    terms: Vec<(&'static str, [(Eval, Eval); 2])>,
+    phase: i16,
+    eval: Eval,
+    side_to_move: Color,
+}
+
+impl Breakdown {
+    #[must_use]
+    pub fn new(position: &Position) -> Self {
+        let mut breakdown = Self {
+            terms: Vec::new(),
+            phase: position.phase(),
+            eval: 0,
+            side_to_move: position.board().side_to_move(),
+        };
+
+        breakdown.eval = evaluate_traced(position, &mut breakdown);
+
+        breakdown
+    }
+}
+
+impl Trace for Breakdown {
+    fn add(&mut self, term: Term, count: Eval) {
+        let (mg, eg) = term.value();
+        let side = usize::from(count < 0);
+
+        let idx = self
+            .terms
+            .iter()
+            .position(|&(name, _)| name == term.name())
+            .unwrap_or_else(|| {
+                self.terms.push((term.name(), [(0, 0); 2]));
+                self.terms.len() - 1
+            });
+
+        let score = &mut self.terms[idx].1[side];
+
+        score.0 += mg * count.abs();
+        score.1 += eg * count.abs();
+    }
+}
+
+impl Display for Breakdown {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let row = |f: &mut Formatter<'_>, name: &str, [white, black]: [(Eval, Eval); 2]| {
+            writeln!(
+                f,
+                "{name:<16} {:>6} {:>6}   {:>6} {:>6}   {:>6} {:>6}",
+                white.0,
+                white.1,
+                black.0,
+                black.1,
+                white.0 - black.0,
+                white.1 - black.1
+            )
+        };
+
+        writeln!(
+            f,
+            "{:<16} {:>13}   {:>13}   {:>13}",
+            "term", "white", "black", "total"
+        )?;
+        writeln!(
+            f,
+            "{:<16} {:>6} {:>6}   {:>6} {:>6}   {:>6} {:>6}",
+            "", "mg", "eg", "mg", "eg", "mg", "eg"
+        )?;
+
+        let mut total = [(0, 0); 2];
+
+        for &(name, scores) in &self.terms {
+            row(f, name, scores)?;
+
+            for (total, score) in total.iter_mut().zip(scores) {
+                total.0 += score.0;
+                total.1 += score.1;
+            }
+        }
+
+        row(f, "total", total)?;
+
+        writeln!(f)?;
+        writeln!(f, "phase:        {} of 24", self.phase.min(24))?;
+        write!(
+            f,
+            "evaluation:   {} for {}",
+            self.eval,
+            match self.side_to_move {
+                Color::White => "white",
+                Color::Black => "black",
+            }
+        )
+    }
+}
+
 #[inline]
-fn pawns_in_front_adjacent_files(square: cozy_chess::Square, piece_colour: Color) -> BitBoard {
-    let file = square.file();
-    let pawn_files = file.bitboard() | file.adjacent();
+fn add<T: Trace>(score: &mut (Eval, Eval), trace: &mut T, term: Term, count: Eval) {
+    let (mg, eg) = term.value();
+
+    score.0 += mg * count;
+    score.1 += eg * count;
+
+    trace.add(term, count);
+}
+
+/// The evaluation itself takes these from the [`Position`]'s running score.
+fn trace_piece_squares<T: Trace>(board: &Board, trace: &mut T) {
+    for colour in Color::ALL {
+        let colour_sign = match colour {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+
+        for piece in Piece::ALL {
+            for square in board.colored_pieces(colour, piece) {
+                trace.add(Term::Material(piece), colour_sign);
+                trace.add(
+                    Term::PieceSquare(piece, piece_square_index(colour, square)),
+                    colour_sign,
+                );
+            }
+        }
+    }
+}
+
+/// Only pawns are looked at, so the result can be cached by pawn hash.
+fn pawn_structure<T: Trace>(board: &Board, trace: &mut T) -> (Eval, Eval) {
+    let mut score = (0, 0);
+
+    for colour in Color::ALL {
+        let colour_sign = match colour {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+
+        let ours = board.colored_pieces(colour, Piece::Pawn);
+        let theirs = board.colored_pieces(!colour, Piece::Pawn);
+
+        for square in ours {
+            let neighbours = BitBoard(ADJACENT_FILES[square.file() as usize]);
+
+            if (neighbours & ours).is_empty() {
+                add(&mut score, trace, Term::IsolatedPawn, colour_sign);
+            } else if is_backward(square, colour, ours, theirs) {
+                add(&mut score, trace, Term::BackwardPawn, colour_sign);
+            }
+
+            if (pawns_in_front_adjacent_files(square, colour) & theirs).is_empty() {
+                let rank = match colour {
+                    Color::White => square.rank(),
+                    Color::Black => square.rank().flip(),
+                };
+
+                add(
+                    &mut score,
+                    trace,
+                    Term::PassedPawn(rank as usize),
+                    colour_sign,
+                );
+            }
+        }
+
+        // pawns with another of our pawns somewhere below them on the same file
+        let stacked = ours.0 & north_fill(ours.0 << 8);
+        for _doubled_file in BitBoard(south_fill(stacked) & RANK_1) {
+            add(&mut score, trace, Term::DoubledPawns, colour_sign);
+        }
+    }
+
+    score
+}
+
+/// Once at least two pieces attack the king ring, their attack units are
+/// looked up in a table that grows faster than they do.
+fn king_safety<T: Trace>(board: &Board, trace: &mut T) -> (Eval, Eval) {
+    let mut score = (0, 0);
+    let occupied = board.occupied();
+
+    for colour in Color::ALL {
+        let colour_sign = match colour {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+
+        let king = board.king(!colour);
+        let ring = get_king_moves(king) | king.bitboard();
+
+        let mut attackers = 0;
+        let mut units = 0;
+
+        for piece in [Piece::Knight, Piece::Bishop, Piece::Rook, Piece::Queen] {
+            for square in board.colored_pieces(colour, piece) {
+                let attacks = match piece {
+                    Piece::Knight => get_knight_moves(square),
+                    Piece::Bishop => get_bishop_moves(square, occupied),
+                    Piece::Rook => get_rook_moves(square, occupied),
+                    _ => get_bishop_moves(square, occupied) | get_rook_moves(square, occupied),
+                } & ring;
+
+                if !attacks.is_empty() {
+                    attackers += 1;
+                    units += KING_ATTACK_WEIGHTS[piece as usize] * attacks.len() as usize;
+                }
+            }
+        }
+
+        if attackers >= 2 {
+            add(
+                &mut score,
+                trace,
+                Term::KingSafety(units.min(KING_SAFETY_TABLE_SIZE - 1)),
+                colour_sign,
+            );
+        }
+    }
+
+    score
+}
+
+fn is_backward(square: Square, colour: Color, ours: BitBoard, theirs: BitBoard) -> bool {
+    let Some(stop) = square.try_offset(0, if colour == Color::White { 1 } else { -1 }) else {
+        return false;
+    };
+
+    // everything behind the stop square on the three files, less our own file
+    let supporters = pawns_in_front_adjacent_files(stop, !colour)
+        & BitBoard(ADJACENT_FILES[square.file() as usize]);
+
+    (supporters & ours).is_empty() && !(get_pawn_attacks(stop, colour) & theirs).is_empty()
+}
+
+fn pawn_shelter<T: Trace>(board: &Board, trace: &mut T) -> (Eval, Eval) {
+    let mut score = (0, 0);
+
+    for colour in Color::ALL {
+        let colour_sign = match colour {
+            Color::White => 1,
+            Color::Black => -1,
+        };
+
+        let king = board.king(colour);
+        let ours = board.colored_pieces(colour, Piece::Pawn).0;
+        let theirs = board.colored_pieces(!colour, Piece::Pawn).0;
+
+        let king_file = king.file() as usize;
+        let ahead = ranks_ahead(king, colour);
+
+        for file in king_file.saturating_sub(1)..=(king_file + 1).min(7) {
+            let file = (FILE_A << file) & ahead;
+
+            let distance =
+                |pawns| nearest(pawns & file, colour).map(|pawn| rank_distance(king, pawn));
+
+            let shield = match distance(ours) {
+                Some(distance) if distance <= 2 => distance - 1,
+                _ => PAWN_SHIELD_SIZE - 1,
+            };
+
+            add(&mut score, trace, Term::PawnShield(shield), colour_sign);
+
+            if let Some(distance) = distance(theirs).filter(|&distance| distance <= PAWN_STORM_SIZE)
+            {
+                add(
+                    &mut score,
+                    trace,
+                    Term::PawnStorm(distance - 1),
+                    colour_sign,
+                );
+            }
+        }
+    }
+
+    score
+}
+
+const fn ranks_ahead(square: Square, colour: Color) -> u64 {
+    let rank = square.rank() as u32;
+
+    match colour {
+        Color::White => match u64::MAX.checked_shl((rank + 1) * 8) {
+            Some(ahead) => ahead,
+            None => 0,
+        },
+        Color::Black => (1 << (rank * 8)) - 1,
+    }
+}
+
+fn nearest(pawns: u64, colour: Color) -> Option<Square> {
+    if pawns == 0 {
+        return None;
+    }
 
-    let rank = square.rank();
+    let index = match colour {
+        Color::White => pawns.trailing_zeros(),
+        Color::Black => pawns.ilog2(),
+    };
+
+    Some(Square::index(index as usize))
+}
 
-    cozy_chess::BitBoard(match piece_colour {
-        Color::White => pawn_files.0 << ((rank as usize + 1) * 8),
-        Color::Black => pawn_files.0 >> ((8 - rank as usize) * 8),
-    })
+const fn rank_distance(a: Square, b: Square) -> usize {
+    (a.rank() as usize).abs_diff(b.rank() as usize)
 }
 
 #[inline]
-const fn piece_square(
+const fn pawns_in_front_adjacent_files(
+    square: cozy_chess::Square,
+    piece_colour: Color,
+) -> BitBoard {
+    BitBoard(PASSED_PAWN_SPANS[piece_colour as usize][square as usize])
+}
+
+const fn north_fill(mut bb: u64) -> u64 {
+    bb |= bb << 8;
+    bb |= bb << 16;
+    bb |= bb << 32;
+    bb
+}
+
+const fn south_fill(mut bb: u64) -> u64 {
+    bb |= bb >> 8;
+    bb |= bb >> 16;
+    bb |= bb >> 32;
+    bb
+}
+
+const FILE_A: u64 = 0x0101_0101_0101_0101;
+const RANK_1: u64 = 0xff;
+
+const ADJACENT_FILES: [u64; 8] = gen_adjacent_files();
+
+const fn gen_adjacent_files() -> [u64; 8] {
+    let mut files = [0; 8];
+
+    let mut file = 0;
+
+    while file < 8 {
+        if file > 0 {
+            files[file] |= FILE_A << (file - 1);
+        }
+
+        if file < 7 {
+            files[file] |= FILE_A << (file + 1);
+        }
+
+        file += 1;
+    }
+
+    files
+}
+
+/// A pawn is passed if no enemy pawn stands on its span.
+const PASSED_PAWN_SPANS: [[u64; 64]; 2] = gen_passed_pawn_spans();
+
+const fn gen_passed_pawn_spans() -> [[u64; 64]; 2] {
+    let mut spans = [[0; 64]; 2];
+
+    let mut square: u32 = 0;
+
+    while square < 64 {
+        let file = square % 8;
+        let rank = square / 8;
+
+        let files = FILE_A << file | ADJACENT_FILES[file as usize];
+
+        spans[Color::White as usize][square as usize] = match files.checked_shl((rank + 1) * 8) {
+            Some(span) => span,
+            None => 0,
+        };
+
+        spans[Color::Black as usize][square as usize] = match files.checked_shr((8 - rank) * 8) {
+            Some(span) => span,
+            None => 0,
+        };
+
+        square += 1;
+    }
+
+    spans
+}
+
+pub const fn phase_weight(piece: Piece) -> i16 {
+    match piece {
+        Piece::Pawn | Piece::King => 0,
+        Piece::Knight | Piece::Bishop => 1,
+        Piece::Rook => 2,
+        Piece::Queen => 4,
+    }
+}
+
+#[inline]
+pub const fn piece_square(
     piece: Piece,
     piece_colour: Color,
     square: cozy_chess::Square,
 ) -> (Eval, Eval) {
-    let square_idx = match piece_colour {
-        Color::White => square.flip_rank() as usize,
-        Color::Black => square as usize,
-    };
-
+    let square_idx = piece_square_index(piece_colour, square);
     let piece_idx = piece as usize;
 
     (
@@ -122,6 +557,15 @@ const fn piece_square(
     )
 }
 
+/// The tables are written the way white sees the board.
+#[inline]
+const fn piece_square_index(colour: Color, square: cozy_chess::Square) -> usize {
+    match colour {
+        Color::White => square.flip_rank() as usize,
+        Color::Black => square as usize,
+    }
+}
+
 const fn gen_piece_square_tables(
     tables: &[[Eval; 64]; 6],
     piece_values: [Eval; 6],
@@ -145,29 +589,29 @@ const fn gen_piece_square_tables(
     result
 }
 
-const MG_PIECE_SQUARE_TABLES: [[Eval; 64]; 6] = gen_piece_square_tables(
-    &[
-        MG_PAWN_TABLE,
-        MG_KNIGHT_TABLE,
-        MG_BISHOP_TABLE,
-        MG_ROOK_TABLE,
-        MG_QUEEN_TABLE,
-        MG_KING_TABLE,
-    ],
-    MG_PIECE_VALUES,
-);
-
-const EG_PIECE_SQUARE_TABLES: [[Eval; 64]; 6] = gen_piece_square_tables(
-    &[
-        EG_PAWN_TABLE,
-        EG_KNIGHT_TABLE,
-        EG_BISHOP_TABLE,
-        EG_ROOK_TABLE,
-        EG_QUEEN_TABLE,
-        EG_KING_TABLE,
-    ],
-    EG_PIECE_VALUES,
-);
+const MG_TABLES: [[Eval; 64]; 6] = [
+    MG_PAWN_TABLE,
+    MG_KNIGHT_TABLE,
+    MG_BISHOP_TABLE,
+    MG_ROOK_TABLE,
+    MG_QUEEN_TABLE,
+    MG_KING_TABLE,
+];
+
+const EG_TABLES: [[Eval; 64]; 6] = [
+    EG_PAWN_TABLE,
+    EG_KNIGHT_TABLE,
+    EG_BISHOP_TABLE,
+    EG_ROOK_TABLE,
+    EG_QUEEN_TABLE,
+    EG_KING_TABLE,
+];
+
+const MG_PIECE_SQUARE_TABLES: [[Eval; 64]; 6] =
+    gen_piece_square_tables(&MG_TABLES, MG_PIECE_VALUES);
+
+const EG_PIECE_SQUARE_TABLES: [[Eval; 64]; 6] =
+    gen_piece_square_tables(&EG_TABLES, EG_PIECE_VALUES);
 
 const MG_PIECE_VALUES: [Eval; 6] = [82, 337, 365, 477, 1025, 0];
 const EG_PIECE_VALUES: [Eval; 6] = [94, 281, 297, 512, 936, 0];
@@ -181,9 +625,52 @@ const EG_BISHOP_PAIR_BONUS: Eval = 20;
 const MG_DOUBLED_PAWNS_PENALTY: Eval = -10;
 const EG_DOUBLED_PAWNS_PENALTY: Eval = -10;
 
+const MG_ISOLATED_PAWN_PENALTY: Eval = -8;
+const EG_ISOLATED_PAWN_PENALTY: Eval = -12;
+
+const MG_BACKWARD_PAWN_PENALTY: Eval = -8;
+const EG_BACKWARD_PAWN_PENALTY: Eval = -6;
+
 const MG_TEMPO: Eval = 20;
 const EG_TEMPO: Eval = 5;
 
+pub const PAWN_SHIELD_SIZE: usize = 3;
+pub const PAWN_STORM_SIZE: usize = 4;
+
+const MG_PAWN_SHIELD: [Eval; PAWN_SHIELD_SIZE] = [0, -10, -25];
+const EG_PAWN_SHIELD: [Eval; PAWN_SHIELD_SIZE] = [0, 0, 0];
+
+const MG_PAWN_STORM: [Eval; PAWN_STORM_SIZE] = [-5, -25, -15, -5];
+const EG_PAWN_STORM: [Eval; PAWN_STORM_SIZE] = [0, 0, 0, 0];
+
+const KING_ATTACK_WEIGHTS: [usize; 6] = [0, 2, 2, 3, 5, 0];
+
+pub const KING_SAFETY_TABLE_SIZE: usize = 64;
+
+#[rustfmt::skip]
+const MG_KING_SAFETY_TABLE: [Eval; KING_SAFETY_TABLE_SIZE] = [
+   0,   0,   1,   2,   3,   5,   7,   9,
+  12,  15,  18,  22,  26,  30,  35,  39,
+  44,  50,  56,  62,  68,  75,  82,  85,
+  89,  97, 105, 113, 122, 131, 140, 150,
+ 169, 180, 191, 202, 213, 225, 237, 248,
+ 260, 272, 283, 295, 307, 319, 330, 342,
+ 354, 366, 377, 389, 401, 412, 424, 436,
+ 448, 459, 471, 483, 494, 500, 500, 500,
+];
+
+#[rustfmt::skip]
+const EG_KING_SAFETY_TABLE: [Eval; KING_SAFETY_TABLE_SIZE] = [
+   0,   0,   0,   0,   0,   1,   1,   2,
+   3,   3,   4,   5,   6,   7,   8,   9,
+  11,  12,  14,  15,  17,  18,  20,  21,
+  22,  24,  26,  28,  30,  32,  35,  37,
+  42,  45,  47,  50,  53,  56,  59,  62,
+  65,  68,  70,  73,  76,  79,  82,  85,
+  88,  91,  94,  97, 100, 103, 106, 109,
+ 112, 114, 117, 120, 123, 125, 125, 125,
+];
+
 #[rustfmt::skip]
 const MG_PAWN_TABLE: [Eval; 64] = [
     0,   0,   0,   0,   0,   0,  0,   0,
@@ -332,10 +819,130 @@ pub type Eval = i16;
 
 pub const EVAL_INFINITY: Eval = 30_000;
 
+#[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+pub const MATE_BOUND: Eval = EVAL_INFINITY - MAX_PLY as Eval;
+
+/// Well below the mate scores, so the two are never confused.
+pub const TB_WIN: Eval = 20_000;
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_doubled_pawns() {
+        let board: Board = "4k3/pp6/8/8/P7/P7/PP6/4K3 w - - 0 1".parse().unwrap();
+
+        assert_eq!(
+            pawn_structure(&board, &mut NoTrace),
+            (MG_DOUBLED_PAWNS_PENALTY, EG_DOUBLED_PAWNS_PENALTY)
+        );
+    }
+
+    struct Terms(Vec<(Term, Eval)>);
+
+    impl Trace for Terms {
+        fn add(&mut self, term: Term, count: Eval) {
+            self.0.push((term, count));
+        }
+    }
+
+    fn pawn_terms(fen: &str) -> Vec<(Term, Eval)> {
+        let mut terms = Terms(Vec::new());
+        pawn_structure(&fen.parse().unwrap(), &mut terms);
+
+        terms
+            .0
+            .retain(|(term, _)| !matches!(term, Term::PassedPawn(_)));
+        terms.0
+    }
+
+    #[test]
+    fn test_breakdown_adds_up() {
+        let position = Position::new(
+            "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP1B1PPP/R2QKB1R b KQ - 0 8"
+                .parse()
+                .unwrap(),
+        );
+
+        let breakdown = Breakdown::new(&position);
+        let (mg, eg) = breakdown
+            .terms
+            .iter()
+            .fold((0, 0), |(mg, eg), (_, [white, black])| {
+                (mg + white.0 - black.0, eg + white.1 - black.1)
+            });
+
+        let phase = breakdown.phase.min(24);
+
+        assert_eq!(breakdown.eval, evaluate(&position));
+        assert_eq!(-breakdown.eval, (mg * phase + eg * (24 - phase)) / 24);
+    }
+
+    #[test]
+    fn test_pawn_shelter() {
+        let mut terms = Terms(Vec::new());
+        let board = "6k1/5p2/6p1/7P/8/8/5PP1/6K1 w - - 0 1".parse().unwrap();
+
+        pawn_shelter(&board, &mut terms);
+
+        assert_eq!(
+            terms.0,
+            [
+                // f2 and g2 are right in front of the king, h2 is gone
+                (Term::PawnShield(0), 1),
+                (Term::PawnShield(0), 1),
+                (Term::PawnShield(2), 1),
+                // f7 covers the black king, g6 a rank further, h7 has gone
+                (Term::PawnShield(0), -1),
+                (Term::PawnShield(1), -1),
+                (Term::PawnShield(2), -1),
+                // and white's h5 pawn is coming up
+                (Term::PawnStorm(2), -1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_isolated_pawns() {
+        assert_eq!(
+            pawn_terms("4k3/5p2/8/8/8/8/P1P5/4K3 w - - 0 1"),
+            [
+                (Term::IsolatedPawn, 1),
+                (Term::IsolatedPawn, 1),
+                (Term::IsolatedPawn, -1)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_backward_pawns() {
+        // d3 can't be guarded by the c4 pawn, and e5 stops it from moving up
+        assert_eq!(
+            pawn_terms("4k3/8/8/4p3/2P5/3P4/8/4K3 w - - 0 1"),
+            [(Term::BackwardPawn, 1), (Term::IsolatedPawn, -1)]
+        );
+
+        // once e5 is gone d3 is free to advance
+        assert_eq!(pawn_terms("4k3/8/8/8/2P5/3P4/8/4K3 w - - 0 1"), []);
+    }
+
+    #[test]
+    fn test_king_safety() {
+        let board: Board = "6k1/5ppp/8/8/8/8/5PPP/4QRK1 w - - 0 1".parse().unwrap();
+
+        assert_eq!(king_safety(&board, &mut NoTrace), (0, 0));
+
+        // the queen attacks f7 and h7, the rook g7 and g8
+        let board: Board = "6k1/5p1p/8/7Q/8/8/5P1P/6RK b - - 0 1".parse().unwrap();
+        let units = 5 * 2 + 3 * 2;
+
+        assert_eq!(
+            king_safety(&board, &mut NoTrace),
+            (MG_KING_SAFETY_TABLE[units], EG_KING_SAFETY_TABLE[units])
+        );
+    }
+
     #[test]
     fn test_pawns() {
         let sq = cozy_chess::Square::E3;
@@ -0,0 +1,398 @@
+use crate::evaluate::Eval;
+use cozy_chess::{util::parse_uci_move, Board, Color, GameStatus};
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+const MAGIC: &[u8; 4] = b"ECGD";
+const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameRecord {
+    pub timestamp: u64,
+    pub start_fen: String,
+    pub moves: Vec<RecordedMove>,
+    pub result: GameResult,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedMove {
+    pub mv: String,
+    pub search: Option<SearchInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchInfo {
+    pub eval: Eval,
+    pub depth: u8,
+    pub time_ms: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+    Unknown,
+}
+
+impl GameResult {
+    const fn to_byte(self) -> u8 {
+        match self {
+            Self::WhiteWins => 0,
+            Self::BlackWins => 1,
+            Self::Draw => 2,
+            Self::Unknown => 3,
+        }
+    }
+
+    const fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => Self::WhiteWins,
+            1 => Self::BlackWins,
+            2 => Self::Draw,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl core::fmt::Display for GameResult {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::WhiteWins => write!(f, "1-0"),
+            Self::BlackWins => write!(f, "0-1"),
+            Self::Draw => write!(f, "1/2-1/2"),
+            Self::Unknown => write!(f, "*"),
+        }
+    }
+}
+
+impl GameRecord {
+    fn final_board(&self) -> Option<Board> {
+        let mut board = self.start_fen.parse::<Board>().ok()?;
+
+        for recorded in &self.moves {
+            let mv = parse_uci_move(&board, &recorded.mv).ok()?;
+            board.try_play(mv).ok()?;
+        }
+
+        Some(board)
+    }
+
+    fn adjudicate(&mut self) {
+        self.result =
+            self.final_board()
+                .map_or(GameResult::Unknown, |board| match board.status() {
+                    GameStatus::Won => match board.side_to_move() {
+                        Color::White => GameResult::BlackWins,
+                        Color::Black => GameResult::WhiteWins,
+                    },
+                    GameStatus::Drawn => GameResult::Draw,
+                    GameStatus::Ongoing => {
+                        if board.halfmove_clock() >= 100 {
+                            GameResult::Draw
+                        } else {
+                            GameResult::Unknown
+                        }
+                    }
+                });
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+
+        buf.extend_from_slice(&self.timestamp.to_le_bytes());
+
+        write_str(&mut buf, &self.start_fen);
+
+        buf.push(self.result.to_byte());
+
+        let move_count = u16::try_from(self.moves.len()).unwrap_or(u16::MAX);
+        buf.extend_from_slice(&move_count.to_le_bytes());
+
+        for recorded in self.moves.iter().take(usize::from(move_count)) {
+            write_str(&mut buf, &recorded.mv);
+
+            match recorded.search {
+                Some(info) => {
+                    buf.push(1);
+                    buf.extend_from_slice(&info.eval.to_le_bytes());
+                    buf.push(info.depth);
+                    buf.extend_from_slice(&info.time_ms.to_le_bytes());
+                }
+                None => buf.push(0),
+            }
+        }
+
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut cursor = Cursor { bytes, pos: 0 };
+
+        let timestamp = u64::from_le_bytes(cursor.take_array()?);
+        let start_fen = cursor.take_str()?;
+        let result = GameResult::from_byte(cursor.take_u8()?);
+
+        let move_count = u16::from_le_bytes(cursor.take_array()?);
+
+        let mut moves = Vec::with_capacity(usize::from(move_count));
+
+        for _ in 0..move_count {
+            let mv = cursor.take_str()?;
+
+            let search = match cursor.take_u8()? {
+                0 => None,
+                _ => Some(SearchInfo {
+                    eval: Eval::from_le_bytes(cursor.take_array()?),
+                    depth: cursor.take_u8()?,
+                    time_ms: u32::from_le_bytes(cursor.take_array()?),
+                }),
+            };
+
+            moves.push(RecordedMove { mv, search });
+        }
+
+        Some(Self {
+            timestamp,
+            start_fen,
+            moves,
+            result,
+        })
+    }
+}
+
+fn write_str(buf: &mut Vec<u8>, s: &str) {
+    let len = u16::try_from(s.len()).unwrap_or(u16::MAX);
+
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(&s.as_bytes()[..usize::from(len)]);
+}
+
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Cursor<'_> {
+    fn take(&mut self, n: usize) -> Option<&[u8]> {
+        let slice = self.bytes.get(self.pos..self.pos + n)?;
+        self.pos += n;
+        Some(slice)
+    }
+
+    fn take_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    fn take_u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn take_str(&mut self) -> Option<String> {
+        let len = u16::from_le_bytes(self.take_array()?);
+
+        String::from_utf8(self.take(usize::from(len))?.to_vec()).ok()
+    }
+}
+
+pub fn append_game(path: &Path, game: &GameRecord) -> std::io::Result<()> {
+    let is_new = std::fs::metadata(path).map_or(true, |meta| meta.len() == 0);
+
+    let mut file = BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?);
+
+    if is_new {
+        file.write_all(MAGIC)?;
+        file.write_all(&[FORMAT_VERSION])?;
+    }
+
+    let payload = game.encode();
+    let len = u32::try_from(payload.len()).map_err(std::io::Error::other)?;
+
+    file.write_all(&len.to_le_bytes())?;
+    file.write_all(&payload)?;
+
+    file.flush()
+}
+
+/// A truncated last record, like from a crash mid-write, is ignored.
+pub fn read_games(path: &Path) -> std::io::Result<Vec<GameRecord>> {
+    let mut bytes = Vec::new();
+
+    BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+
+    if bytes.len() < MAGIC.len() + 1 || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "not an eccat game database",
+        ));
+    }
+
+    if bytes[MAGIC.len()] != FORMAT_VERSION {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("unsupported game database version {}", bytes[MAGIC.len()]),
+        ));
+    }
+
+    let mut cursor = Cursor {
+        bytes: &bytes,
+        pos: MAGIC.len() + 1,
+    };
+
+    let mut games = Vec::new();
+
+    while let Some(len) = cursor.take_array().map(u32::from_le_bytes) {
+        let Some(game) = cursor.take(len as usize).and_then(GameRecord::decode) else {
+            break;
+        };
+
+        games.push(game);
+    }
+
+    Ok(games)
+}
+
+#[derive(Debug, Default)]
+pub struct GameRecorder {
+    current: Option<GameRecord>,
+    search_start: Option<Instant>,
+    last_search: Option<(Eval, u8)>,
+}
+
+impl GameRecorder {
+    pub fn position(&mut self, start_fen: &str, moves: &[String]) -> Option<GameRecord> {
+        let continues = self.current.as_ref().is_some_and(|game| {
+            game.start_fen == start_fen
+                && game.moves.len() <= moves.len()
+                && game.moves.iter().zip(moves).all(|(a, b)| &a.mv == b)
+        });
+
+        let finished = if continues { None } else { self.finish() };
+
+        let game = self.current.get_or_insert_with(|| GameRecord {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs()),
+            start_fen: start_fen.to_string(),
+            moves: Vec::new(),
+            result: GameResult::Unknown,
+        });
+
+        for mv in &moves[game.moves.len()..] {
+            game.moves.push(RecordedMove {
+                mv: mv.clone(),
+                search: None,
+            });
+        }
+
+        finished
+    }
+
+    pub fn search_started(&mut self) {
+        self.search_start = Some(Instant::now());
+        self.last_search = None;
+    }
+
+    pub const fn search_summary(&mut self, eval: Eval, depth: u8) {
+        self.last_search = Some((eval, depth));
+    }
+
+    pub fn best_move(&mut self, mv: &str) {
+        let Some(game) = &mut self.current else {
+            return;
+        };
+
+        let time_ms = self.search_start.take().map_or(0, |start| {
+            u32::try_from(start.elapsed().as_millis()).unwrap_or(u32::MAX)
+        });
+
+        let search = self.last_search.take().map(|(eval, depth)| SearchInfo {
+            eval,
+            depth,
+            time_ms,
+        });
+
+        game.moves.push(RecordedMove {
+            mv: mv.to_string(),
+            search,
+        });
+    }
+
+    pub const fn current(&self) -> Option<&GameRecord> {
+        self.current.as_ref()
+    }
+
+    pub fn resume(&mut self, game: Option<GameRecord>) {
+        self.current = game;
+        self.search_start = None;
+        self.last_search = None;
+    }
+
+    /// Only if the engine made at least one move in it.
+    pub fn finish(&mut self) -> Option<GameRecord> {
+        let mut game = self.current.take()?;
+
+        if game.moves.iter().all(|recorded| recorded.search.is_none()) {
+            return None;
+        }
+
+        game.adjudicate();
+
+        Some(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        let game = GameRecord {
+            timestamp: 1_700_000_000,
+            start_fen: Board::default().to_string(),
+            moves: vec![
+                RecordedMove {
+                    mv: "e2e4".to_string(),
+                    search: Some(SearchInfo {
+                        eval: 35,
+                        depth: 12,
+                        time_ms: 1500,
+                    }),
+                },
+                RecordedMove {
+                    mv: "e7e5".to_string(),
+                    search: None,
+                },
+            ],
+            result: GameResult::Unknown,
+        };
+
+        assert_eq!(GameRecord::decode(&game.encode()), Some(game));
+    }
+
+    #[test]
+    fn test_recorder_follows_positions() {
+        let startpos = Board::default().to_string();
+        let mut recorder = GameRecorder::default();
+
+        assert!(recorder.position(&startpos, &[]).is_none());
+        recorder.search_started();
+        recorder.search_summary(20, 8);
+        recorder.best_move("f2f3");
+
+        let moves = ["f2f3", "e7e5", "g2g4"].map(String::from);
+        assert!(recorder.position(&startpos, &moves).is_none());
+        recorder.search_started();
+        recorder.best_move("d8h4");
+
+        let game = recorder.finish().unwrap();
+
+        assert_eq!(game.moves.len(), 4);
+        assert_eq!(game.result, GameResult::BlackWins);
+    }
+}
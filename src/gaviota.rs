@@ -0,0 +1,117 @@
+use crate::{
+    evaluate::{Eval, EVAL_INFINITY},
+    search::MAX_PLY,
+};
+use cozy_chess::Board;
+use std::{collections::HashSet, path::Path};
+
+pub const MAX_PIECES: usize = 5;
+
+/// The tables aren't decoded, so this only tells which endings they cover.
+#[derive(Debug, Default)]
+pub struct Tables {
+    signatures: HashSet<String>,
+}
+
+impl Tables {
+    pub fn scan(dir: &Path) -> std::io::Result<Self> {
+        let mut tables = Self::default();
+
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+
+            let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                continue;
+            };
+
+            // `kqk.gtb`, or compressed like `kqk.gtb.cp4`
+            let Some((signature, compression)) = name.split_once(".gtb") else {
+                continue;
+            };
+
+            let compressed = compression.strip_prefix(".cp").is_some_and(|scheme| {
+                !scheme.is_empty() && scheme.chars().all(|c| c.is_ascii_digit())
+            });
+
+            if is_signature(signature) && (compression.is_empty() || compressed) {
+                tables.signatures.insert(signature.to_string());
+            }
+        }
+
+        Ok(tables)
+    }
+
+    pub fn count(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn max_pieces(&self) -> usize {
+        self.signatures
+            .iter()
+            .map(String::len)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+/// Like `krpkr`: the white king and pieces, then the black ones.
+fn is_signature(name: &str) -> bool {
+    name.strip_prefix('k')
+        .and_then(|rest| rest.split_once('k'))
+        .is_some_and(|(white, black)| {
+            white
+                .chars()
+                .chain(black.chars())
+                .all(|c| "qrbnp".contains(c))
+        })
+}
+
+/// `dtm` is in plies, positive if the side to move mates. `None` outside
+/// Gaviota's reach, or too far off to score as a mate.
+pub fn mate_score(board: &Board, dtm: i32) -> Option<Eval> {
+    if board.occupied().len() as usize > MAX_PIECES || dtm == 0 {
+        return None;
+    }
+
+    let plies = usize::try_from(dtm.unsigned_abs()).ok()?;
+
+    if plies >= MAX_PLY {
+        return None;
+    }
+
+    let score = EVAL_INFINITY - Eval::try_from(plies).ok()?;
+
+    Some(if dtm > 0 { score } else { -score })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_signature() {
+        assert!(is_signature("kqk"));
+        assert!(is_signature("krpkr"));
+        assert!(is_signature("kk"));
+
+        assert!(!is_signature("KQvK"));
+        assert!(!is_signature("qkk"));
+        assert!(!is_signature("kxk"));
+        assert!(!is_signature("kq"));
+    }
+
+    #[test]
+    fn test_mate_score() {
+        let board: Board = "8/8/8/4k3/8/8/8/4KQ2 w - - 0 1".parse().unwrap();
+
+        assert_eq!(mate_score(&board, 19), Some(EVAL_INFINITY - 19));
+        assert_eq!(mate_score(&board, -18), Some(18 - EVAL_INFINITY));
+        assert_eq!(mate_score(&board, 0), None);
+        assert_eq!(mate_score(&board, 1000), None);
+
+        // six pieces is beyond Gaviota
+        let board: Board = "8/8/8/4k3/8/8/3PP3/4KQ1R w - - 0 1".parse().unwrap();
+
+        assert_eq!(mate_score(&board, 19), None);
+    }
+}
@@ -0,0 +1,73 @@
+use crossbeam_channel::Receiver;
+use std::io::{BufRead, BufReader, Read, Write};
+
+pub trait Input: Send {
+    /// `None` once there will be no more, which the engine takes as `quit`.
+    fn read_line(&mut self) -> Option<String>;
+}
+
+pub trait Output: Send {
+    fn write_line(&mut self, line: &str);
+}
+
+impl Input for std::io::Stdin {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+
+        // the gui closed the pipe, or it broke
+        match Self::read_line(self, &mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+}
+
+impl Output for std::io::Stdout {
+    fn write_line(&mut self, line: &str) {
+        // a gui that stopped reading has nothing left to tell
+        let _ = writeln!(self.lock(), "{line}");
+    }
+}
+
+impl Input for Receiver<String> {
+    fn read_line(&mut self) -> Option<String> {
+        self.recv().ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct ReaderInput<R>(BufReader<R>);
+
+impl<R: Read> ReaderInput<R> {
+    pub fn new(reader: R) -> Self {
+        Self(BufReader::new(reader))
+    }
+}
+
+impl<R: Read + Send> Input for ReaderInput<R> {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+
+        match self.0.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+}
+
+/// Flushed a line at a time.
+#[derive(Debug)]
+pub struct WriterOutput<W>(pub W);
+
+impl<W: Write + Send> Output for WriterOutput<W> {
+    fn write_line(&mut self, line: &str) {
+        // the other end hung up, and will send no more commands either
+        let _ = writeln!(self.0, "{line}").and_then(|()| self.0.flush());
+    }
+}
+
+impl<F: FnMut(&str) + Send> Output for F {
+    fn write_line(&mut self, line: &str) {
+        self(line);
+    }
+}
@@ -1,15 +1,65 @@
-use crate::tt::TranspositionTable;
-use cozy_chess::{util::parse_uci_move, Board, Color, File, Piece, Rank, Square};
-use search::{EngineToSearch, History, Search, SearchMode, SearchToEngine};
-use std::sync::{Arc, Mutex};
+use crate::tt::{TableHandle, TranspositionTable};
+use chrono::Duration;
+use core::{
+    fmt::{Display, Write as _},
+    str::FromStr,
+};
+use cozy_chess::{util::parse_uci_move, Board, Color, File, GameStatus, Move, Piece, Rank, Square};
+use crossbeam_channel::Receiver;
+use evaluate::Eval;
+use gamedb::{GameRecord, GameRecorder};
+use metrics::{ErrorKind, Metrics, MetricsServer};
+use opponent::Opponent;
+use position::Position;
+use search::{Bound, EngineToSearch, History, Search, SearchToEngine};
+use state::EngineState;
+use std::{
+    collections::VecDeque,
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+};
 use uci::{EngineToUci, Uci, UciToEngine};
-
+use wdl::Wdl;
+
+pub mod bench;
+mod builder;
+pub mod config;
+pub mod datagen;
+pub mod egtb_download;
+pub mod epd;
+mod error;
+mod eval_cache;
 mod evaluate;
+mod gamedb;
+#[cfg(feature = "gaviota")]
+mod gaviota;
+pub mod io;
+pub mod lichess_bot;
+mod lichess_tb;
+pub mod logging;
+#[cfg(feature = "online")]
+mod md5;
+mod metrics;
+mod opponent;
 mod oracle;
+pub mod params;
+mod perft;
+mod position;
+pub mod random_board;
 mod search;
 mod see;
+pub mod selfplay;
+pub mod server;
+mod state;
 mod tt;
+pub mod tune;
 mod uci;
+mod wdl;
+
+pub use builder::EngineBuilder;
+pub use error::EngineError;
+pub use search::SearchLimits;
+pub use uci::GameTime;
 
 const PKG_NAME: &str = env!("CARGO_PKG_NAME");
 const PKG_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -20,6 +70,14 @@ const GIT_DESCRIBE: &str = env!("VERGEN_GIT_DESCRIBE");
 const RUSTC_SEMVER: &str = env!("VERGEN_RUSTC_SEMVER");
 const SYSINFO_NAME: &str = env!("VERGEN_SYSINFO_NAME");
 
+/// When it's full the UCI thread waits for room, so no command is lost. The
+/// search only drops superseded `info` reports.
+const REPORT_CAPACITY: usize = 256;
+
+const MAX_QUEUED_SEARCHES: usize = 256;
+
+const JOIN_TIMEOUT: core::time::Duration = core::time::Duration::from_secs(1);
+
 const ERROR_VERGEN: &str = "VERGEN_IDEMPOTENT_OUTPUT";
 
 const GIT_DESCRIBE_STR: &str = if const_str::equal!(GIT_DESCRIBE, ERROR_VERGEN) {
@@ -34,9 +92,75 @@ const VERSION_STR: &str = const_str::format!("{PKG_NAME} v{PKG_VERSION}{GIT_DESC
 pub struct Engine {
     uci: Uci,
     search: Search,
-    quit: bool,
+    quit: QuitState,
     debug: bool,
     options: EngineOptions,
+    game_recorder: GameRecorder,
+    noise_seed: u64,
+    status: SearchStatus,
+    board: Board,
+    history: Vec<History>,
+    /// For `undo`.
+    undo_boards: Vec<Board>,
+    /// `position` may have replaced `board` since the search started.
+    search_board: Board,
+    search_history: Vec<History>,
+    queued_searches: VecDeque<QueuedSearch>,
+    /// Only apply once `ponderhit` arrives.
+    ponder_limits: Option<SearchLimits>,
+    /// Playing a game rather than analysing, so it's worth thinking ahead.
+    playing: bool,
+    held_bestmove: Option<String>,
+    metrics: Arc<Mutex<Metrics>>,
+    metrics_server: Option<MetricsServer>,
+    startup_options: Vec<(String, String)>,
+    shared_hash: Option<SharedHash>,
+    library_reports: Option<Receiver<EngineReport>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum QuitState {
+    Running,
+    Requested,
+    Done,
+}
+
+/// Every accepted `go` leaves [`SearchStatus::Idle`] and is answered by
+/// exactly one `bestmove`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SearchStatus {
+    Idle,
+    /// An infinite search's best move waits for `stop`.
+    Searching {
+        infinite: bool,
+    },
+    Pondering,
+    Stopping,
+    /// Thinking about the position after the expected reply. A `go` for it takes
+    /// the search over.
+    ThinkingAhead {
+        predicted: u64,
+    },
+    Abandoning,
+    /// UCI only allows its best move after `stop`, or `ponderhit` when pondering.
+    Finished {
+        pondering: bool,
+    },
+}
+
+impl SearchStatus {
+    const fn is_thinking_ahead(self) -> bool {
+        matches!(self, Self::ThinkingAhead { .. } | Self::Abandoning)
+    }
+}
+
+#[derive(Debug)]
+struct QueuedSearch {
+    limits: SearchLimits,
+    ponder: bool,
+    board: Board,
+    history: Vec<History>,
+    stopped: bool,
 }
 
 impl Engine {
@@ -45,254 +169,1169 @@ impl Engine {
         Self {
             uci: Uci::new(),
             search: Search::new(),
-            quit: false,
+            quit: QuitState::Running,
             debug: false,
             options: EngineOptions::default(),
+            game_recorder: GameRecorder::default(),
+            noise_seed: new_noise_seed(),
+            status: SearchStatus::Idle,
+            board: Board::default(),
+            history: Vec::new(),
+            undo_boards: Vec::new(),
+            search_board: Board::default(),
+            search_history: Vec::new(),
+            queued_searches: VecDeque::new(),
+            ponder_limits: None,
+            playing: false,
+            held_bestmove: None,
+            metrics: Arc::default(),
+            metrics_server: None,
+            startup_options: Vec::new(),
+            shared_hash: None,
+            library_reports: None,
         }
     }
 
-    #[allow(clippy::too_many_lines)]
-    pub fn main_loop(&mut self) -> Result<(), Box<dyn core::error::Error>> {
-        let (report_tx, report_rx) = crossbeam_channel::unbounded();
+    #[must_use]
+    pub fn builder() -> EngineBuilder {
+        EngineBuilder::default()
+    }
+
+    #[must_use]
+    pub fn with_io(
+        mut self,
+        input: impl io::Input + 'static,
+        output: impl io::Output + 'static,
+    ) -> Self {
+        self.uci = Uci::with_io(Box::new(input), Box::new(output));
+        self
+    }
+
+    /// None of the engines resizes, clears or replaces it.
+    #[must_use]
+    pub fn with_shared_hash(mut self, hash: SharedHash) -> Self {
+        self.shared_hash = Some(hash);
+        self
+    }
+
+    #[must_use]
+    pub fn with_options(mut self, options: Vec<(String, String)>) -> Self {
+        self.startup_options = options;
+        self
+    }
 
-        let board = Arc::new(Mutex::new(Board::default()));
-        let history = Arc::new(Mutex::new(Vec::new()));
+    fn transposition_table(&self) -> TableHandle {
+        self.shared_hash.as_ref().map_or_else(
+            || {
+                Arc::new(RwLock::new(Arc::new(TranspositionTable::new(
+                    usize::try_from(HashOption::default()).unwrap(),
+                ))))
+            },
+            |hash| Arc::clone(&hash.0),
+        )
+    }
 
-        let transposition_table = Arc::new(Mutex::new(TranspositionTable::new(
-            usize::try_from(HashOption::default()).unwrap(),
-        )));
+    /// Searches without reading commands. With no limits at all it never returns.
+    pub fn search(
+        &mut self,
+        board: &Board,
+        limits: SearchLimits,
+    ) -> Result<Option<SearchResult>, EngineError> {
+        let report_rx = if let Some(report_rx) = &self.library_reports {
+            report_rx.clone()
+        } else {
+            let (report_tx, report_rx) = crossbeam_channel::bounded(REPORT_CAPACITY);
+
+            self.search.init(
+                report_tx,
+                self.transposition_table(),
+                self.shared_hash.is_some(),
+            );
+            self.library_reports = Some(report_rx.clone());
+
+            let mut first_error = None;
+
+            for (name, value) in core::mem::take(&mut self.startup_options) {
+                if let Err(error) = self.try_set_option(&name, Some(value)) {
+                    first_error.get_or_insert(error);
+                }
+            }
+
+            if let Some(error) = first_error {
+                return Err(error);
+            }
+
+            report_rx
+        };
+
+        self.search.send(EngineToSearch::Start {
+            limits,
+            board: Box::new(board.clone()),
+            history: vec![History { hash: board.hash() }],
+        });
+
+        let mut last_summary = None;
+
+        loop {
+            match report_rx.recv()? {
+                EngineReport::Search(SearchToEngine::Summary {
+                    depth,
+                    cp,
+                    bound: Bound::Exact,
+                    nodes,
+                    pv,
+                    ..
+                }) => last_summary = Some((depth, cp, nodes, pv)),
+                EngineReport::Search(SearchToEngine::BestMove { mv, .. }) => {
+                    return SearchResult::new(board, &mv, last_summary);
+                }
+                EngineReport::Search(SearchToEngine::Panicked(cause)) => {
+                    self.search.respawn();
+                    self.send_search_settings()?;
+
+                    return Err(EngineError::SearchPanicked(cause));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    pub fn main_loop(&mut self) -> Result<(), EngineError> {
+        let (report_tx, report_rx) = crossbeam_channel::bounded(REPORT_CAPACITY);
+
+        let transposition_table = self.transposition_table();
 
         self.uci.init(report_tx.clone());
 
         self.search.init(
             report_tx,
-            Arc::clone(&board),
-            Arc::clone(&history),
             Arc::clone(&transposition_table),
+            self.shared_hash.is_some(),
         );
 
-        println!("{VERSION_STR} by {}", pkg_authors());
+        for (name, value) in core::mem::take(&mut self.startup_options) {
+            self.set_option(&name, Some(value))?;
+        }
+
+        self.uci
+            .print(format!("{VERSION_STR} by {}", pkg_authors()));
 
-        println!(
+        self.uci.print(format!(
             "({}{BUILD_DATE}) [Rust {RUSTC_SEMVER}] on {SYSINFO_NAME}",
             if GIT_BRANCH == ERROR_VERGEN {
                 String::new()
             } else {
                 format!("{GIT_BRANCH}, ")
             }
-        );
+        ));
 
-        while !self.quit {
-            match report_rx.recv()? {
+        while self.quit != QuitState::Done {
+            let report = report_rx.recv()?;
+
+            // everything logged while handling a command belongs to it
+            let _span = match &report {
+                EngineReport::Uci(command) => tracing::debug_span!("command", ?command),
+                _ => tracing::Span::none(),
+            }
+            .entered();
+
+            match report {
                 EngineReport::Uci(uci_report) => match uci_report {
                     UciToEngine::Uci => self.uci.send(EngineToUci::Identify)?,
-                    UciToEngine::Debug(debug) => self.debug = debug,
+                    UciToEngine::Debug(debug) => {
+                        self.debug = debug;
+                        logging::set_debug(debug);
+                    }
                     UciToEngine::IsReady => self.uci.send(EngineToUci::Ready)?,
                     UciToEngine::Register => {
-                        eprintln!("warning: register uci command not supported");
+                        self.uci.warning("register uci command not supported");
                     }
-                    UciToEngine::Position(new_board, new_history) => {
-                        *board.lock().unwrap() = new_board;
-                        *history.lock().unwrap() = new_history;
+                    UciToEngine::Position {
+                        board: new_board,
+                        history: new_history,
+                        start_fen,
+                        moves,
+                    } => {
+                        self.board = new_board;
+                        self.history = new_history;
+                        self.undo_boards.clear();
+
+                        let finished = self.game_recorder.position(&start_fen, &moves);
+                        self.store_game(finished);
+                    }
+                    UciToEngine::SetOption { name, value } => {
+                        self.set_option(&name, value)?;
                     }
-                    UciToEngine::SetOption { name, value } => match name.to_lowercase().as_str() {
-                        "hash" => match value {
-                            Some(value) => match value.parse() {
-                                Ok(value) => match self.options.hash.set(value) {
-                                    Ok(()) => {
-                                        self.search.send(EngineToSearch::SetHash(
-                                            usize::try_from(value)?,
-                                        ))?;
-                                    }
-                                    Err(error) => {
-                                        eprintln!("error: {error}");
-                                    }
-                                },
-                                Err(error) => {
-                                    eprintln!("error: invalid value for Hash option: {error}");
-                                }
-                            },
-                            None => {
-                                eprintln!("error: missing value for Hash option");
-                            }
-                        },
-                        "threads" => match value {
-                            Some(value) => match value.parse() {
-                                Ok(value) => match self.options.threads.set(value) {
-                                    Ok(()) => {}
-                                    Err(error) => {
-                                        eprintln!("error: {error}");
-                                    }
-                                },
-                                Err(error) => {
-                                    eprintln!("error: invalid value for Threads option: {error}");
-                                }
-                            },
-                            None => {
-                                eprintln!("error: missing value for Threads option");
-                            }
-                        },
-                        _ => {
-                            eprintln!("warning: unsupported option: {name} = {value:?}");
-                        }
-                    },
                     UciToEngine::UciNewGame => {
-                        *board.lock().unwrap() = Board::default();
-                        *history.lock().unwrap() = Vec::new();
+                        let finished = self.game_recorder.finish();
+                        self.store_game(finished);
 
-                        self.search.send(EngineToSearch::ClearHash)?;
-                    }
-                    UciToEngine::Stop => self.search.send(EngineToSearch::Stop)?,
-                    UciToEngine::PonderHit => {
-                        eprintln!("warning: ponderhit uci command not supported");
-                    }
-                    UciToEngine::Quit => self.quit()?,
-                    UciToEngine::GoInfinite => self
-                        .search
-                        .send(EngineToSearch::Start(SearchMode::Infinite))?,
-                    UciToEngine::GoMoveTime(movetime) => self
-                        .search
-                        .send(EngineToSearch::Start(SearchMode::MoveTime(movetime)))?,
-                    UciToEngine::GoGameTime(gametime) => self
-                        .search
-                        .send(EngineToSearch::Start(SearchMode::GameTime(gametime)))?,
-                    UciToEngine::GoDepth(depth) => self
-                        .search
-                        .send(EngineToSearch::Start(SearchMode::Depth(depth)))?,
-
-                    UciToEngine::Unknown(error) => {
-                        if let Some(error) = error {
-                            eprintln!("error: {error}");
+                        self.board = Board::default();
+                        self.history = Vec::new();
+                        self.undo_boards.clear();
+
+                        // the position being thought about belongs to the old game
+                        if matches!(self.status, SearchStatus::ThinkingAhead { .. }) {
+                            self.stop_search();
                         }
-                    }
 
-                    UciToEngine::Eval => {
-                        println!("side to move: {}", board.lock().unwrap().side_to_move());
-                        println!(
-                            "evaluation:   {}",
-                            evaluate::evaluate(&board.lock().unwrap())
-                        );
+                        self.search.send(EngineToSearch::ClearHash);
+
+                        self.noise_seed = new_noise_seed();
+                        self.send_eval_noise()?;
                     }
-                    UciToEngine::PrintBoard => {
-                        let board = board.lock().unwrap();
+                    UciToEngine::Stop => self.stop_search(),
+                    UciToEngine::PonderHit => self.ponder_hit(),
+                    UciToEngine::Quit => self.quit()?,
+                    UciToEngine::Go { limits, ponder } => self.go(limits, ponder),
 
-                        pretty_print_board(&board);
+                    UciToEngine::Eval => {
+                        let position = Position::new(self.board.clone());
 
-                        println!("{board}");
-                        println!("hash: {:x}", board.hash());
+                        self.uci.print(evaluate::Breakdown::new(&position));
                     }
+                    UciToEngine::PrintBoard => self.uci.print(format_board(&self.board)),
+                    UciToEngine::PrintFen => self.uci.print(&self.board),
                     UciToEngine::PrintOptions => {
-                        println!("Options:");
+                        let mut text = "Options:".to_string();
 
-                        println!(
-                            "  {name} = {value}",
-                            name = HashOption::name(),
-                            value = self.options.hash.get()
-                        );
+                        for (name, value) in self.options.values() {
+                            let _ = write!(text, "\n  {name} = {value}");
+                        }
 
-                        println!(
-                            "  {name} = {value}",
-                            name = ThreadsOption::name(),
-                            value = 1
-                        );
+                        self.uci.print(text);
                     }
                     UciToEngine::PlayMove(mv) => {
-                        let parsed_move = parse_uci_move(&board.lock().unwrap(), &mv);
-
-                        let mv = match parsed_move {
-                            Ok(mv) => mv,
-                            Err(err) => {
-                                eprintln!("error: {err}");
-                                continue;
-                            }
+                        let Ok(parsed) = parse_uci_move(&self.board, &mv) else {
+                            self.uci.error(EngineError::InvalidMove(mv));
+                            continue;
                         };
 
-                        let play_result = board.lock().unwrap().try_play(mv);
+                        let before = self.board.clone();
 
-                        match play_result {
-                            Ok(()) => {
-                                let board = board.lock().unwrap();
+                        if self.board.try_play(parsed).is_ok() {
+                            self.undo_boards.push(before);
+                            self.history.push(History {
+                                hash: self.board.hash(),
+                            });
+                        } else {
+                            self.uci.error(EngineError::IllegalMove(mv));
+                        }
+                    }
+                    UciToEngine::Flip => match self.board.null_move() {
+                        Some(flipped) => {
+                            let before = core::mem::replace(&mut self.board, flipped);
+
+                            self.undo_boards.push(before);
+                            self.history.push(History {
+                                hash: self.board.hash(),
+                            });
+                        }
+                        None => self.uci.error("cannot pass the move while in check"),
+                    },
+                    UciToEngine::Undo => match self.undo_boards.pop() {
+                        Some(board) => {
+                            self.board = board;
+                            self.history.pop();
+                        }
+                        None => self.uci.error("no move to undo"),
+                    },
+                    UciToEngine::RandomPosition { start, plies } => {
+                        let mut rng = random_board::Rng::new(new_noise_seed());
 
-                                history.lock().unwrap().push(History { hash: board.hash() });
-                            }
-                            Err(err) => {
-                                eprintln!("error: {err}");
-                            }
+                        self.board = random_board::random_board_from(
+                            &mut rng,
+                            start,
+                            plies.unwrap_or(random_board::DEFAULT_PLIES),
+                        );
+                        self.history = vec![History {
+                            hash: self.board.hash(),
+                        }];
+                        self.undo_boards.clear();
+
+                        // castling rights of other rooks only survive in shredder fen
+                        if start == random_board::Start::Standard {
+                            self.uci.print(&self.board);
+                        } else {
+                            self.uci.print(format!("{:#}", self.board));
+                        }
+                    }
+                    UciToEngine::See(mv) => match parse_uci_move(&self.board, &mv) {
+                        Ok(mv) if self.board.is_legal(mv) => {
+                            self.uci.print(format_see(&self.board, mv));
                         }
+                        Ok(_) => self.uci.error(EngineError::IllegalMove(mv)),
+                        Err(_) => self.uci.error(EngineError::InvalidMove(mv)),
+                    },
+                    UciToEngine::Moves => {
+                        let hash_move = transposition_table
+                            .read()
+                            .unwrap()
+                            .probe(self.board.hash())
+                            .and_then(|entry| *entry.info().best_move);
+
+                        self.uci.print(format_moves(&self.board, hash_move));
                     }
                     UciToEngine::Help => {
-                        println!("Custom commands:");
-                        println!("  eval    - evaluate the current position");
-                        println!("  board   - display the current board");
-                        println!("  options - display the current engine options");
-                        println!("  make    - make a move on the board (e.g. make e2e4)");
-                        println!("  sleep   - sleep the uci thread for a number of milliseconds (e.g. sleep 1000)");
-                        println!(
-                            "  probe   - probe the transposition table for the current position"
-                        );
+                        self.uci.print(concat!(
+                            "Custom commands:\n",
+                            "  eval    - evaluate the current position, term by term\n",
+                            "  d       - display the board, its fen, hash, checkers and static eval (also board)\n",
+                            "  fen     - print the fen of the current position\n",
+                            "  options - display the current engine options\n",
+                            "  make    - make a move on the board (e.g. make e2e4)\n",
+                            "  flip    - give the move to the other side, if not in check\n",
+                            "  undo    - take back the last make or flip\n",
+                            "  randompos - set up a position after random moves from the start, or a chess960 or dfrc one, and print its fen (e.g. randompos chess960 16)\n",
+                            "  moves   - list the legal moves in the order the search tries them, and why\n",
+                            "  see     - show what a move wins once every capture on its square is played out (e.g. see e4d5)\n",
+                            "  sleep   - sleep the uci thread for a number of milliseconds (e.g. sleep 1000)\n",
+                            "  probe   - probe the transposition table for the current position\n",
+                            "  games   - list recorded games, or show one (e.g. games 3)\n",
+                            "  bench   - search the bench positions and print nodes and nps (e.g. bench 10)\n",
+                            "  perft   - count the leaf nodes at a depth, per move with divide (e.g. perft divide 4)\n",
+                            "  metrics - display the monitoring metrics\n",
+                            "  savestate - save the position, options and hash table (e.g. savestate eccat.state)\n",
+                            "  loadstate - restore a state written by savestate (e.g. loadstate eccat.state)\n",
+                        ));
                     }
                     UciToEngine::Sleep(ms) => {
-                        println!("slept for {ms} ms");
+                        self.uci.print(format!("slept for {ms} ms"));
                     }
                     UciToEngine::Probe => {
-                        let key = board.lock().unwrap().hash();
+                        let key = self.board.hash();
 
-                        if let Some(entry) = transposition_table.lock().unwrap().probe(key) {
-                            let info = entry.info();
+                        let entry = transposition_table.read().unwrap().probe(key);
 
-                            println!("found entry for this position");
+                        if let Some(entry) = entry {
+                            let info = entry.info();
 
-                            println!("key: {}", info.key);
-                            println!("depth: {}", info.depth);
-                            println!("flag: {:?}", info.flag);
-                            println!("score: {}", info.score);
+                            let mut text = format!(
+                                "found entry for this position\n\
+                                 key: {}\n\
+                                 depth: {}\n\
+                                 flag: {:?}\n\
+                                 score: {}",
+                                info.key, info.depth, info.flag, info.score
+                            );
 
                             if let Some(best_move) = info.best_move {
-                                println!("best move: {best_move}");
+                                let _ = write!(text, "\nbest move: {best_move}");
                             }
+
+                            self.uci.print(text);
                         } else {
-                            println!("no entry found for this position with hash {key:x}");
+                            self.uci.print(format!(
+                                "no entry found for this position with hash {key:x}"
+                            ));
+                        }
+                    }
+                    UciToEngine::Games(index) => self.print_games(index),
+                    UciToEngine::Bench(depth) => {
+                        self.uci
+                            .print(bench::run(depth.unwrap_or(bench::DEFAULT_DEPTH)));
+                    }
+                    UciToEngine::Perft { depth, divide } => {
+                        self.uci.print(format_perft(&self.board, depth, divide));
+                    }
+                    UciToEngine::Metrics => self.uci.print(self.metrics.lock().unwrap().render()),
+                    UciToEngine::SaveState(path) => {
+                        if !matches!(self.status, SearchStatus::Idle) {
+                            self.uci.error("cannot save state while searching");
+                            continue;
+                        }
+
+                        let table = Arc::clone(&transposition_table.read().unwrap());
+
+                        let state = EngineState {
+                            board: self.board.clone(),
+                            history: self.history.clone(),
+                            options: self.options.values(),
+                            game: self.game_recorder.current().cloned(),
+                            heuristics: self.search.heuristics().unwrap_or_default(),
+                        };
+
+                        match state::save(Path::new(&path), &state, &table) {
+                            Ok(()) => self.uci.print(format!("saved engine state to {path}")),
+                            Err(error) => {
+                                self.uci
+                                    .error(format!("could not save state to {path}: {error}"));
+                            }
                         }
                     }
+                    UciToEngine::LoadState(path) => {
+                        if !matches!(self.status, SearchStatus::Idle) {
+                            self.uci.error("cannot load state while searching");
+                            continue;
+                        }
+
+                        let (state, table) = match state::load(Path::new(&path)) {
+                            Ok(loaded) => loaded,
+                            Err(error) => {
+                                self.uci
+                                    .error(format!("could not load state from {path}: {error}"));
+                                continue;
+                            }
+                        };
+
+                        for (name, value) in state.options {
+                            // the saved table already has the saved size, and
+                            // a shared one keeps its own
+                            if name.eq_ignore_ascii_case(HashOption::name()) {
+                                if self.shared_hash.is_some() {
+                                    continue;
+                                }
+
+                                if let Ok(value) = value.parse() {
+                                    let _ = self.options.hash.set(value);
+                                }
+                            } else {
+                                self.set_option(&name, Some(value))?;
+                            }
+                        }
+
+                        if self.shared_hash.is_some() {
+                            self.uci.send(EngineToUci::InfoString(
+                                "keeping the shared hash table, other sessions are using it"
+                                    .to_string(),
+                            ))?;
+                        } else {
+                            *transposition_table.write().unwrap() = Arc::new(table);
+                        }
+
+                        self.search
+                            .send(EngineToSearch::SetHeuristics(Box::new(state.heuristics)));
+
+                        self.board = state.board;
+                        self.history = state.history;
+                        self.undo_boards.clear();
+
+                        self.game_recorder.resume(state.game);
+
+                        self.uci.print(format!("loaded engine state from {path}"));
+                    }
                 },
                 EngineReport::Search(search_report) => match search_report {
-                    SearchToEngine::BestMove(bestmove) => {
-                        self.uci.send(EngineToUci::BestMove(bestmove))?;
+                    SearchToEngine::BestMove { mv, ponder } => {
+                        self.search_finished(&mv, ponder.as_deref())?;
                     }
                     search::SearchToEngine::Summary {
                         depth,
                         seldepth,
                         time,
                         cp,
+                        bound,
                         nodes,
                         nps,
                         hashfull,
                         pv,
-                    } => self.uci.send(EngineToUci::Summary {
-                        depth,
-                        seldepth,
-                        time,
-                        cp,
-                        nodes,
-                        nps,
-                        hashfull,
-                        pv,
-                    })?,
+                    } => {
+                        if self.status.is_thinking_ahead() {
+                            continue;
+                        }
+
+                        if bound == Bound::Exact {
+                            self.game_recorder.search_summary(cp, depth);
+                        }
+                        self.metrics
+                            .lock()
+                            .unwrap()
+                            .search_summary(depth, nodes, nps, hashfull);
+
+                        let wdl = self
+                            .options
+                            .show_wdl
+                            .get()
+                            .then(|| Wdl::new(cp, &self.search_board));
+
+                        self.uci.send(EngineToUci::Summary {
+                            depth,
+                            seldepth,
+                            time,
+                            cp,
+                            bound,
+                            wdl,
+                            nodes,
+                            nps,
+                            hashfull,
+                            pv,
+                        })?;
+                    }
+                    SearchToEngine::InfoString(info) => {
+                        if self.status.is_thinking_ahead() {
+                            continue;
+                        }
+
+                        self.uci.send(EngineToUci::InfoString(info))?;
+                    }
+                    SearchToEngine::CurrentMove { depth, mv, number } => {
+                        if self.status.is_thinking_ahead() {
+                            continue;
+                        }
+
+                        self.uci
+                            .send(EngineToUci::CurrentMove { depth, mv, number })?;
+                    }
+                    SearchToEngine::Panicked(cause) => {
+                        self.metrics.lock().unwrap().error(ErrorKind::SearchPanic);
+
+                        eprintln!("error: search thread panicked: {cause}");
+
+                        self.restart_search()?;
+                    }
                 },
                 EngineReport::Error(error) => {
-                    eprintln!("error: {error}");
+                    self.metrics.lock().unwrap().error(ErrorKind::Uci);
+
+                    self.uci.error(error);
+                }
+            }
+
+            self.metrics
+                .lock()
+                .unwrap()
+                .set_games_in_progress(u64::from(self.game_recorder.current().is_some()));
+        }
+
+        // nothing reads reports anymore, so a thread sending one gives up
+        // rather than waiting for room
+        drop(report_rx);
+
+        self.shutdown();
+
+        Ok(())
+    }
+
+    /// A thread still blocked after [`JOIN_TIMEOUT`], like the UCI thread reading
+    /// stdin, is left behind.
+    fn shutdown(&mut self) {
+        self.library_reports = None;
+
+        if !self.search.shutdown() {
+            eprintln!("warning: the search thread did not exit in time");
+        }
+
+        self.uci.shutdown();
+    }
+
+    fn go(&mut self, limits: SearchLimits, ponder: bool) {
+        let position = self.board.hash();
+
+        if let SearchStatus::ThinkingAhead { predicted } = self.status {
+            // the search thinking ahead searches every move, so it can't
+            // answer a go restricted to some of them
+            if !ponder && position == predicted && limits.search_moves.is_empty() {
+                self.game_recorder.search_started();
+
+                self.playing = limits.game_time.is_some();
+                self.status = SearchStatus::Searching {
+                    infinite: limits.is_infinite(),
+                };
+                self.search.send(EngineToSearch::ThinkHit(limits));
+
+                return;
+            }
+
+            self.stop_search();
+        }
+
+        if self.status == SearchStatus::Idle {
+            self.start_search(limits, ponder, self.board.clone(), self.history.clone());
+            return;
+        }
+
+        // a go that follows a stop only has to wait for the bestmove, but one
+        // for a search that is still running is a gui error and is rejected,
+        // just like repeated stops are never sent on to the search
+        let redundant = match self.queued_searches.back() {
+            Some(queued) => !queued.stopped,
+            None => !matches!(
+                self.status,
+                SearchStatus::Stopping | SearchStatus::Abandoning
+            ),
+        };
+
+        if redundant {
+            self.uci
+                .error("go received while a search is running, ignoring it");
+            return;
+        }
+
+        if self.queued_searches.len() >= MAX_QUEUED_SEARCHES {
+            self.uci
+                .error("too many go commands waiting for a search, ignoring this one");
+            return;
+        }
+
+        self.queued_searches.push_back(QueuedSearch {
+            limits,
+            ponder,
+            board: self.board.clone(),
+            history: self.history.clone(),
+            stopped: false,
+        });
+    }
+
+    fn start_search(
+        &mut self,
+        limits: SearchLimits,
+        ponder: bool,
+        board: Board,
+        history: Vec<History>,
+    ) {
+        self.game_recorder.search_started();
+
+        self.playing = limits.game_time.is_some() && !ponder;
+        self.status = if ponder {
+            SearchStatus::Pondering
+        } else {
+            SearchStatus::Searching {
+                infinite: limits.is_infinite(),
+            }
+        };
+
+        // only stop or ponderhit end a ponder search
+        let limits = if ponder {
+            let pondering = SearchLimits {
+                search_moves: limits.search_moves.clone(),
+                ..SearchLimits::default()
+            };
+
+            self.ponder_limits = Some(limits);
+
+            pondering
+        } else {
+            limits
+        };
+
+        self.search.send(EngineToSearch::Start {
+            limits,
+            board: Box::new(board.clone()),
+            history: history.clone(),
+        });
+
+        self.search_board = board;
+        self.search_history = history;
+    }
+
+    fn stop_search(&mut self) {
+        match self.status {
+            SearchStatus::Searching { .. } | SearchStatus::Pondering => {
+                self.search.send(EngineToSearch::Stop);
+
+                self.status = SearchStatus::Stopping;
+            }
+            SearchStatus::ThinkingAhead { .. } => {
+                self.search.send(EngineToSearch::Stop);
+
+                self.status = SearchStatus::Abandoning;
+            }
+            SearchStatus::Finished { .. } => {
+                if let Some(bestmove) = self.held_bestmove.take() {
+                    let _ = self.send_bestmove(&bestmove);
+                }
+
+                self.status = SearchStatus::Idle;
+                return;
+            }
+            SearchStatus::Stopping | SearchStatus::Abandoning => {}
+            SearchStatus::Idle => return,
+        }
+
+        for queued in &mut self.queued_searches {
+            queued.stopped = true;
+        }
+    }
+
+    /// A ponder search without limits of its own is stopped and its best move
+    /// played.
+    fn ponder_hit(&mut self) {
+        match self.status {
+            SearchStatus::Pondering => match self.ponder_limits.take() {
+                Some(limits) if !limits.is_infinite() => {
+                    self.playing = limits.game_time.is_some();
+                    self.status = SearchStatus::Searching { infinite: false };
+                    self.search.send(EngineToSearch::ThinkHit(limits));
+                }
+                _ => self.stop_search(),
+            },
+            SearchStatus::Finished { pondering: true } => self.stop_search(),
+            _ => self.uci.warning("ponderhit received while not pondering"),
+        }
+    }
+
+    fn search_finished(&mut self, bestmove: &str, ponder: Option<&str>) -> Result<(), EngineError> {
+        match self.status {
+            SearchStatus::Idle | SearchStatus::Finished { .. } => {
+                self.uci.warning(format!(
+                    "dropped bestmove {bestmove} with no search running"
+                ));
+                return Ok(());
+            }
+            // nobody asked for it
+            SearchStatus::ThinkingAhead { .. } | SearchStatus::Abandoning => {}
+            SearchStatus::Searching { infinite: true } | SearchStatus::Pondering => {
+                self.held_bestmove = Some(bestmove.to_string());
+                self.status = SearchStatus::Finished {
+                    pondering: self.status == SearchStatus::Pondering,
+                };
+
+                return Ok(());
+            }
+            SearchStatus::Searching { infinite: false } | SearchStatus::Stopping => {
+                self.send_bestmove(bestmove)?;
+            }
+        }
+
+        let thinking_ahead = self.status.is_thinking_ahead();
+        self.status = SearchStatus::Idle;
+
+        if let Some(queued) = self.queued_searches.pop_front() {
+            self.start_search(queued.limits, queued.ponder, queued.board, queued.history);
+
+            // more searches are waiting behind this one
+            if queued.stopped || !self.queued_searches.is_empty() {
+                self.stop_search();
+            }
+        } else if self.quit == QuitState::Requested {
+            self.finish_quit()?;
+        } else if !thinking_ahead && self.playing && self.options.permanent_brain.get() {
+            if let Some(ponder) = ponder {
+                self.think_ahead(bestmove, ponder);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send_bestmove(&mut self, bestmove: &str) -> Result<(), EngineError> {
+        self.game_recorder.best_move(bestmove);
+        self.metrics.lock().unwrap().search_finished();
+
+        self.uci.send(EngineToUci::BestMove(bestmove.to_string()))?;
+
+        Ok(())
+    }
+
+    fn think_ahead(&mut self, bestmove: &str, reply: &str) {
+        let mut predicted = self.search_board.clone();
+        let mut history = self.search_history.clone();
+
+        for mv in [bestmove, reply] {
+            let Ok(mv) = parse_uci_move(&predicted, mv) else {
+                return;
+            };
+
+            if predicted.try_play(mv).is_err() {
+                return;
+            }
+
+            history.push(History {
+                hash: predicted.hash(),
+            });
+        }
+
+        if predicted.status() != GameStatus::Ongoing {
+            return;
+        }
+
+        self.status = SearchStatus::ThinkingAhead {
+            predicted: predicted.hash(),
+        };
+
+        self.search.send(EngineToSearch::Think {
+            board: Box::new(predicted.clone()),
+            history: history.clone(),
+        });
+
+        // a go for the predicted position takes this search over
+        self.search_board = predicted;
+        self.search_history = history;
+    }
+
+    /// A search it was running is answered with the first legal move.
+    fn restart_search(&mut self) -> Result<(), EngineError> {
+        self.search.respawn();
+        self.send_search_settings()?;
+
+        if self.status == SearchStatus::Idle {
+            return Ok(());
+        }
+
+        let position = &self.search_board;
+        let fallback = search::first_legal_move(position).map_or_else(
+            || search::NO_MOVE.to_string(),
+            |mv| uci::convert_move_to_uci(position, mv).to_string(),
+        );
+
+        self.search_finished(&fallback, None)
+    }
+
+    fn send_search_settings(&self) -> Result<(), EngineError> {
+        self.search.send(EngineToSearch::SetHash(usize::try_from(
+            self.options.hash.get(),
+        )?));
+        self.search.send(EngineToSearch::SetOnlineTablebase(
+            self.options.online_tablebase.get(),
+        ));
+        self.search.send(EngineToSearch::SetDeterministic(
+            self.options.deterministic.get(),
+        ));
+        self.search.send(EngineToSearch::SetThreads(usize::try_from(
+            self.options.threads.get(),
+        )?));
+        self.search
+            .send(EngineToSearch::SetMoveOverhead(Duration::milliseconds(
+                self.options.move_overhead.get(),
+            )));
+
+        #[cfg(feature = "tune")]
+        for (param, value) in params::Param::ALL.into_iter().zip(self.options.params) {
+            self.search.send(EngineToSearch::SetParam(param, value));
+        }
+
+        self.send_opponent_settings()?;
+        self.send_eval_noise()
+    }
+
+    /// A value that can't be set is reported to the gui rather than failing.
+    fn set_option(&mut self, name: &str, value: Option<String>) -> Result<(), EngineError> {
+        match self.try_set_option(name, value) {
+            Err(error) if !error.is_fatal() => {
+                self.uci.error(error);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    #[allow(clippy::too_many_lines)]
+    fn try_set_option(&mut self, name: &str, value: Option<String>) -> Result<(), EngineError> {
+        let parsed = value.as_deref();
+
+        match name.to_lowercase().as_str() {
+            "hash" => {
+                set_parsed(&mut self.options.hash, parsed)?;
+
+                // the other engines sharing the table are still using it
+                if self.shared_hash.is_some() {
+                    self.uci.send(EngineToUci::InfoString(
+                        "the hash table is shared, so it keeps its size".to_string(),
+                    ))?;
+                } else {
+                    self.search.send(EngineToSearch::SetHash(usize::try_from(
+                        self.options.hash.get(),
+                    )?));
+                }
+            }
+            "threads" => {
+                set_parsed(&mut self.options.threads, parsed)?;
+
+                self.search.send(EngineToSearch::SetThreads(usize::try_from(
+                    self.options.threads.get(),
+                )?));
+            }
+            "timeusage" => {
+                set_parsed(&mut self.options.time_usage, parsed)?;
+
+                self.send_opponent_settings()?;
+            }
+            "moveoverhead" => {
+                set_parsed(&mut self.options.move_overhead, parsed)?;
+
+                self.search
+                    .send(EngineToSearch::SetMoveOverhead(Duration::milliseconds(
+                        self.options.move_overhead.get(),
+                    )));
+            }
+            "contempt" => {
+                set_parsed(&mut self.options.contempt, parsed)?;
+
+                self.send_opponent_settings()?;
+            }
+            "uci_opponent" => {
+                let value = value.unwrap_or_default().trim().to_string();
+
+                if !value.is_empty() && Opponent::parse(&value).is_none() {
+                    self.uci
+                        .warning(format!("could not parse UCI_Opponent value: {value}"));
+                }
+
+                let _ = self.options.opponent.set(value);
+
+                self.send_opponent_settings()?;
+            }
+            "opponentscaling" => {
+                set_parsed(&mut self.options.opponent_scaling, parsed)?;
+
+                self.send_opponent_settings()?;
+            }
+            "eval noise" => {
+                set_parsed(&mut self.options.eval_noise, parsed)?;
+
+                self.send_eval_noise()?;
+            }
+            "uci_analysemode" => {
+                set_parsed(&mut self.options.analyse_mode, parsed)?;
+
+                self.send_eval_noise()?;
+            }
+            "uci_showwdl" => {
+                set_parsed(&mut self.options.show_wdl, parsed)?;
+            }
+            "permanentbrain" => {
+                set_parsed(&mut self.options.permanent_brain, parsed)?;
+            }
+            "onlinetablebase" => {
+                set_parsed(&mut self.options.online_tablebase, parsed)?;
+
+                self.search.send(EngineToSearch::SetOnlineTablebase(
+                    self.options.online_tablebase.get(),
+                ));
+            }
+            "deterministic" => {
+                set_parsed(&mut self.options.deterministic, parsed)?;
+
+                self.search.send(EngineToSearch::SetDeterministic(
+                    self.options.deterministic.get(),
+                ));
+            }
+            #[cfg(feature = "gaviota")]
+            "gaviotatbpath" => {
+                set_string(&mut self.options.gaviota_tb_path, parsed);
+
+                self.report_gaviota_tables()?;
+            }
+            "gamedatabase" => {
+                set_string(&mut self.options.game_database, parsed);
+            }
+            "metricsaddress" => {
+                set_string(&mut self.options.metrics_address, parsed);
+
+                self.start_metrics_server();
+            }
+            _ => {
+                #[cfg(feature = "tune")]
+                if let Some(param) = params::Param::find(name) {
+                    return self.set_param(param, parsed);
                 }
+
+                self.uci
+                    .warning(format!("unsupported option: {name} = {value:?}"));
             }
         }
 
         Ok(())
     }
 
-    fn quit(&mut self) -> Result<(), Box<dyn core::error::Error>> {
+    #[cfg(feature = "tune")]
+    fn set_param(&mut self, param: params::Param, value: Option<&str>) -> Result<(), EngineError> {
+        let (min, max) = param.range();
+
+        let value = value
+            .ok_or_else(|| EngineError::MissingValue(param.name()))?
+            .parse::<i32>()
+            .map_err(|error| EngineError::InvalidValue {
+                option: param.name(),
+                reason: error.to_string(),
+            })?;
+
+        if !(min..=max).contains(&value) {
+            return Err(EngineError::OutOfRange {
+                option: param.name(),
+                value: value.into(),
+                min: min.into(),
+                max: max.into(),
+            });
+        }
+
+        self.options.params[param as usize] = value;
+        self.search.send(EngineToSearch::SetParam(param, value));
+
+        Ok(())
+    }
+
+    fn send_opponent_settings(&self) -> Result<(), EngineError> {
+        let opponent = self
+            .options
+            .opponent_scaling
+            .get()
+            .then(|| Opponent::parse(&self.options.opponent.get()))
+            .flatten();
+
+        let contempt = self.options.contempt.get()
+            + opponent
+                .as_ref()
+                .map_or(0, |opponent| i64::from(opponent.contempt()));
+
+        let time_usage = self.options.time_usage.get()
+            * opponent.as_ref().map_or(100, Opponent::time_scale)
+            / 100;
+
+        self.search
+            .send(EngineToSearch::SetContempt(Eval::try_from(contempt)?));
+        self.search
+            .send(EngineToSearch::SetTimeUsage(u16::try_from(time_usage)?));
+
+        Ok(())
+    }
+
+    /// Noise is always off when analysing.
+    fn send_eval_noise(&self) -> Result<(), EngineError> {
+        let amount = if self.options.analyse_mode.get() {
+            0
+        } else {
+            self.options.eval_noise.get()
+        };
+
+        self.search.send(EngineToSearch::SetEvalNoise {
+            amount: Eval::try_from(amount)?,
+            seed: self.noise_seed,
+        });
+
+        Ok(())
+    }
+
+    /// The tables aren't probed, only reported.
+    #[cfg(feature = "gaviota")]
+    fn report_gaviota_tables(&self) -> Result<(), EngineError> {
+        let path = self.options.gaviota_tb_path.get();
+
+        if path.is_empty() {
+            return Ok(());
+        }
+
+        let tables = match gaviota::Tables::scan(Path::new(&path)) {
+            Ok(tables) => tables,
+            Err(err) => {
+                self.uci
+                    .warning(format!("could not read GaviotaTbPath {path}: {err}"));
+                return Ok(());
+            }
+        };
+
+        let info = if tables.count() == 0 {
+            format!("no gaviota tables found in {path}")
+        } else {
+            format!(
+                "found {} gaviota tables of up to {} pieces in {path}, \
+                 but probing local tables is not supported yet",
+                tables.count(),
+                tables.max_pieces()
+            )
+        };
+
+        self.uci.send(EngineToUci::InfoString(info))?;
+
+        Ok(())
+    }
+
+    fn start_metrics_server(&mut self) {
+        // drop the old one first, the address might be the same
+        self.metrics_server = None;
+
+        let address = self.options.metrics_address.get();
+
+        if address.is_empty() {
+            return;
+        }
+
+        match MetricsServer::start(&address, Arc::clone(&self.metrics)) {
+            Ok(server) => self.metrics_server = Some(server),
+            Err(error) => self.uci.error(error),
+        }
+    }
+
+    fn store_game(&self, game: Option<GameRecord>) {
+        let path = self.options.game_database.get();
+
+        if let (Some(game), false) = (game, path.is_empty()) {
+            if let Err(error) = gamedb::append_game(Path::new(&path), &game) {
+                self.uci
+                    .error(format!("could not write game to {path}: {error}"));
+            }
+        }
+    }
+
+    fn print_games(&self, index: Option<usize>) {
+        let path = self.options.game_database.get();
+
+        if path.is_empty() {
+            self.uci
+                .error("no game database set (setoption name GameDatabase value <path>)");
+            return;
+        }
+
+        let games = match gamedb::read_games(Path::new(&path)) {
+            Ok(games) => games,
+            Err(error) => {
+                self.uci.error(format!("could not read {path}: {error}"));
+                return;
+            }
+        };
+
+        match index {
+            None => {
+                let mut text = format!("{} games in {path}", games.len());
+
+                for (i, game) in games.iter().enumerate() {
+                    let _ = write!(
+                        text,
+                        "\n  {i:>4}  {:<7}  {:>3} moves  {}",
+                        game.result.to_string(),
+                        game.moves.len(),
+                        game.start_fen
+                    );
+                }
+
+                self.uci.print(text);
+            }
+            Some(index) => {
+                let Some(game) = games.get(index) else {
+                    self.uci.error(format!("no game with index {index}"));
+                    return;
+                };
+
+                let mut text = format!("start:  {}\nresult: {}", game.start_fen, game.result);
+
+                for (ply, recorded) in game.moves.iter().enumerate() {
+                    let _ = match recorded.search {
+                        Some(info) => write!(
+                            text,
+                            "\n  {ply:>3}. {:<6} eval {:>6} depth {:>3} time {} ms",
+                            recorded.mv, info.eval, info.depth, info.time_ms
+                        ),
+                        None => write!(text, "\n  {ply:>3}. {}", recorded.mv),
+                    };
+                }
+
+                self.uci.print(text);
+            }
+        }
+    }
+
+    fn quit(&mut self) -> Result<(), EngineError> {
+        self.quit = QuitState::Requested;
+
+        // a held best move is sent straight away, leaving nothing to wait for
+        self.stop_search();
+
+        if self.status == SearchStatus::Idle {
+            self.finish_quit()
+        } else {
+            Ok(())
+        }
+    }
+
+    fn finish_quit(&mut self) -> Result<(), EngineError> {
+        let finished = self.game_recorder.finish();
+        self.store_game(finished);
+
         self.uci.send(EngineToUci::Quit)?;
-        self.search.send(EngineToSearch::Quit)?;
+        self.search.send(EngineToSearch::Quit);
 
-        self.quit = true;
+        self.quit = QuitState::Done;
 
         Ok(())
     }
@@ -304,17 +1343,183 @@ impl Default for Engine {
     }
 }
 
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SharedHash(TableHandle);
+
+impl SharedHash {
+    #[must_use]
+    pub fn new(mb_size: usize) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(TranspositionTable::new(
+            mb_size,
+        )))))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchResult {
+    pub best_move: Move,
+    /// From the side to move's point of view.
+    pub score: Eval,
+    pub pv: Vec<Move>,
+    pub nodes: u64,
+    /// 0 if no depth completed, like after a tablebase hit.
+    pub depth: u8,
+}
+
+impl SearchResult {
+    fn new(
+        board: &Board,
+        best_move: &str,
+        summary: Option<(u8, Eval, u64, Vec<String>)>,
+    ) -> Result<Option<Self>, EngineError> {
+        if best_move == search::NO_MOVE {
+            return Ok(None);
+        }
+
+        let best_move = parse_uci_move(board, best_move)
+            .map_err(|_| EngineError::InvalidMove(best_move.to_string()))?;
+
+        let Some((depth, score, nodes, pv)) = summary else {
+            return Ok(Some(Self {
+                best_move,
+                score: 0,
+                pv: vec![best_move],
+                nodes: 0,
+                depth: 0,
+            }));
+        };
+
+        let mut pv_board = board.clone();
+        let pv = pv
+            .iter()
+            .map_while(|mv| {
+                let mv = parse_uci_move(&pv_board, mv).ok()?;
+                pv_board.try_play(mv).ok()?;
+                Some(mv)
+            })
+            .collect();
+
+        Ok(Some(Self {
+            best_move,
+            score,
+            pv,
+            nodes,
+            depth,
+        }))
+    }
+}
+
 #[derive(Debug)]
 pub enum EngineReport {
     Uci(UciToEngine),
     Search(SearchToEngine),
-    Error(String),
+    Error(EngineError),
 }
 
 #[derive(Debug)]
 struct EngineOptions {
     hash: HashOption,
     threads: ThreadsOption,
+    time_usage: TimeUsageOption,
+    move_overhead: MoveOverheadOption,
+    contempt: ContemptOption,
+    opponent: OpponentOption,
+    opponent_scaling: OpponentScalingOption,
+    eval_noise: EvalNoiseOption,
+    analyse_mode: AnalyseModeOption,
+    show_wdl: ShowWdlOption,
+    permanent_brain: PermanentBrainOption,
+    online_tablebase: OnlineTablebaseOption,
+    deterministic: DeterministicOption,
+    #[cfg(feature = "gaviota")]
+    gaviota_tb_path: GaviotaTbPathOption,
+    game_database: GameDatabaseOption,
+    metrics_address: MetricsAddressOption,
+    #[cfg(feature = "tune")]
+    params: [i32; params::Param::ALL.len()],
+}
+
+impl EngineOptions {
+    fn values(&self) -> Vec<(String, String)> {
+        #[allow(unused_mut)]
+        let mut values = vec![
+            (HashOption::name().to_string(), self.hash.get().to_string()),
+            (
+                ThreadsOption::name().to_string(),
+                self.threads.get().to_string(),
+            ),
+            (
+                TimeUsageOption::name().to_string(),
+                self.time_usage.get().to_string(),
+            ),
+            (
+                MoveOverheadOption::name().to_string(),
+                self.move_overhead.get().to_string(),
+            ),
+            (
+                ContemptOption::name().to_string(),
+                self.contempt.get().to_string(),
+            ),
+            (OpponentOption::name().to_string(), self.opponent.get()),
+            (
+                OpponentScalingOption::name().to_string(),
+                self.opponent_scaling.get().to_string(),
+            ),
+            (
+                EvalNoiseOption::name().to_string(),
+                self.eval_noise.get().to_string(),
+            ),
+            (
+                AnalyseModeOption::name().to_string(),
+                self.analyse_mode.get().to_string(),
+            ),
+            (
+                ShowWdlOption::name().to_string(),
+                self.show_wdl.get().to_string(),
+            ),
+            (
+                PermanentBrainOption::name().to_string(),
+                self.permanent_brain.get().to_string(),
+            ),
+            (
+                OnlineTablebaseOption::name().to_string(),
+                self.online_tablebase.get().to_string(),
+            ),
+            (
+                DeterministicOption::name().to_string(),
+                self.deterministic.get().to_string(),
+            ),
+            #[cfg(feature = "gaviota")]
+            (
+                GaviotaTbPathOption::name().to_string(),
+                self.gaviota_tb_path.get(),
+            ),
+            (
+                GameDatabaseOption::name().to_string(),
+                self.game_database.get(),
+            ),
+            (
+                MetricsAddressOption::name().to_string(),
+                self.metrics_address.get(),
+            ),
+        ];
+
+        #[cfg(feature = "tune")]
+        values.extend(
+            params::Param::ALL
+                .into_iter()
+                .zip(self.params)
+                .map(|(param, value)| (param.name().to_string(), value.to_string())),
+        );
+
+        values
+    }
 }
 
 impl Default for EngineOptions {
@@ -322,22 +1527,77 @@ impl Default for EngineOptions {
         Self {
             hash: HashOption(HashOption::default()),
             threads: ThreadsOption(ThreadsOption::default()),
+            time_usage: TimeUsageOption(TimeUsageOption::default()),
+            move_overhead: MoveOverheadOption(MoveOverheadOption::default()),
+            contempt: ContemptOption(ContemptOption::default()),
+            opponent: OpponentOption(OpponentOption::default()),
+            opponent_scaling: OpponentScalingOption(OpponentScalingOption::default()),
+            eval_noise: EvalNoiseOption(EvalNoiseOption::default()),
+            analyse_mode: AnalyseModeOption(AnalyseModeOption::default()),
+            show_wdl: ShowWdlOption(ShowWdlOption::default()),
+            permanent_brain: PermanentBrainOption(PermanentBrainOption::default()),
+            online_tablebase: OnlineTablebaseOption(OnlineTablebaseOption::default()),
+            deterministic: DeterministicOption(DeterministicOption::default()),
+            #[cfg(feature = "gaviota")]
+            gaviota_tb_path: GaviotaTbPathOption(GaviotaTbPathOption::default()),
+            game_database: GameDatabaseOption(GameDatabaseOption::default()),
+            metrics_address: MetricsAddressOption(MetricsAddressOption::default()),
+            #[cfg(feature = "tune")]
+            params: params::Param::ALL.map(params::Param::default),
         }
     }
 }
 
 trait EngineOption {
     type Value;
-    type Error;
 
     fn name() -> &'static str;
-    fn min() -> Self::Value;
-    fn max() -> Self::Value;
     fn default() -> Self::Value;
 
     fn get(&self) -> Self::Value;
 
-    fn set(&mut self, value: Self::Value) -> Result<(), Self::Error>;
+    fn set(&mut self, value: Self::Value) -> Result<(), EngineError>;
+}
+
+fn set_string<O: EngineOption<Value = String>>(option: &mut O, value: Option<&str>) {
+    let _ = option.set(value.unwrap_or_default().trim().to_string());
+}
+
+fn set_parsed<O>(option: &mut O, value: Option<&str>) -> Result<(), EngineError>
+where
+    O: EngineOption,
+    O::Value: FromStr,
+    <O::Value as FromStr>::Err: Display,
+{
+    let value = value
+        .ok_or_else(|| EngineError::MissingValue(O::name()))?
+        .parse()
+        .map_err(
+            |error: <O::Value as FromStr>::Err| EngineError::InvalidValue {
+                option: O::name(),
+                reason: error.to_string(),
+            },
+        )?;
+
+    option.set(value)
+}
+
+trait SpinOption: EngineOption<Value = i64> {
+    fn min() -> Self::Value;
+    fn max() -> Self::Value;
+
+    fn check(value: Self::Value) -> Result<(), EngineError> {
+        if (Self::min()..=Self::max()).contains(&value) {
+            Ok(())
+        } else {
+            Err(EngineError::OutOfRange {
+                option: Self::name(),
+                value,
+                min: Self::min(),
+                max: Self::max(),
+            })
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -346,16 +1606,52 @@ struct HashOption(pub i64);
 #[derive(Debug)]
 struct ThreadsOption(pub i64);
 
-macro_rules! impl_option {
-    ($option:ty, $name:expr, $value:ty, $min:expr, $max:expr, $default:expr) => {
-        impl EngineOption for $option {
-            type Value = $value;
-            type Error = String;
+#[derive(Debug)]
+struct TimeUsageOption(pub i64);
 
-            fn name() -> &'static str {
-                $name
-            }
+#[derive(Debug)]
+struct MoveOverheadOption(pub i64);
+
+#[derive(Debug)]
+struct ContemptOption(pub i64);
+
+#[derive(Debug)]
+struct OpponentOption(pub String);
+
+#[derive(Debug)]
+struct OpponentScalingOption(pub bool);
+
+#[derive(Debug)]
+struct EvalNoiseOption(pub i64);
+
+#[derive(Debug)]
+struct AnalyseModeOption(pub bool);
+
+#[derive(Debug)]
+struct ShowWdlOption(pub bool);
+
+#[derive(Debug)]
+struct PermanentBrainOption(pub bool);
 
+#[derive(Debug)]
+struct OnlineTablebaseOption(pub bool);
+
+#[derive(Debug)]
+struct DeterministicOption(pub bool);
+
+#[cfg(feature = "gaviota")]
+#[derive(Debug)]
+struct GaviotaTbPathOption(pub String);
+
+#[derive(Debug)]
+struct GameDatabaseOption(pub String);
+
+#[derive(Debug)]
+struct MetricsAddressOption(pub String);
+
+macro_rules! impl_option {
+    ($option:ty, $name:expr, $value:ty, $min:expr, $max:expr, $default:expr) => {
+        impl SpinOption for $option {
             fn min() -> Self::Value {
                 $min
             }
@@ -363,6 +1659,14 @@ macro_rules! impl_option {
             fn max() -> Self::Value {
                 $max
             }
+        }
+
+        impl EngineOption for $option {
+            type Value = $value;
+
+            fn name() -> &'static str {
+                $name
+            }
 
             fn default() -> Self::Value {
                 $default
@@ -372,14 +1676,8 @@ macro_rules! impl_option {
                 self.0
             }
 
-            fn set(&mut self, value: Self::Value) -> Result<(), Self::Error> {
-                if value < Self::min() {
-                    return Err(format!("{} must be at least {}", Self::name(), Self::min()));
-                }
-
-                if value > Self::max() {
-                    return Err(format!("{} must be at most {}", Self::name(), Self::max()));
-                }
+            fn set(&mut self, value: Self::Value) -> Result<(), EngineError> {
+                Self::check(value)?;
 
                 self.0 = value;
 
@@ -398,7 +1696,107 @@ impl_option!(
     16
 );
 
-impl_option!(ThreadsOption, "Threads", i64, 1, 1, 1);
+impl_option!(ThreadsOption, "Threads", i64, 1, 256, 1);
+
+impl_option!(TimeUsageOption, "TimeUsage", i64, 25, 400, 100);
+
+impl_option!(MoveOverheadOption, "MoveOverhead", i64, 0, 5000, 100);
+
+impl_option!(ContemptOption, "Contempt", i64, -200, 200, 0);
+
+impl_option!(EvalNoiseOption, "Eval Noise", i64, 0, 200, 0);
+
+macro_rules! impl_string_option {
+    ($option:ty, $name:expr, $default:expr) => {
+        impl EngineOption for $option {
+            type Value = String;
+
+            fn name() -> &'static str {
+                $name
+            }
+
+            fn default() -> Self::Value {
+                String::from($default)
+            }
+
+            fn get(&self) -> Self::Value {
+                self.0.clone()
+            }
+
+            fn set(&mut self, value: Self::Value) -> Result<(), EngineError> {
+                self.0 = value;
+
+                Ok(())
+            }
+        }
+    };
+}
+
+#[cfg(feature = "gaviota")]
+impl_string_option!(GaviotaTbPathOption, "GaviotaTbPath", "");
+impl_string_option!(GameDatabaseOption, "GameDatabase", "");
+impl_string_option!(MetricsAddressOption, "MetricsAddress", "");
+impl_string_option!(OpponentOption, "UCI_Opponent", "");
+
+macro_rules! impl_check_option {
+    ($option:ty, $name:expr, $default:expr) => {
+        impl EngineOption for $option {
+            type Value = bool;
+
+            fn name() -> &'static str {
+                $name
+            }
+
+            fn default() -> Self::Value {
+                $default
+            }
+
+            fn get(&self) -> Self::Value {
+                self.0
+            }
+
+            fn set(&mut self, value: Self::Value) -> Result<(), EngineError> {
+                self.0 = value;
+
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_check_option!(OnlineTablebaseOption, "OnlineTablebase", false);
+impl_check_option!(DeterministicOption, "Deterministic", false);
+impl_check_option!(OpponentScalingOption, "OpponentScaling", false);
+impl_check_option!(AnalyseModeOption, "UCI_AnalyseMode", false);
+impl_check_option!(ShowWdlOption, "UCI_ShowWDL", false);
+
+impl_check_option!(PermanentBrainOption, "PermanentBrain", false);
+
+/// There's no way to interrupt a thread, so one that doesn't exit is left
+/// running.
+fn join_timeout(handle: std::thread::JoinHandle<()>) -> bool {
+    let deadline = std::time::Instant::now() + JOIN_TIMEOUT;
+
+    while !handle.is_finished() {
+        if std::time::Instant::now() >= deadline {
+            return false;
+        }
+
+        std::thread::sleep(core::time::Duration::from_millis(1));
+    }
+
+    let _ = handle.join();
+
+    true
+}
+
+fn new_noise_seed() -> u64 {
+    // only the low bits change between games, so truncating is fine
+    #[allow(clippy::cast_possible_truncation)]
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos() as u64)
+}
 
 fn pkg_authors() -> String {
     env!("CARGO_PKG_AUTHORS")
@@ -407,11 +1805,99 @@ fn pkg_authors() -> String {
         .join(", ")
 }
 
-fn pretty_print_board(board: &Board) {
-    println!("+---+---+---+---+---+---+---+---+");
+fn format_moves(board: &Board, hash_move: Option<Move>) -> String {
+    let moves = search::scored_moves(board, hash_move);
+
+    let mut text = format!("{} legal moves", moves.len());
+
+    for (mv, score) in moves {
+        let _ = write!(
+            text,
+            "\n  {:<6} {score}",
+            uci::convert_move_to_uci(board, mv).to_string()
+        );
+    }
+
+    text
+}
+
+fn format_see(board: &Board, mv: Move) -> String {
+    let mut exchange = vec![uci::convert_move_to_uci(board, mv).to_string()];
+
+    let value = see::see_traced(board, mv, |from| {
+        exchange.push(format!("{from}{}", mv.to));
+    });
+
+    format!("see:      {value}\nexchange: {}", exchange.join(" "))
+}
+
+fn format_perft(board: &Board, depth: u8, divide: bool) -> String {
+    let start = std::time::Instant::now();
+    let mut text = String::new();
+
+    let nodes = if divide {
+        let divided = perft::divide(board, depth);
+
+        for (mv, nodes) in &divided {
+            let _ = writeln!(text, "{}: {nodes}", uci::convert_move_to_uci(board, *mv));
+        }
+
+        text.push('\n');
+
+        divided.iter().map(|(_, nodes)| nodes).sum()
+    } else {
+        perft::perft(board, depth)
+    };
+
+    let elapsed = start.elapsed();
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    let nps = (nodes as f64 / elapsed.as_secs_f64().max(f64::EPSILON)) as u64;
+
+    let _ = write!(text, "nodes {nodes} time {} nps {nps}", elapsed.as_millis());
+
+    text
+}
+
+fn format_board(board: &Board) -> String {
+    let checkers = board
+        .checkers()
+        .into_iter()
+        .map(|square| square.to_string())
+        .collect::<Vec<_>>();
+
+    let eval = evaluate::evaluate(&Position::new(board.clone()));
+
+    format!(
+        "{}\n\
+         \n\
+         fen:      {board}\n\
+         hash:     {:x}\n\
+         checkers: {}\n\
+         eval:     {eval} for {}",
+        format_pretty_board(board),
+        board.hash(),
+        if checkers.is_empty() {
+            "none".to_string()
+        } else {
+            checkers.join(" ")
+        },
+        match board.side_to_move() {
+            Color::White => "white",
+            Color::Black => "black",
+        }
+    )
+}
+
+fn format_pretty_board(board: &Board) -> String {
+    let mut text = "+---+---+---+---+---+---+---+---+\n".to_string();
 
     for rank in Rank::ALL.into_iter().rev() {
-        print!("|");
+        text.push('|');
 
         for file in File::ALL {
             let square = Square::new(file, rank);
@@ -436,12 +1922,16 @@ fn pretty_print_board(board: &Board) {
                         Color::Black => symbol,
                     };
 
-                    print!(" {symbol} |");
+                    let _ = write!(text, " {symbol} |");
                 }
-                _ => print!("   |"),
+                _ => text.push_str("   |"),
             }
         }
 
-        println!("\n+---+---+---+---+---+---+---+---+");
+        let _ = writeln!(text, " {rank}\n+---+---+---+---+---+---+---+---+");
     }
+
+    text.push_str("  a   b   c   d   e   f   g   h");
+
+    text
 }
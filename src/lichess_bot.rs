@@ -0,0 +1,417 @@
+#[cfg(feature = "online")]
+use crate::{
+    search::{game_search, History},
+    tt::TranspositionTable,
+    uci::{convert_move_to_uci, GameTime},
+};
+#[cfg(feature = "online")]
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "online")]
+use cozy_chess::{util::parse_uci_move, Board, Color};
+#[cfg(feature = "online")]
+use serde_json::Value;
+#[cfg(feature = "online")]
+use std::io::{BufRead, BufReader};
+
+#[cfg(feature = "online")]
+const API_URL: &str = "https://lichess.org";
+
+pub const DEFAULT_MAX_GAMES: usize = 1;
+
+#[cfg(feature = "online")]
+const HASH_MB: usize = 64;
+
+#[derive(Debug)]
+pub struct BotConfig {
+    /// Of a bot account, with the `bot:play` scope.
+    pub token: String,
+    pub max_games: usize,
+}
+
+#[derive(Debug)]
+pub enum BotEvent {
+    Connected {
+        username: String,
+    },
+    ChallengeAccepted {
+        id: String,
+        challenger: String,
+    },
+    ChallengeDeclined {
+        id: String,
+        challenger: String,
+        reason: &'static str,
+    },
+    GameStarted {
+        id: String,
+    },
+    GameFinished {
+        id: String,
+        status: String,
+    },
+    Error {
+        id: String,
+        error: String,
+    },
+}
+
+/// Accepts challenges to standard games with a clock while fewer than
+/// `config.max_games` are running, and plays each on its own thread.
+#[cfg(feature = "online")]
+pub fn run(config: &BotConfig, on_event: impl Fn(BotEvent) + Sync) -> Result<(), String> {
+    let client = Client::new(&config.token);
+
+    let account = client.get_json("/api/account")?;
+
+    let bot_id = account["id"]
+        .as_str()
+        .ok_or("the token doesn't belong to an account")?;
+
+    on_event(BotEvent::Connected {
+        username: account["username"].as_str().unwrap_or(bot_id).to_string(),
+    });
+
+    let games = AtomicUsize::new(0);
+
+    std::thread::scope(|scope| {
+        client.stream("/api/stream/event", |event| {
+            match event["type"].as_str() {
+                Some("challenge") => {
+                    let challenge = &event["challenge"];
+
+                    let id = challenge["id"].as_str().unwrap_or_default().to_string();
+                    let challenger = challenge["challenger"]["id"]
+                        .as_str()
+                        .unwrap_or_default()
+                        .to_string();
+
+                    // challenges the bot sent show up too
+                    if challenger == bot_id {
+                        return Ok(true);
+                    }
+
+                    let busy = games.load(Ordering::Relaxed) >= config.max_games;
+
+                    if let Some(reason) = decline_reason(challenge, busy) {
+                        client.post(
+                            &format!("/api/challenge/{id}/decline"),
+                            &[("reason", reason)],
+                        )?;
+
+                        on_event(BotEvent::ChallengeDeclined {
+                            id,
+                            challenger,
+                            reason,
+                        });
+                    } else {
+                        client.post(&format!("/api/challenge/{id}/accept"), &[])?;
+
+                        on_event(BotEvent::ChallengeAccepted { id, challenger });
+                    }
+                }
+                Some("gameStart") => {
+                    let game = &event["game"];
+                    let id = game["gameId"]
+                        .as_str()
+                        .or_else(|| game["id"].as_str())
+                        .unwrap_or_default()
+                        .to_string();
+
+                    games.fetch_add(1, Ordering::Relaxed);
+
+                    let (client, games, on_event) = (&client, &games, &on_event);
+
+                    scope.spawn(move || {
+                        on_event(BotEvent::GameStarted { id: id.clone() });
+
+                        let status = play_game(client, bot_id, &id, on_event);
+
+                        games.fetch_sub(1, Ordering::Relaxed);
+
+                        match status {
+                            Ok(status) => on_event(BotEvent::GameFinished { id, status }),
+                            Err(error) => on_event(BotEvent::Error { id, error }),
+                        }
+                    });
+                }
+                _ => {}
+            }
+
+            Ok(true)
+        })
+    })
+}
+
+#[cfg(not(feature = "online"))]
+pub fn run(_config: &BotConfig, _on_event: impl Fn(BotEvent) + Sync) -> Result<(), String> {
+    Err("eccat was built without the `online` feature".to_string())
+}
+
+#[cfg(feature = "online")]
+fn play_game(
+    client: &Client,
+    bot_id: &str,
+    id: &str,
+    on_event: &impl Fn(BotEvent),
+) -> Result<String, String> {
+    let tt = TranspositionTable::new(HASH_MB);
+
+    let mut game = None;
+    let mut status = "aborted".to_string();
+
+    client.stream(&format!("/api/bot/game/stream/{id}"), |event| {
+        let state = match event["type"].as_str() {
+            Some("gameFull") => {
+                game = Some(Game::new(&event, bot_id)?);
+                &event["state"]
+            }
+            Some("gameState") => &event,
+            _ => return Ok(true),
+        };
+
+        let Some(game) = &game else {
+            return Ok(true);
+        };
+
+        state["status"]
+            .as_str()
+            .unwrap_or_default()
+            .clone_into(&mut status);
+
+        if status != "started" && status != "created" {
+            return Ok(false);
+        }
+
+        let (board, history) = game.position(state)?;
+
+        if board.side_to_move() != game.color {
+            return Ok(true);
+        }
+
+        let Some(mv) = game_search(&board, &history, game_time(state), &tt) else {
+            return Ok(true);
+        };
+
+        let mv = convert_move_to_uci(&board, mv);
+
+        // the game may have ended while searching, which isn't worth stopping for
+        if let Err(error) = client.post(&format!("/api/bot/game/{id}/move/{mv}"), &[]) {
+            on_event(BotEvent::Error {
+                id: id.to_string(),
+                error,
+            });
+        }
+
+        Ok(true)
+    })?;
+
+    Ok(status)
+}
+
+#[cfg(feature = "online")]
+fn decline_reason(challenge: &Value, busy: bool) -> Option<&'static str> {
+    if !matches!(
+        challenge["variant"]["key"].as_str(),
+        Some("standard" | "fromPosition")
+    ) {
+        Some("standard")
+    } else if challenge["timeControl"]["type"].as_str() != Some("clock") {
+        // a search needs a clock to budget its time
+        Some("timeControl")
+    } else if busy {
+        Some("later")
+    } else {
+        None
+    }
+}
+
+#[cfg(feature = "online")]
+struct Game {
+    start: Board,
+    color: Color,
+}
+
+#[cfg(feature = "online")]
+impl Game {
+    fn new(full: &Value, bot_id: &str) -> Result<Self, String> {
+        let start = match full["initialFen"].as_str() {
+            None | Some("startpos") => Board::default(),
+            Some(fen) => Board::from_fen(fen, false).map_err(|err| format!("{fen}: {err}"))?,
+        };
+
+        let color = if full["white"]["id"].as_str() == Some(bot_id) {
+            Color::White
+        } else {
+            Color::Black
+        };
+
+        Ok(Self { start, color })
+    }
+
+    fn position(&self, state: &Value) -> Result<(Board, Vec<History>), String> {
+        let mut board = self.start.clone();
+        let mut history = vec![History { hash: board.hash() }];
+
+        for mv in state["moves"]
+            .as_str()
+            .unwrap_or_default()
+            .split_whitespace()
+        {
+            let parsed = parse_uci_move(&board, mv)
+                .ok()
+                .filter(|&parsed| board.is_legal(parsed))
+                .ok_or_else(|| format!("illegal move from lichess: {mv}"))?;
+
+            board.play_unchecked(parsed);
+            history.push(History { hash: board.hash() });
+        }
+
+        Ok((board, history))
+    }
+}
+
+#[cfg(feature = "online")]
+fn game_time(state: &Value) -> GameTime {
+    let ms = |key: &str| chrono::Duration::milliseconds(state[key].as_i64().unwrap_or_default());
+
+    GameTime {
+        white_time: ms("wtime"),
+        black_time: ms("btime"),
+        white_increment: ms("winc"),
+        black_increment: ms("binc"),
+        moves_to_go: None,
+    }
+}
+
+#[cfg(feature = "online")]
+struct Client {
+    agent: ureq::Agent,
+    authorization: String,
+}
+
+#[cfg(feature = "online")]
+impl Client {
+    fn new(token: &str) -> Self {
+        Self {
+            agent: ureq::Agent::new_with_defaults(),
+            authorization: format!("Bearer {token}"),
+        }
+    }
+
+    fn get_json(&self, path: &str) -> Result<Value, String> {
+        let body = self
+            .agent
+            .get(format!("{API_URL}{path}"))
+            .header("Authorization", &self.authorization)
+            .call()
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|err| format!("{path}: {err}"))?;
+
+        serde_json::from_str(&body).map_err(|err| format!("{path}: {err}"))
+    }
+
+    fn post(&self, path: &str, form: &[(&str, &str)]) -> Result<(), String> {
+        self.agent
+            .post(format!("{API_URL}{path}"))
+            .header("Authorization", &self.authorization)
+            .send_form(form.iter().copied())
+            .map(drop)
+            .map_err(|err| format!("{path}: {err}"))
+    }
+
+    fn stream(
+        &self,
+        path: &str,
+        mut on_event: impl FnMut(Value) -> Result<bool, String>,
+    ) -> Result<(), String> {
+        let response = self
+            .agent
+            .get(format!("{API_URL}{path}"))
+            .header("Authorization", &self.authorization)
+            .call()
+            .map_err(|err| format!("{path}: {err}"))?;
+
+        for line in BufReader::new(response.into_body().into_reader()).lines() {
+            let line = line.map_err(|err| format!("{path}: {err}"))?;
+
+            // sent every few seconds to keep the connection open
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let event = serde_json::from_str(&line).map_err(|err| format!("{path}: {err}"))?;
+
+            if !on_event(event)? {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "online"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decline_reason() {
+        let challenge = |variant: &str, time_control: &str| {
+            serde_json::json!({
+                "variant": { "key": variant },
+                "timeControl": { "type": time_control },
+            })
+        };
+
+        assert_eq!(decline_reason(&challenge("standard", "clock"), false), None);
+        assert_eq!(
+            decline_reason(&challenge("standard", "clock"), true),
+            Some("later")
+        );
+        assert_eq!(
+            decline_reason(&challenge("atomic", "clock"), false),
+            Some("standard")
+        );
+        assert_eq!(
+            decline_reason(&challenge("standard", "correspondence"), false),
+            Some("timeControl")
+        );
+    }
+
+    #[test]
+    fn test_game() {
+        let full = serde_json::json!({
+            "type": "gameFull",
+            "initialFen": "startpos",
+            "white": { "id": "someone" },
+            "black": { "id": "eccat" },
+            "state": {
+                "moves": "e2e4 e7e5 g1f3 b8c6 f1c4 g8f6 e1g1",
+                "wtime": 60000,
+                "btime": 55000,
+                "winc": 1000,
+                "binc": 1000,
+                "status": "started",
+            },
+        });
+
+        let game = Game::new(&full, "eccat").unwrap();
+        let (board, history) = game.position(&full["state"]).unwrap();
+
+        assert_eq!(game.color, Color::Black);
+        assert_eq!(
+            board.to_string(),
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 5 4"
+        );
+        assert_eq!(history.len(), 8);
+
+        let clocks = game_time(&full["state"]);
+
+        assert_eq!(clocks.black_time, chrono::Duration::milliseconds(55000));
+        assert_eq!(clocks.white_increment, chrono::Duration::seconds(1));
+
+        let bad = serde_json::json!({ "moves": "e2e4 e2e4" });
+
+        assert!(game.position(&bad).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use core::time::Duration;
+use cozy_chess::{Board, Move};
+use std::{collections::HashMap, time::Instant};
+
+pub const MAX_PIECES: usize = 7;
+
+#[cfg(feature = "online")]
+const API_URL: &str = "https://tablebase.lichess.ovh/standard";
+
+pub const MAX_REQUEST_TIME: Duration = Duration::from_secs(1);
+
+/// Lichess asks API users to keep to about one request per second.
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(not(feature = "online"), allow(dead_code))]
+pub enum Wdl {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// With what it leads to for the side making it.
+#[derive(Clone, Copy, Debug)]
+pub struct TbMove {
+    pub mv: Move,
+    /// `None` when the server can't tell whether the fifty move rule saves
+    /// the losing side.
+    pub wdl: Option<Wdl>,
+    pub dtz: Option<i32>,
+    pub dtm: Option<i32>,
+}
+
+/// Best first.
+#[derive(Clone, Debug)]
+pub struct RootProbe {
+    pub moves: Vec<TbMove>,
+}
+
+impl RootProbe {
+    pub fn best(&self, is_allowed: impl Fn(Move) -> bool) -> Option<TbMove> {
+        self.moves
+            .iter()
+            .copied()
+            .find(|tb_move| is_allowed(tb_move.mv))
+    }
+}
+
+/// Results are cached per position and requests are rate-limited, so repeated
+/// `go` commands in the same ending don't hammer the server.
+#[derive(Debug, Default)]
+pub struct LichessTablebase {
+    cache: HashMap<u64, Option<RootProbe>>,
+    last_request: Option<Instant>,
+}
+
+impl LichessTablebase {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn probe_root(
+        &mut self,
+        board: &Board,
+        timeout: Duration,
+    ) -> Result<Option<RootProbe>, String> {
+        if board.occupied().len() as usize > MAX_PIECES {
+            return Ok(None);
+        }
+
+        if let Some(cached) = self.cache.get(&board.hash()) {
+            return Ok(cached.clone());
+        }
+
+        if self
+            .last_request
+            .is_some_and(|last| last.elapsed() < MIN_REQUEST_INTERVAL)
+        {
+            return Err("online tablebase rate limit reached, skipping probe".to_string());
+        }
+
+        self.last_request = Some(Instant::now());
+
+        let body = fetch(board, timeout.min(MAX_REQUEST_TIME))?;
+        let probe = parse_response(board, &body)?;
+
+        self.cache.insert(board.hash(), probe.clone());
+
+        Ok(probe)
+    }
+}
+
+#[cfg(feature = "online")]
+fn fetch(board: &Board, timeout: Duration) -> Result<String, String> {
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .build()
+        .into();
+
+    agent
+        .get(API_URL)
+        .query("fen", board.to_string())
+        .call()
+        .and_then(|mut response| response.body_mut().read_to_string())
+        .map_err(|err| format!("online tablebase request failed: {err}"))
+}
+
+#[cfg(not(feature = "online"))]
+fn fetch(_board: &Board, _timeout: Duration) -> Result<String, String> {
+    Err("eccat was built without the `online` feature".to_string())
+}
+
+#[cfg(feature = "online")]
+fn parse_response(board: &Board, body: &str) -> Result<Option<RootProbe>, String> {
+    let json: serde_json::Value = serde_json::from_str(body)
+        .map_err(|err| format!("invalid online tablebase response: {err}"))?;
+
+    let Some(moves) = json["moves"].as_array() else {
+        return Ok(None);
+    };
+
+    // each move is described from the opponent's side, after it's made
+    let moves = moves
+        .iter()
+        .filter_map(|entry| {
+            let wdl = match entry["category"].as_str()? {
+                "win" => Some(Wdl::Loss),
+                "loss" => Some(Wdl::Win),
+                "draw" | "cursed-win" | "blessed-loss" => Some(Wdl::Draw),
+                "maybe-win" | "maybe-loss" => None,
+                _ => return None,
+            };
+
+            let distance = |key: &str| {
+                entry[key]
+                    .as_i64()
+                    .and_then(|value| i32::try_from(-value).ok())
+            };
+
+            Some(TbMove {
+                mv: cozy_chess::util::parse_uci_move(board, entry["uci"].as_str()?).ok()?,
+                wdl,
+                dtz: distance("dtz"),
+                dtm: distance("dtm"),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Ok((!moves.is_empty()).then_some(RootProbe { moves }))
+}
+
+#[cfg(not(feature = "online"))]
+#[allow(clippy::unnecessary_wraps)]
+const fn parse_response(_board: &Board, _body: &str) -> Result<Option<RootProbe>, String> {
+    Ok(None)
+}
+
+#[cfg(all(test, feature = "online"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_response() {
+        let board: Board = "8/8/8/4k3/8/8/8/4KQ2 w - - 0 1".parse().unwrap();
+        let body = r#"{"category":"win","dtz":19,"dtm":19,"moves":[{"uci":"f1f7","category":"loss","dtz":-18,"dtm":-18},{"uci":"f1f6","category":"loss","dtz":-20,"dtm":-20},{"uci":"e1d1","category":"draw","dtz":0,"dtm":null}]}"#;
+
+        let probe = parse_response(&board, body).unwrap().unwrap();
+        let best = probe.best(|_| true).unwrap();
+
+        assert_eq!(best.mv, "f1f7".parse().unwrap());
+        assert_eq!(best.wdl, Some(Wdl::Win));
+        assert_eq!(best.dtz, Some(18));
+
+        // searchmoves without the best move
+        let f1f7 = "f1f7".parse().unwrap();
+        let best = probe.best(|mv| mv != f1f7).unwrap();
+
+        assert_eq!(best.mv, "f1f6".parse().unwrap());
+        assert_eq!(best.dtm, Some(20));
+
+        let e1d1 = "e1d1".parse().unwrap();
+        assert_eq!(probe.best(|mv| mv == e1d1).unwrap().wdl, Some(Wdl::Draw));
+    }
+
+    #[test]
+    fn test_maybe_results_are_unproven() {
+        let board: Board = "8/8/8/4k3/8/8/8/4KQ2 w - - 0 1".parse().unwrap();
+        let body = r#"{"category":"maybe-win","moves":[{"uci":"f1f7","category":"maybe-loss","dtz":-99,"dtm":null},{"uci":"e1d1","category":"maybe-win","dtz":98,"dtm":null}]}"#;
+
+        let probe = parse_response(&board, body).unwrap().unwrap();
+
+        assert_eq!(probe.moves.len(), 2);
+        assert!(probe.moves.iter().all(|tb_move| tb_move.wdl.is_none()));
+    }
+}
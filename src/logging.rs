@@ -0,0 +1,60 @@
+use std::sync::OnceLock;
+use tracing_subscriber::{
+    filter::LevelFilter, fmt::format::FmtSpan, layer::SubscriberExt, reload,
+    util::SubscriberInitExt, Registry,
+};
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::WARN;
+
+const DEBUG_LEVEL: LevelFilter = LevelFilter::DEBUG;
+
+static LEVEL: OnceLock<reload::Handle<LevelFilter, Registry>> = OnceLock::new();
+
+/// Does nothing if a subscriber was set already, like by a program embedding
+/// the engine.
+pub fn init() {
+    let (level, handle) = reload::Layer::new(DEFAULT_LEVEL);
+
+    let installed = tracing_subscriber::registry()
+        .with(level)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .with_span_events(FmtSpan::CLOSE),
+        )
+        .try_init()
+        .is_ok();
+
+    if installed {
+        let _ = LEVEL.set(handle);
+    }
+}
+
+pub fn set_debug(debug: bool) {
+    if let Some(handle) = LEVEL.get() {
+        let _ = handle.modify(|level| {
+            *level = if debug { DEBUG_LEVEL } else { DEFAULT_LEVEL };
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::Level;
+
+    #[test]
+    fn test_set_debug() {
+        init();
+
+        assert!(tracing::enabled!(Level::WARN));
+        assert!(!tracing::enabled!(Level::DEBUG));
+
+        set_debug(true);
+        assert!(tracing::enabled!(Level::DEBUG));
+        assert!(!tracing::enabled!(Level::TRACE));
+
+        set_debug(false);
+        assert!(!tracing::enabled!(Level::DEBUG));
+    }
+}
@@ -1,8 +1,31 @@
-use eccat::Engine;
-use std::process::ExitCode;
+use eccat::{
+    bench, config, datagen, egtb_download, epd, lichess_bot, params, selfplay, server, tune, Engine,
+};
+use std::{path::Path, process::ExitCode};
 
 fn main() -> ExitCode {
-    match Engine::new().main_loop() {
+    eccat::logging::init();
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let result = match args.first().map(String::as_str) {
+        Some("download-egtb") => download_egtb(&args[1..]),
+        Some("ablate") => ablate(&args[1..]),
+        Some("bench") => run_bench(&args[1..]),
+        Some("tune") => run_tune(&args[1..]),
+        Some("spsa-config") => {
+            print!("{}", params::spsa_config());
+            Ok(())
+        }
+        Some("epdtest") => epd_test(&args[1..]),
+        Some("selfplay") => self_play(&args[1..]),
+        Some("datagen") => run_datagen(&args[1..]),
+        Some("lichess-bot") => lichess_bot(&args[1..]),
+        Some("serve") => serve(&args[1..]),
+        _ => run_engine(&args),
+    };
+
+    match result {
         Ok(()) => ExitCode::SUCCESS,
         Err(error) => {
             eprintln!("error: {error}");
@@ -11,6 +34,443 @@ fn main() -> ExitCode {
     }
 }
 
+/// Option defaults come from `--config <file>`, or `eccat.toml` if there is
+/// one.
+fn run_engine(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let options = match args {
+        [flag, path, ..] if flag == "--config" => config::load(Path::new(path))?,
+        _ => default_options()?,
+    };
+
+    Engine::new().with_options(options).main_loop()?;
+
+    Ok(())
+}
+
+fn default_options() -> Result<Vec<(String, String)>, String> {
+    let path = Path::new(config::DEFAULT_CONFIG_FILE);
+
+    if path.is_file() {
+        config::load(path)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+fn serve(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let usage = "usage: eccat serve <host:port | unix:path> [--sessions <n>] \
+                 [--shared-hash <mb>] [--config <file>]";
+
+    let [address, flags @ ..] = args else {
+        return Err(usage.into());
+    };
+
+    let mut config = server::ServerConfig {
+        address: server::Address::parse(address),
+        sessions: server::DEFAULT_SESSIONS,
+        shared_hash: None,
+        options: default_options()?,
+    };
+
+    for flag in flags.chunks(2) {
+        let [name, value] = flag else {
+            return Err(usage.into());
+        };
+
+        match name.as_str() {
+            "--sessions" => config.sessions = value.parse()?,
+            "--shared-hash" => config.shared_hash = Some(value.parse()?),
+            "--config" => config.options = config::load(Path::new(value))?,
+            _ => return Err(usage.into()),
+        }
+    }
+
+    server::run(&config, |event| match event {
+        server::ServerEvent::Listening { address } => println!("listening on {address}"),
+        server::ServerEvent::Connected { session } => println!("session {session} connected"),
+        server::ServerEvent::Disconnected { session } => {
+            println!("session {session} disconnected");
+        }
+        server::ServerEvent::Refused => println!("refused a connection, every session is taken"),
+        server::ServerEvent::AcceptFailed(error) => eprintln!("error: {error}"),
+        server::ServerEvent::Error { session, error } => {
+            eprintln!("error: session {session}: {error}");
+        }
+    })?;
+
+    Ok(())
+}
+
+fn download_egtb(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let dir = args
+        .first()
+        .ok_or("usage: eccat download-egtb <dir> [connections]")?;
+
+    let threads = args
+        .get(1)
+        .map(|threads| threads.parse())
+        .transpose()?
+        .unwrap_or(egtb_download::DEFAULT_THREADS);
+
+    egtb_download::download_egtb(
+        egtb_download::DEFAULT_MIRROR,
+        Path::new(dir),
+        threads,
+        |event| match event {
+            egtb_download::DownloadEvent::Started { total } => {
+                println!("downloading {total} files to {dir}");
+            }
+            egtb_download::DownloadEvent::FileDone {
+                file,
+                completed,
+                total,
+                skipped,
+            } => println!(
+                "[{completed}/{total}] {file}{}",
+                if skipped { " (already downloaded)" } else { "" }
+            ),
+            egtb_download::DownloadEvent::FileRetrying {
+                file,
+                attempt,
+                error,
+            } => eprintln!(
+                "warning: {file}: {error} (attempt {attempt} of {}, retrying)",
+                egtb_download::ATTEMPTS
+            ),
+            egtb_download::DownloadEvent::FileFailed { file, error } => {
+                eprintln!("error: {file}: {error}");
+            }
+            egtb_download::DownloadEvent::Finished { failed, .. } => {
+                if !failed.is_empty() {
+                    eprintln!("these files failed to download, run again to retry them:");
+
+                    for file in failed {
+                        eprintln!("  {file}");
+                    }
+                }
+            }
+        },
+    )?;
+
+    Ok(())
+}
+
+fn run_bench(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let depth = args
+        .first()
+        .map(|depth| depth.parse())
+        .transpose()?
+        .unwrap_or(bench::DEFAULT_DEPTH);
+
+    println!("{}", bench::run(depth));
+
+    Ok(())
+}
+
+fn run_tune(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let path = args
+        .first()
+        .ok_or("usage: eccat tune <positions> [epochs]")?;
+
+    let epochs = args
+        .get(1)
+        .map(|epochs| epochs.parse())
+        .transpose()?
+        .unwrap_or(tune::DEFAULT_EPOCHS);
+
+    let (mut tuner, skipped) = tune::Tuner::load(Path::new(path))?;
+
+    if skipped > 0 {
+        eprintln!("warning: skipped {skipped} lines that aren't a position and a result");
+    }
+
+    eprintln!(
+        "tuning on {} positions, k = {:.3}, error = {:.6}",
+        tuner.positions(),
+        tuner.k(),
+        tuner.error(tuner.k())
+    );
+
+    tuner.tune(epochs, |epoch, error| {
+        if epoch % 50 == 0 || epoch == epochs {
+            eprintln!("epoch {epoch}: error = {error:.6}");
+        }
+    });
+
+    println!("{tuner}");
+
+    Ok(())
+}
+
+fn epd_test(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let usage = "usage: eccat epdtest <file> [--movetime <ms>]";
+
+    let path = args.first().ok_or(usage)?;
+
+    let move_time = match args.get(1..) {
+        Some([flag, ms]) if flag == "--movetime" => ms.parse()?,
+        Some([]) | None => epd::DEFAULT_MOVE_TIME,
+        Some(_) => return Err(usage.into()),
+    };
+
+    let (positions, skipped) = epd::load(Path::new(path))?;
+
+    if skipped > 0 {
+        eprintln!("warning: skipped {skipped} lines that aren't a position with legal moves");
+    }
+
+    println!(
+        "searching {} positions for {move_time} ms each",
+        positions.len()
+    );
+
+    let mut solved = 0;
+    let mut searched = 0;
+    let mut points = 0;
+    let mut max_points = 0;
+
+    epd::run(
+        &positions,
+        chrono::Duration::milliseconds(move_time),
+        |position, result| {
+            searched += 1;
+            solved += usize::from(result.solved);
+            points += result.points;
+            max_points += position.max_points();
+
+            let expected = position
+                .best_moves
+                .iter()
+                .map(|&mv| epd::san(&position.board, mv))
+                .chain(
+                    position
+                        .avoid_moves
+                        .iter()
+                        .map(|&mv| format!("not {}", epd::san(&position.board, mv))),
+                )
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            println!(
+                "{:<16} {:<8} {:<6} {expected}",
+                position.id.as_deref().unwrap_or("-"),
+                result.played,
+                if result.solved { "ok" } else { "failed" },
+            );
+        },
+    );
+
+    println!();
+    println!("solved {solved}/{searched}");
+
+    if max_points > 0 {
+        println!("score {points}/{max_points}");
+    }
+
+    Ok(())
+}
+
+fn self_play(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let usage = "usage: eccat selfplay <engine>[,Name=value...] <engine>[,Name=value...] \
+                 [--tc <seconds>[+<increment>]] [--games <n>] [--openings <file>] \
+                 [--elo0 <elo>] [--elo1 <elo>]";
+
+    let [first, second, flags @ ..] = args else {
+        return Err(usage.into());
+    };
+
+    let engine = |spec: &str| selfplay::EngineConfig::parse(spec).ok_or(usage);
+
+    let mut config = selfplay::MatchConfig {
+        engines: [engine(first)?, engine(second)?],
+        time_control: selfplay::TimeControl::parse(selfplay::DEFAULT_TIME_CONTROL).unwrap(),
+        openings: Vec::new(),
+        games: selfplay::DEFAULT_GAMES,
+        sprt: selfplay::Sprt::default(),
+    };
+
+    for flag in flags.chunks(2) {
+        let [name, value] = flag else {
+            return Err(usage.into());
+        };
+
+        match name.as_str() {
+            "--tc" => {
+                config.time_control = selfplay::TimeControl::parse(value).ok_or(usage)?;
+            }
+            "--games" => config.games = value.parse()?,
+            "--openings" => {
+                let (openings, skipped) = selfplay::load_openings(Path::new(value))?;
+
+                if skipped > 0 {
+                    eprintln!("warning: skipped {skipped} lines that aren't a position");
+                }
+
+                config.openings = openings;
+            }
+            "--elo0" => config.sprt.elo0 = value.parse()?,
+            "--elo1" => config.sprt.elo1 = value.parse()?,
+            _ => return Err(usage.into()),
+        }
+    }
+
+    let (lower, upper) = config.sprt.bounds();
+
+    println!(
+        "sprt elo0 {} elo1 {}, stopping at llr {lower:.2} or {upper:.2}",
+        config.sprt.elo0, config.sprt.elo1
+    );
+
+    let (score, result) = selfplay::run(&config, |score, _| {
+        println!(
+            "games {}: {score}  elo {:+.1}  llr {:.2}",
+            score.games(),
+            score.elo(),
+            config.sprt.llr(score)
+        );
+    })?;
+
+    println!();
+
+    match result {
+        Some(selfplay::SprtResult::Accept) => println!("H1 accepted after {} games", score.games()),
+        Some(selfplay::SprtResult::Reject) => println!("H0 accepted after {} games", score.games()),
+        None => println!("no result after {} games", score.games()),
+    }
+
+    Ok(())
+}
+
+fn run_datagen(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let usage = "usage: eccat datagen <output> [--games <n>] [--nodes <n>] [--threads <n>] \
+                 [--seed <n>]";
+
+    let [path, flags @ ..] = args else {
+        return Err(usage.into());
+    };
+
+    let mut config = datagen::DatagenConfig {
+        games: datagen::DEFAULT_GAMES,
+        nodes: datagen::DEFAULT_NODES,
+        threads: 1,
+        seed: 1,
+    };
+
+    for flag in flags.chunks(2) {
+        let [name, value] = flag else {
+            return Err(usage.into());
+        };
+
+        match name.as_str() {
+            "--games" => config.games = value.parse()?,
+            "--nodes" => config.nodes = value.parse()?,
+            "--threads" => config.threads = value.parse()?,
+            "--seed" => config.seed = value.parse()?,
+            _ => return Err(usage.into()),
+        }
+    }
+
+    println!(
+        "playing {} games at {} nodes per move on {} threads into {path}",
+        config.games, config.nodes, config.threads
+    );
+
+    datagen::run(Path::new(path), &config, |games, positions| {
+        if games % 10 == 0 || games == config.games {
+            println!("games {games}: {positions} positions");
+        }
+    })?;
+
+    Ok(())
+}
+
+fn lichess_bot(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let usage = "usage: eccat lichess-bot --token <token> [--max-games <n>]";
+
+    let mut token = None;
+    let mut max_games = lichess_bot::DEFAULT_MAX_GAMES;
+
+    for flag in args.chunks(2) {
+        let [name, value] = flag else {
+            return Err(usage.into());
+        };
+
+        match name.as_str() {
+            "--token" => token = Some(value.clone()),
+            "--max-games" => max_games = value.parse()?,
+            _ => return Err(usage.into()),
+        }
+    }
+
+    let config = lichess_bot::BotConfig {
+        token: token.ok_or(usage)?,
+        max_games,
+    };
+
+    lichess_bot::run(&config, |event| match event {
+        lichess_bot::BotEvent::Connected { username } => {
+            println!("connected to lichess as {username}");
+        }
+        lichess_bot::BotEvent::ChallengeAccepted { id, challenger } => {
+            println!("accepted challenge {id} from {challenger}");
+        }
+        lichess_bot::BotEvent::ChallengeDeclined {
+            id,
+            challenger,
+            reason,
+        } => println!("declined challenge {id} from {challenger}: {reason}"),
+        lichess_bot::BotEvent::GameStarted { id } => println!("game {id} started"),
+        lichess_bot::BotEvent::GameFinished { id, status } => {
+            println!("game {id} finished: {status}");
+        }
+        lichess_bot::BotEvent::Error { id, error } => eprintln!("error: game {id}: {error}"),
+    })?;
+
+    Ok(())
+}
+
+fn ablate(args: &[String]) -> Result<(), Box<dyn core::error::Error>> {
+    let depth = args
+        .first()
+        .map(|depth| depth.parse())
+        .transpose()?
+        .unwrap_or(bench::DEFAULT_DEPTH);
+
+    println!(
+        "searching {} positions to depth {depth} with each heuristic disabled in turn",
+        bench::BENCH_POSITIONS.len()
+    );
+    println!();
+    println!(
+        "{:<30} {:>10} {:>8} {:>8} {:>8}",
+        "disabled", "nodes", "nodes %", "ms", "time %"
+    );
+
+    let mut baseline = None;
+
+    bench::ablate(depth, |ablation| {
+        let result = ablation.result;
+        let base = *baseline.get_or_insert(result);
+
+        let change = |new: f64, old: f64| 100.0 * (new - old) / old.max(f64::EPSILON);
+
+        #[allow(clippy::cast_precision_loss)]
+        let nodes_change = change(result.nodes as f64, base.nodes as f64);
+        let time_change = change(result.time.as_secs_f64(), base.time.as_secs_f64());
+
+        println!(
+            "{:<30} {:>10} {:>+8.1} {:>8} {:>+8.1}",
+            ablation.disabled.unwrap_or("(none)"),
+            result.nodes,
+            nodes_change,
+            result.time.as_millis(),
+            time_change,
+        );
+    });
+
+    Ok(())
+}
+
 #[cfg(all(not(windows), feature = "jemalloc"))]
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
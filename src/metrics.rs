@@ -0,0 +1,222 @@
+use core::{
+    fmt::Write as _,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+use std::{
+    io::{Read as _, Write as _},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// In the Prometheus text format.
+#[derive(Debug, Default, Clone)]
+pub struct Metrics {
+    searches: u64,
+    nodes: u64,
+    depth_sum: u64,
+    nps: u64,
+    hashfull: u16,
+    games_in_progress: u64,
+    uci_errors: u64,
+    search_panics: u64,
+
+    current: Option<(u8, u64)>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ErrorKind {
+    Uci,
+    SearchPanic,
+}
+
+impl Metrics {
+    pub const fn search_summary(&mut self, depth: u8, nodes: u64, nps: u64, hashfull: u16) {
+        self.current = Some((depth, nodes));
+        self.nps = nps;
+        self.hashfull = hashfull;
+    }
+
+    pub fn search_finished(&mut self) {
+        let (depth, nodes) = self.current.take().unwrap_or_default();
+
+        self.searches += 1;
+        self.nodes += nodes;
+        self.depth_sum += u64::from(depth);
+    }
+
+    pub const fn set_games_in_progress(&mut self, games: u64) {
+        self.games_in_progress = games;
+    }
+
+    pub const fn error(&mut self, kind: ErrorKind) {
+        match kind {
+            ErrorKind::Uci => self.uci_errors += 1,
+            ErrorKind::SearchPanic => self.search_panics += 1,
+        }
+    }
+
+    #[must_use]
+    pub fn render(&self) -> String {
+        #[allow(clippy::cast_precision_loss)]
+        let average_depth = if self.searches == 0 {
+            0.0
+        } else {
+            self.depth_sum as f64 / self.searches as f64
+        };
+
+        let mut text = String::new();
+
+        let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, String)]| {
+            let _ = writeln!(text, "# HELP eccat_{name} {help}");
+            let _ = writeln!(text, "# TYPE eccat_{name} {kind}");
+
+            for (labels, value) in samples {
+                let _ = writeln!(text, "eccat_{name}{labels} {value}");
+            }
+        };
+
+        metric(
+            "searches_total",
+            "counter",
+            "Searches that sent a bestmove.",
+            &[("", self.searches.to_string())],
+        );
+        metric(
+            "nodes_total",
+            "counter",
+            "Nodes searched by finished searches.",
+            &[("", self.nodes.to_string())],
+        );
+        metric(
+            "nodes_per_second",
+            "gauge",
+            "Speed of the most recent search.",
+            &[("", self.nps.to_string())],
+        );
+        metric(
+            "search_depth_average",
+            "gauge",
+            "Average depth reached by finished searches.",
+            &[("", format!("{average_depth:.2}"))],
+        );
+        metric(
+            "hashfull_permille",
+            "gauge",
+            "Transposition table fill in permille.",
+            &[("", self.hashfull.to_string())],
+        );
+        metric(
+            "games_in_progress",
+            "gauge",
+            "Games currently being played.",
+            &[("", self.games_in_progress.to_string())],
+        );
+        metric(
+            "errors_total",
+            "counter",
+            "Errors by where they happened.",
+            &[
+                ("{kind=\"uci\"}", self.uci_errors.to_string()),
+                ("{kind=\"search_panic\"}", self.search_panics.to_string()),
+            ],
+        );
+
+        text
+    }
+}
+
+/// Every request gets the metrics, whatever its path.
+#[derive(Debug)]
+pub struct MetricsServer {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    pub fn start(address: &str, metrics: Arc<Mutex<Metrics>>) -> Result<Self, String> {
+        let listener = TcpListener::bind(address)
+            .map_err(|err| format!("could not listen on {address}: {err}"))?;
+
+        // polled, so the server can notice it should stop
+        listener
+            .set_nonblocking(true)
+            .map_err(|err| err.to_string())?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stop_flag = Arc::clone(&stop);
+
+        let handle = std::thread::spawn(move || {
+            while !stop_flag.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let body = metrics.lock().unwrap().render();
+                        let _ = respond(stream, &body);
+                    }
+                    Err(_) => std::thread::sleep(POLL_INTERVAL),
+                }
+            }
+        });
+
+        Ok(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for MetricsServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+fn respond(mut stream: TcpStream, body: &str) -> std::io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    // the request itself doesn't matter, but it has to be read before
+    // answering or some clients see the connection reset
+    let mut request = [0; 1024];
+    let _ = stream.read(&mut request)?;
+
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::default();
+
+        metrics.search_summary(4, 100, 1000, 5);
+        metrics.search_summary(6, 300, 2000, 7);
+        metrics.search_finished();
+        metrics.search_summary(8, 500, 3000, 9);
+        metrics.search_finished();
+        metrics.error(ErrorKind::SearchPanic);
+
+        let text = metrics.render();
+
+        assert!(text.contains("eccat_searches_total 2\n"));
+        assert!(text.contains("eccat_nodes_total 800\n"));
+        assert!(text.contains("eccat_nodes_per_second 3000\n"));
+        assert!(text.contains("eccat_search_depth_average 7.00\n"));
+        assert!(text.contains("eccat_hashfull_permille 9\n"));
+        assert!(text.contains("eccat_errors_total{kind=\"search_panic\"} 1\n"));
+        assert!(text.contains("eccat_errors_total{kind=\"uci\"} 0\n"));
+    }
+}
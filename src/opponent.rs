@@ -0,0 +1,101 @@
+use crate::evaluate::Eval;
+
+pub const ENGINE_RATING: i64 = 2400;
+
+/// GUIs send it as `<title> <rating> <computer|human> <name>`, with `none`
+/// for unknown fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Opponent {
+    pub title: Option<String>,
+    pub rating: Option<i64>,
+    pub computer: bool,
+    pub name: String,
+}
+
+impl Opponent {
+    #[must_use]
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut fields = value.split_whitespace();
+
+        let title = match fields.next()? {
+            "none" => None,
+            title => Some(title.to_string()),
+        };
+
+        let rating = match fields.next()? {
+            "none" => None,
+            rating => Some(rating.parse().ok()?),
+        };
+
+        let computer = match fields.next()? {
+            "computer" => true,
+            "human" => false,
+            _ => return None,
+        };
+
+        let name = fields.collect::<Vec<_>>().join(" ");
+
+        Some(Self {
+            title,
+            rating,
+            computer,
+            name,
+        })
+    }
+
+    /// Positive when we're the stronger side and should avoid draws.
+    #[must_use]
+    pub fn contempt(&self) -> Eval {
+        self.rating_difference().map_or(0, |diff| {
+            Eval::try_from((diff / 10).clamp(-50, 50)).unwrap_or_default()
+        })
+    }
+
+    #[must_use]
+    pub fn time_scale(&self) -> i64 {
+        self.rating_difference()
+            .map_or(100, |diff| (100 - diff / 20).clamp(75, 125))
+    }
+
+    fn rating_difference(&self) -> Option<i64> {
+        self.rating.map(|rating| ENGINE_RATING - rating)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Opponent::parse("GM 2800 human Gary Kasparov"),
+            Some(Opponent {
+                title: Some("GM".to_string()),
+                rating: Some(2800),
+                computer: false,
+                name: "Gary Kasparov".to_string(),
+            })
+        );
+
+        let unknown = Opponent::parse("none none computer Shredder").unwrap();
+
+        assert_eq!(unknown.rating, None);
+        assert!(unknown.computer);
+        assert_eq!(unknown.contempt(), 0);
+        assert_eq!(unknown.time_scale(), 100);
+
+        assert_eq!(Opponent::parse("GM lots human Someone"), None);
+    }
+
+    #[test]
+    fn test_scaling() {
+        let weaker = Opponent::parse("none 1800 human Someone").unwrap();
+        let stronger = Opponent::parse("none 3000 computer Something").unwrap();
+
+        assert_eq!(weaker.contempt(), 50);
+        assert_eq!(weaker.time_scale(), 75);
+        assert_eq!(stronger.contempt(), -50);
+        assert_eq!(stronger.time_scale(), 125);
+    }
+}
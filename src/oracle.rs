@@ -1,9 +1,36 @@
+use crate::search::Bound;
 use cozy_chess::{BitBoard, Board, Color, Piece};
 
 pub struct Oracle {}
 
 impl Oracle {
-    pub fn is_draw(board: &Board) -> bool {
+    /// [`Bound::Exact`] for a dead draw, [`Bound::Upper`] if the side to move
+    /// can't win and [`Bound::Lower`] if it can't lose.
+    pub fn bound(board: &Board) -> Option<Bound> {
+        if Self::is_dead_draw(board) {
+            return Some(Bound::Exact);
+        }
+
+        let us = board.side_to_move();
+
+        match (Self::can_win(board, us), Self::can_win(board, !us)) {
+            (false, true) => Some(Bound::Upper),
+            (true, false) => Some(Bound::Lower),
+            _ => None,
+        }
+    }
+
+    /// Without pawns and with at most one minor piece a side can't force mate.
+    fn can_win(board: &Board, color: Color) -> bool {
+        let ours = board.colors(color);
+        let heavy =
+            board.pieces(Piece::Pawn) | board.pieces(Piece::Rook) | board.pieces(Piece::Queen);
+        let minors = board.pieces(Piece::Knight) | board.pieces(Piece::Bishop);
+
+        !(ours & heavy).is_empty() || (ours & minors).len() > 1
+    }
+
+    fn is_dead_draw(board: &Board) -> bool {
         let all_pieces = board.occupied();
         let kings = board.pieces(Piece::King);
         let knights = board.pieces(Piece::Knight);
@@ -47,3 +74,33 @@ impl Oracle {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bound(fen: &str) -> Option<Bound> {
+        Oracle::bound(&fen.parse().unwrap())
+    }
+
+    #[test]
+    fn test_bound() {
+        // king and knight against king
+        assert_eq!(bound("8/8/8/4k3/8/8/8/4KN2 w - - 0 1"), Some(Bound::Exact));
+
+        // the bishop can only hold the pawn, whoever is to move
+        assert_eq!(
+            bound("8/8/8/4k3/8/3p4/8/4KB2 w - - 0 1"),
+            Some(Bound::Upper)
+        );
+        assert_eq!(
+            bound("8/8/8/4k3/8/3p4/8/4KB2 b - - 0 1"),
+            Some(Bound::Lower)
+        );
+
+        // a rook is enough to win with
+        assert_eq!(bound("8/8/8/4k3/8/8/8/4KR2 w - - 0 1"), Some(Bound::Lower));
+        assert_eq!(bound("8/8/8/4k3/8/3p4/8/4KR2 w - - 0 1"), None);
+        assert_eq!(bound("8/8/8/4k3/8/8/8/3NKB2 b - - 0 1"), Some(Bound::Upper));
+    }
+}
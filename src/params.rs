@@ -0,0 +1,222 @@
+use crate::evaluate::Eval;
+use core::fmt::Write as _;
+
+pub const SPSA_LEARNING_RATE: f64 = 0.002;
+
+/// Fractions are in hundredths, since spin options only take integers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Param {
+    RfpMargin,
+    FutilityMargin1,
+    FutilityMargin2,
+    /// Reductions are `base + ln(depth) * ln(moves) / divisor`.
+    LmrBase,
+    LmrDivisor,
+    /// The clock is split over this many moves without `movestogo`.
+    TimeDivisor,
+    SoftTimePercent,
+    HardTimePercent,
+    AspirationWindow,
+}
+
+impl Param {
+    pub const ALL: [Self; 9] = [
+        Self::RfpMargin,
+        Self::FutilityMargin1,
+        Self::FutilityMargin2,
+        Self::LmrBase,
+        Self::LmrDivisor,
+        Self::TimeDivisor,
+        Self::SoftTimePercent,
+        Self::HardTimePercent,
+        Self::AspirationWindow,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::RfpMargin => "RfpMargin",
+            Self::FutilityMargin1 => "FutilityMargin1",
+            Self::FutilityMargin2 => "FutilityMargin2",
+            Self::LmrBase => "LmrBase",
+            Self::LmrDivisor => "LmrDivisor",
+            Self::TimeDivisor => "TimeDivisor",
+            Self::SoftTimePercent => "SoftTimePercent",
+            Self::HardTimePercent => "HardTimePercent",
+            Self::AspirationWindow => "AspirationWindow",
+        }
+    }
+
+    #[must_use]
+    pub const fn default(self) -> i32 {
+        match self {
+            Self::RfpMargin => 30,
+            Self::FutilityMargin1 => 293,
+            Self::FutilityMargin2 => 620,
+            Self::LmrBase => 75,
+            Self::LmrDivisor => 225,
+            Self::TimeDivisor => 20,
+            Self::SoftTimePercent => 60,
+            Self::HardTimePercent => 200,
+            Self::AspirationWindow => 25,
+        }
+    }
+
+    #[must_use]
+    pub const fn range(self) -> (i32, i32) {
+        match self {
+            Self::RfpMargin | Self::LmrBase => (0, 200),
+            Self::FutilityMargin1 => (0, 1000),
+            Self::FutilityMargin2 => (0, 1500),
+            Self::LmrDivisor | Self::HardTimePercent => (100, 500),
+            Self::TimeDivisor => (5, 60),
+            Self::SoftTimePercent => (10, 100),
+            Self::AspirationWindow => (5, 200),
+        }
+    }
+
+    /// A twentieth of the range.
+    #[must_use]
+    pub const fn step(self) -> i32 {
+        let (min, max) = self.range();
+
+        let step = (max - min) / 20;
+
+        if step > 1 {
+            step
+        } else {
+            1
+        }
+    }
+
+    #[must_use]
+    pub fn find(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|param| param.name().eq_ignore_ascii_case(name))
+    }
+}
+
+#[derive(Debug)]
+pub struct SearchParams {
+    values: [i32; Param::ALL.len()],
+    /// By `[depth][move index]`, rebuilt when its parameters change.
+    lmr_table: Box<[[u8; 64]; 64]>,
+}
+
+impl SearchParams {
+    #[must_use]
+    pub const fn get(&self, param: Param) -> i32 {
+        self.values[param as usize]
+    }
+
+    pub fn set(&mut self, param: Param, value: i32) {
+        let (min, max) = param.range();
+
+        self.values[param as usize] = value.clamp(min, max);
+
+        if matches!(param, Param::LmrBase | Param::LmrDivisor) {
+            self.lmr_table = lmr_table(self.get(Param::LmrBase), self.get(Param::LmrDivisor));
+        }
+    }
+
+    #[must_use]
+    pub fn eval(&self, param: Param) -> Eval {
+        Eval::try_from(self.get(param)).unwrap_or(Eval::MAX)
+    }
+
+    #[must_use]
+    pub fn lmr(&self, depth: u8, move_idx: usize) -> u8 {
+        self.lmr_table[usize::from(depth.min(63))][move_idx.min(63)]
+    }
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        let values = Param::ALL.map(Param::default);
+
+        Self {
+            values,
+            lmr_table: lmr_table(
+                values[Param::LmrBase as usize],
+                values[Param::LmrDivisor as usize],
+            ),
+        }
+    }
+}
+
+/// As the SPSA page of `OpenBench` takes them.
+#[must_use]
+pub fn spsa_config() -> String {
+    Param::ALL.iter().fold(String::new(), |mut config, param| {
+        let (min, max) = param.range();
+
+        let _ = writeln!(
+            config,
+            "{}, int, {}, {min}, {max}, {}, {SPSA_LEARNING_RATE}",
+            param.name(),
+            param.default(),
+            param.step()
+        );
+
+        config
+    })
+}
+
+fn lmr_table(base: i32, divisor: i32) -> Box<[[u8; 64]; 64]> {
+    let mut table = Box::new([[0; 64]; 64]);
+
+    let base = f64::from(base) / 100.0;
+    let divisor = f64::from(divisor) / 100.0;
+
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (move_idx, reduction) in row.iter_mut().enumerate().skip(1) {
+            #[allow(
+                clippy::cast_precision_loss,
+                clippy::cast_possible_truncation,
+                clippy::cast_sign_loss
+            )]
+            {
+                *reduction = (base + (depth as f64).ln() * (move_idx as f64).ln() / divisor) as u8;
+            }
+        }
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_params() {
+        let mut params = SearchParams::default();
+
+        assert_eq!(params.get(Param::RfpMargin), 30);
+        assert_eq!(params.lmr(1, 30), 0);
+        assert_eq!(params.lmr(20, 30), 5);
+
+        // a smaller divisor reduces more
+        params.set(Param::LmrDivisor, 150);
+        assert_eq!(params.lmr(20, 30), 7);
+
+        params.set(Param::AspirationWindow, 10_000);
+        assert_eq!(params.get(Param::AspirationWindow), 200);
+
+        assert_eq!(Param::find("lmrdivisor"), Some(Param::LmrDivisor));
+        assert_eq!(Param::find("Hash"), None);
+    }
+
+    #[test]
+    fn test_spsa_config() {
+        let config = spsa_config();
+
+        assert_eq!(config.lines().count(), Param::ALL.len());
+        assert_eq!(
+            config.lines().next(),
+            Some("RfpMargin, int, 30, 0, 200, 10, 0.002")
+        );
+        assert!(config.contains("TimeDivisor, int, 20, 5, 60, 2, 0.002\n"));
+    }
+}
@@ -0,0 +1,82 @@
+use cozy_chess::{Board, Move};
+
+/// The last ply is counted from the generated moves instead of being played.
+#[must_use]
+pub fn perft(board: &Board, depth: u8) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let mut nodes = 0;
+
+    board.generate_moves(|mvs| {
+        if depth == 1 {
+            nodes += mvs.len() as u64;
+        } else {
+            for mv in mvs {
+                let mut child = board.clone();
+                child.play_unchecked(mv);
+
+                nodes += perft(&child, depth - 1);
+            }
+        }
+
+        false
+    });
+
+    nodes
+}
+
+#[must_use]
+pub fn divide(board: &Board, depth: u8) -> Vec<(Move, u64)> {
+    let mut moves = Vec::new();
+
+    board.generate_moves(|mvs| {
+        moves.extend(mvs);
+        false
+    });
+
+    moves
+        .into_iter()
+        .map(|mv| {
+            let mut child = board.clone();
+            child.play_unchecked(mv);
+
+            (mv, perft(&child, depth.saturating_sub(1)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perft_startpos() {
+        let board = Board::default();
+
+        assert_eq!(perft(&board, 0), 1);
+        assert_eq!(perft(&board, 1), 20);
+        assert_eq!(perft(&board, 2), 400);
+        assert_eq!(perft(&board, 3), 8902);
+    }
+
+    #[test]
+    fn test_perft_kiwipete() {
+        let board = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"
+            .parse::<Board>()
+            .unwrap();
+
+        assert_eq!(perft(&board, 3), 97862);
+    }
+
+    #[test]
+    fn test_divide_sums_to_perft() {
+        let board = Board::default();
+
+        let divided = divide(&board, 3);
+
+        assert_eq!(divided.len(), 20);
+        assert_eq!(divided.iter().map(|(_, nodes)| nodes).sum::<u64>(), 8902);
+    }
+}
@@ -0,0 +1,147 @@
+use crate::evaluate::{phase_weight, piece_square, Eval};
+use cozy_chess::{Board, Color, File, Move, Piece, Square};
+
+/// The evaluation terms that only depend on which pieces stand where are
+/// updated move by move.
+#[derive(Debug, Clone)]
+pub struct Position {
+    board: Board,
+    mg: Eval,
+    eg: Eval,
+    phase: i16,
+    piece_counts: [[u8; 6]; 2],
+}
+
+impl Position {
+    #[must_use]
+    pub fn new(board: Board) -> Self {
+        let mut position = Self {
+            board,
+            mg: 0,
+            eg: 0,
+            phase: 0,
+            piece_counts: [[0; 6]; 2],
+        };
+
+        for colour in Color::ALL {
+            for piece in Piece::ALL {
+                for square in position.board.colored_pieces(colour, piece) {
+                    position.add(colour, piece, square);
+                }
+            }
+        }
+
+        position
+    }
+
+    pub const fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// From white's point of view.
+    pub const fn piece_square_score(&self) -> (Eval, Eval) {
+        (self.mg, self.eg)
+    }
+
+    /// 0 for bare kings and 24 or more for the starting material.
+    pub const fn phase(&self) -> i16 {
+        self.phase
+    }
+
+    pub const fn count(&self, colour: Color, piece: Piece) -> u8 {
+        self.piece_counts[colour as usize][piece as usize]
+    }
+
+    pub fn play_null(&mut self) {
+        self.board = self.board.null_move().expect("null move while in check");
+    }
+
+    pub fn play_unchecked(&mut self, mv: Move) {
+        let board = &self.board;
+
+        let colour = board.side_to_move();
+        let piece = board.piece_on(mv.from).unwrap();
+
+        if board.colors(colour).has(mv.to) {
+            // castling is encoded as the king capturing its own rook
+            let rank = mv.from.rank();
+
+            let (king_file, rook_file) = if mv.to.file() > mv.from.file() {
+                (File::G, File::F)
+            } else {
+                (File::C, File::D)
+            };
+
+            self.remove(colour, Piece::King, mv.from);
+            self.remove(colour, Piece::Rook, mv.to);
+            self.add(colour, Piece::King, Square::new(king_file, rank));
+            self.add(colour, Piece::Rook, Square::new(rook_file, rank));
+        } else {
+            if let Some(victim) = board.piece_on(mv.to) {
+                self.remove(!colour, victim, mv.to);
+            } else if piece == Piece::Pawn && mv.from.file() != mv.to.file() {
+                self.remove(
+                    !colour,
+                    Piece::Pawn,
+                    Square::new(mv.to.file(), mv.from.rank()),
+                );
+            }
+
+            self.remove(colour, piece, mv.from);
+            self.add(colour, mv.promotion.unwrap_or(piece), mv.to);
+        }
+
+        self.board.play_unchecked(mv);
+    }
+
+    const fn add(&mut self, colour: Color, piece: Piece, square: Square) {
+        self.update_scores(colour, piece, square, 1);
+        self.piece_counts[colour as usize][piece as usize] += 1;
+    }
+
+    const fn remove(&mut self, colour: Color, piece: Piece, square: Square) {
+        self.update_scores(colour, piece, square, -1);
+        self.piece_counts[colour as usize][piece as usize] -= 1;
+    }
+
+    const fn update_scores(&mut self, colour: Color, piece: Piece, square: Square, sign: i16) {
+        let (mg, eg) = piece_square(piece, colour, square);
+
+        let colour_sign = match colour {
+            Color::White => sign,
+            Color::Black => -sign,
+        };
+
+        self.mg += mg * colour_sign;
+        self.eg += eg * colour_sign;
+        self.phase += phase_weight(piece) * sign;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_matches_fresh() {
+        let mut position = Position::new(
+            "r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1"
+                .parse()
+                .unwrap(),
+        );
+
+        for mv in ["e5d6", "e8g8", "b7a8q", "f8d8", "e1g1", "g8g7"] {
+            let mv = cozy_chess::util::parse_uci_move(position.board(), mv).unwrap();
+
+            assert!(position.board().is_legal(mv));
+
+            position.play_unchecked(mv);
+
+            let fresh = Position::new(position.board().clone());
+
+            assert_eq!(position.piece_square_score(), fresh.piece_square_score());
+            assert_eq!(position.phase(), fresh.phase());
+            assert_eq!(position.piece_counts, fresh.piece_counts);
+        }
+    }
+}
@@ -0,0 +1,172 @@
+use cozy_chess::{Board, GameStatus};
+
+pub const DEFAULT_PLIES: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    #[must_use]
+    pub const fn new(seed: u64) -> Self {
+        // xorshift never leaves zero
+        Self(if seed == 0 {
+            0x9e37_79b9_7f4a_7c15
+        } else {
+            seed
+        })
+    }
+
+    pub const fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    pub const fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+/// Numbered by their Scharnagl number, with 518 the standard one.
+pub const CHESS960_POSITIONS: u32 = 960;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Start {
+    Standard,
+    Chess960,
+    Dfrc,
+}
+
+impl Start {
+    #[must_use]
+    pub fn board(self, rng: &mut Rng) -> Board {
+        let mut index = || u32::try_from(rng.below(CHESS960_POSITIONS as usize)).unwrap();
+
+        match self {
+            Self::Standard => Board::default(),
+            Self::Chess960 => Board::chess960_startpos(index()),
+            Self::Dfrc => Board::double_chess960_startpos(index(), index()),
+        }
+    }
+}
+
+#[must_use]
+pub fn chess960_board(index: u32) -> Option<Board> {
+    (index < CHESS960_POSITIONS).then(|| Board::chess960_startpos(index))
+}
+
+#[must_use]
+pub fn dfrc_board(white: u32, black: u32) -> Option<Board> {
+    (white < CHESS960_POSITIONS && black < CHESS960_POSITIONS)
+        .then(|| Board::double_chess960_startpos(white, black))
+}
+
+/// Tries again whenever the game ends on the way.
+#[must_use]
+pub fn random_board(rng: &mut Rng, plies: usize) -> Board {
+    random_board_from(rng, Start::Standard, plies)
+}
+
+#[must_use]
+pub fn random_board_from(rng: &mut Rng, start: Start, plies: usize) -> Board {
+    'retry: loop {
+        let mut board = start.board(rng);
+
+        for _ in 0..plies {
+            let mut moves = Vec::new();
+
+            board.generate_moves(|piece_moves| {
+                moves.extend(piece_moves);
+                false
+            });
+
+            if moves.is_empty() {
+                continue 'retry;
+            }
+
+            board.play_unchecked(moves[rng.below(moves.len())]);
+        }
+
+        if board.status() == GameStatus::Ongoing {
+            return board;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cozy_chess::{Color, File, Piece, Rank, Square};
+    use std::collections::HashSet;
+
+    fn back_rank(board: &Board) -> String {
+        File::ALL
+            .into_iter()
+            .map(|file| {
+                let piece = board.piece_on(Square::new(file, Rank::First)).unwrap();
+                char::from(piece).to_ascii_uppercase()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_chess960() {
+        assert_eq!(chess960_board(518).unwrap(), Board::default());
+        assert_eq!(back_rank(&chess960_board(0).unwrap()), "BBQNNRKR");
+        assert_eq!(back_rank(&chess960_board(959).unwrap()), "RKRNNQBB");
+        assert!(chess960_board(960).is_none());
+
+        let board = chess960_board(0).unwrap();
+
+        assert_eq!(
+            format!("{board:#}"),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFhf - 0 1"
+        );
+
+        let mut back_ranks = HashSet::new();
+
+        for index in 0..CHESS960_POSITIONS {
+            let board = chess960_board(index).unwrap();
+            let king = board.king(Color::White).file();
+            let rooks = board.colored_pieces(Color::White, Piece::Rook);
+            let bishops = board.colored_pieces(Color::White, Piece::Bishop);
+
+            // the king between its rooks, and a bishop on each colour
+            assert!(rooks.into_iter().any(|rook| rook.file() < king));
+            assert!(rooks.into_iter().any(|rook| rook.file() > king));
+            assert_eq!(
+                bishops
+                    .into_iter()
+                    .map(|bishop| bishop.file() as usize % 2)
+                    .sum::<usize>(),
+                1
+            );
+
+            let rights = board.castle_rights(Color::White);
+            assert!(rights.short.is_some() && rights.long.is_some());
+
+            back_ranks.insert(back_rank(&board));
+        }
+
+        assert_eq!(back_ranks.len(), 960);
+    }
+
+    #[test]
+    fn test_dfrc() {
+        let board = dfrc_board(0, 959).unwrap();
+
+        assert_eq!(
+            format!("{board:#}"),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w HFca - 0 1"
+        );
+        assert!(dfrc_board(0, 960).is_none());
+
+        let mut rng = Rng::new(7);
+        let board = random_board_from(&mut rng, Start::Dfrc, 10);
+
+        assert_eq!(board.fullmove_number(), 6);
+        assert_eq!(board.status(), GameStatus::Ongoing);
+    }
+}
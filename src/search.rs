@@ -1,49 +1,117 @@
 use crate::{
-    evaluate::{evaluate, Eval, EVAL_INFINITY},
+    eval_cache::EvalCache,
+    evaluate::{evaluate, Eval, EVAL_INFINITY, MATE_BOUND, TB_WIN},
+    lichess_tb::{LichessTablebase, Wdl, MAX_REQUEST_TIME},
     oracle::Oracle,
+    params::{Param, SearchParams},
+    position::Position,
     see,
-    tt::{Entry, Flag, TranspositionTable},
+    tt::{Entry, Flag, TableHandle, TranspositionTable},
     uci::{convert_move_to_uci, GameTime},
     EngineReport,
 };
 use arrayvec::ArrayVec;
 use chrono::Duration;
-use cozy_chess::{Board, Color, Move, Piece};
-use crossbeam_channel::{Receiver, Sender};
-use std::{
-    sync::{Arc, Mutex},
-    thread::JoinHandle,
-    time::Instant,
-};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::{cmp::Reverse, panic::AssertUnwindSafe};
+use cozy_chess::{Board, Color, GameStatus, Move, Piece, Square};
+use crossbeam_channel::{Receiver, Select, Sender, TrySendError};
+use std::{collections::VecDeque, sync::Arc, thread::JoinHandle, time::Instant};
 
+#[derive(Debug)]
 pub enum EngineToSearch {
-    Start(SearchMode),
+    Start {
+        limits: SearchLimits,
+        board: Box<Board>,
+        history: Vec<History>,
+    },
+    /// Analyses `board` until stopped, without touching the engine's position.
+    Think {
+        board: Box<Board>,
+        history: Vec<History>,
+    },
+    /// The ponder move was played, so the search carries on under these limits.
+    ThinkHit(SearchLimits),
     Stop,
     Quit,
 
     SetHash(usize),
     ClearHash,
+    SetOnlineTablebase(bool),
+    SetTimeUsage(u16),
+    SetMoveOverhead(Duration),
+    SetThreads(usize),
+    SetContempt(Eval),
+    SetEvalNoise {
+        amount: Eval,
+        seed: u64,
+    },
+    SetDeterministic(bool),
+    #[cfg_attr(not(feature = "tune"), allow(dead_code))]
+    SetParam(Param, i32),
+    GetHeuristics(Sender<Heuristics>),
+    SetHeuristics(Box<Heuristics>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+impl Bound {
+    #[must_use]
+    pub const fn new(score: Eval, alpha: Eval, beta: Eval) -> Self {
+        if score <= alpha {
+            Self::Upper
+        } else if score >= beta {
+            Self::Lower
+        } else {
+            Self::Exact
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum SearchToEngine {
-    BestMove(String),
+    BestMove {
+        mv: String,
+        ponder: Option<String>,
+    },
     Summary {
         depth: u8,
         seldepth: u8,
         time: Duration,
         cp: Eval,
+        bound: Bound,
         nodes: u64,
         nps: u64,
         hashfull: u16,
         pv: Vec<String>,
     },
+    CurrentMove {
+        depth: u8,
+        mv: String,
+        number: usize,
+    },
+    InfoString(String),
+    Panicked(String),
 }
 
 #[derive(Debug, Default)]
 pub struct Search {
     handle: Option<JoinHandle<()>>,
     control_tx: Option<Sender<EngineToSearch>>,
+    shared: Option<SharedState>,
+}
+
+#[derive(Clone, Debug)]
+struct SharedState {
+    report_tx: Sender<EngineReport>,
+    transposition_table: TableHandle,
+    /// Other engines search with the same table, so it is never cleared.
+    hash_is_shared: bool,
 }
 
 impl Search {
@@ -55,69 +123,64 @@ impl Search {
     pub fn init(
         &mut self,
         report_tx: Sender<EngineReport>,
-        board: Arc<Mutex<Board>>,
-        history: Arc<Mutex<Vec<History>>>,
-        transposition_table: Arc<Mutex<TranspositionTable>>,
+        transposition_table: TableHandle,
+        hash_is_shared: bool,
     ) {
-        let (control_tx, control_rx) = crossbeam_channel::unbounded();
+        self.shared = Some(SharedState {
+            report_tx,
+            transposition_table,
+            hash_is_shared,
+        });
 
-        let handle = std::thread::spawn(move || {
-            let mut quit = false;
-            let mut halt = true;
-
-            while !quit {
-                let cmd = control_rx.recv().unwrap();
-
-                let mut search_mode = None;
-
-                match cmd {
-                    EngineToSearch::Start(sm) => {
-                        search_mode = Some(sm);
-                        halt = false;
-                    }
-                    EngineToSearch::Stop => halt = true,
-                    EngineToSearch::Quit => quit = true,
-                    EngineToSearch::SetHash(size) => {
-                        transposition_table.lock().unwrap().resize(size);
-                        halt = true;
-                    }
-                    EngineToSearch::ClearHash => {
-                        transposition_table.lock().unwrap().clear();
-                        halt = true;
-                    }
-                }
+        self.spawn();
+    }
 
-                if !halt && !quit {
-                    let mut refs = SearchRefs {
-                        board: &mut board.lock().unwrap(),
-                        control_rx: &control_rx,
-                        report_tx: &report_tx,
-                        search_mode: &search_mode.unwrap(),
-                        search_state: &mut SearchState::default(),
-                        history: &mut history.lock().unwrap(),
-                        transposition_table: &mut transposition_table.lock().unwrap(),
-                    };
-
-                    let (best_move, terminate) = iterative_deepening(&mut refs);
-
-                    let report = SearchToEngine::BestMove(
-                        convert_move_to_uci(refs.board, best_move).to_string(),
-                    );
-
-                    report_tx.send(EngineReport::Search(report)).unwrap();
-
-                    if let Some(terminate) = terminate {
-                        match terminate {
-                            SearchTerminate::Stop => {
-                                halt = true;
-                            }
-                            SearchTerminate::Quit => {
-                                halt = true;
-                                quit = true;
-                            }
-                        }
-                    }
-                }
+    /// The hash table is cleared unless it's shared, and the engine has to send
+    /// its settings again.
+    pub fn respawn(&mut self) {
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+
+        if let Some(shared) = &self.shared {
+            // the dead thread may have been holding it
+            shared.transposition_table.clear_poison();
+
+            if !shared.hash_is_shared {
+                shared.transposition_table.read().unwrap().clear();
+            }
+        }
+
+        self.spawn();
+    }
+
+    fn spawn(&mut self) {
+        let Some(shared) = self.shared.clone() else {
+            return;
+        };
+
+        let (control_tx, control_rx) = crossbeam_channel::bounded(CONTROL_CAPACITY);
+
+        let handle = std::thread::spawn(move || {
+            let report_tx = shared.report_tx.clone();
+
+            let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+                search_thread(&control_rx, shared);
+            }));
+
+            if let Err(payload) = result {
+                let cause = payload
+                    .downcast_ref::<&str>()
+                    .map(ToString::to_string)
+                    .or_else(|| payload.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown cause".to_string());
+
+                send_draining(
+                    &report_tx,
+                    &control_rx,
+                    EngineReport::Search(SearchToEngine::Panicked(cause)),
+                    &mut VecDeque::new(),
+                );
             }
         });
 
@@ -125,597 +188,2465 @@ impl Search {
         self.control_tx = Some(control_tx);
     }
 
-    pub fn send(
-        &self,
-        cmd: EngineToSearch,
-    ) -> Result<(), crossbeam_channel::SendError<EngineToSearch>> {
-        if let Some(tx) = &self.control_tx {
-            tx.send(cmd)?;
+    pub fn shutdown(&mut self) -> bool {
+        if let Some(tx) = self.control_tx.take() {
+            let _ = tx.send(EngineToSearch::Quit);
         }
 
-        Ok(())
+        self.handle.take().is_none_or(crate::join_timeout)
     }
-}
 
-fn iterative_deepening(refs: &mut SearchRefs) -> (Move, Option<SearchTerminate>) {
-    let mut best_move = None;
-    let mut root_pv = Vec::new();
-    let mut depth = 1;
-    let mut stop = false;
+    pub fn heuristics(&self) -> Option<Heuristics> {
+        let (reply_tx, reply_rx) = crossbeam_channel::bounded(1);
 
-    if let SearchMode::GameTime(gametime) = &refs.search_mode {
-        let (clock, increment) = match refs.board.side_to_move() {
-            Color::White => (gametime.white_time, gametime.white_increment),
-            Color::Black => (gametime.black_time, gametime.black_increment),
-        };
+        self.send(EngineToSearch::GetHeuristics(reply_tx));
 
-        let time = gametime.moves_to_go.map_or_else(
-            || clock / 20,
-            |mtg| {
-                if mtg == 0 {
-                    clock
-                } else {
-                    clock / i32::from(mtg)
+        reply_rx.recv().ok()
+    }
+
+    /// Commands to a thread that has died are dropped until it's respawned.
+    pub fn send(&self, cmd: EngineToSearch) {
+        if let Some(tx) = &self.control_tx {
+            let _ = tx.send(cmd);
+        }
+    }
+}
+
+#[allow(clippy::too_many_lines)]
+fn search_thread(control_rx: &Receiver<EngineToSearch>, shared: SharedState) {
+    let SharedState {
+        report_tx,
+        transposition_table,
+        hash_is_shared,
+    } = shared;
+
+    let mut reporter = Reporter::new(report_tx);
+
+    let mut online_tablebase = None;
+    let mut options = SearchOptions::default();
+
+    // one per search thread, the main thread's first, kept from one search to
+    // the next so what the heuristics learned isn't thrown away
+    let mut thread_states = Vec::<SearchState>::new();
+
+    // settings that arrived mid-search, applied once it has finished
+    let mut deferred = VecDeque::new();
+
+    // ends once the engine is dropped, if it never sent `Quit`
+    while let Some(cmd) = deferred.pop_front().or_else(|| control_rx.recv().ok()) {
+        let root = match cmd {
+            EngineToSearch::Start {
+                limits,
+                board,
+                history,
+            } => Some((limits, *board, history)),
+            EngineToSearch::Think { board, history } => {
+                Some((SearchLimits::default(), *board, history))
+            }
+            // nothing to stop, the search already finished
+            EngineToSearch::Stop | EngineToSearch::ThinkHit(_) => None,
+            EngineToSearch::Quit => break,
+            EngineToSearch::SetHash(size) => {
+                let _span = tracing::debug_span!("tt_resize", mb_size = size).entered();
+
+                *transposition_table.write().unwrap() = Arc::new(TranspositionTable::new(size));
+                None
+            }
+            EngineToSearch::ClearHash => {
+                // the other engines' games aren't over
+                if !hash_is_shared {
+                    transposition_table.read().unwrap().clear();
                 }
-            },
-        );
+                // a new game, which the old one's history tables know nothing of
+                thread_states.clear();
+                None
+            }
+            EngineToSearch::SetOnlineTablebase(enabled) => {
+                online_tablebase = enabled.then(LichessTablebase::new);
+                None
+            }
+            EngineToSearch::GetHeuristics(reply_tx) => {
+                let heuristics = thread_states.first().map(SearchState::heuristics);
 
-        let time_slice = time + increment - Duration::milliseconds(100);
+                let _ = reply_tx.send(heuristics.unwrap_or_default());
+                None
+            }
+            EngineToSearch::SetHeuristics(heuristics) => {
+                thread_states.resize_with(thread_states.len().max(1), SearchState::default);
 
-        refs.search_state.allocated_time = time_slice.to_std().unwrap_or_default();
-    }
+                for state in &mut thread_states {
+                    state.set_heuristics(&heuristics);
+                }
+                None
+            }
+            cmd @ (EngineToSearch::SetMoveOverhead(_)
+            | EngineToSearch::SetTimeUsage(_)
+            | EngineToSearch::SetThreads(_)
+            | EngineToSearch::SetContempt(_)
+            | EngineToSearch::SetEvalNoise { .. }
+            | EngineToSearch::SetDeterministic(_)
+            | EngineToSearch::SetParam(..)) => {
+                options.set(&cmd);
+                None
+            }
+        };
 
-    refs.transposition_table.clear();
+        let Some((limits, root_board, history)) = root else {
+            continue;
+        };
 
-    refs.search_state.start_time = Some(Instant::now());
+        let probe = online_tablebase.as_mut().and_then(|tablebase| {
+            probe_online_tablebase(&mut reporter, &limits, &options, &root_board, tablebase)
+        });
 
-    while depth <= 128 && !stop {
-        refs.search_state.depth = depth;
+        thread_states.resize_with(options.threads, SearchState::default);
+        thread_states[0].new_search(limits);
+
+        let best_move = probe.map(|mv| (mv, None)).or_else(|| {
+            search_root(
+                &root_board,
+                &history,
+                &options,
+                &transposition_table,
+                &mut thread_states,
+                MainThread {
+                    control_rx,
+                    reporter: &mut reporter,
+                },
+            )
+        });
 
-        let eval = negamax(
-            refs,
-            &mut root_pv,
-            depth,
-            -EVAL_INFINITY,
-            EVAL_INFINITY,
-            NodeType::Root,
+        // every start gets exactly one bestmove, even one that was stopped
+        // straight away or has no legal moves
+        let report = best_move.map_or_else(
+            || SearchToEngine::BestMove {
+                mv: NO_MOVE.to_string(),
+                ponder: None,
+            },
+            |(best_move, ponder)| {
+                let mut after = root_board.clone();
+                after.play_unchecked(best_move);
+
+                SearchToEngine::BestMove {
+                    mv: convert_move_to_uci(&root_board, best_move).to_string(),
+                    ponder: ponder.map(|ponder| convert_move_to_uci(&after, ponder).to_string()),
+                }
+            },
         );
 
-        check_terminate(refs);
+        for cmd in thread_states[0].deferred.drain(..) {
+            defer(&mut deferred, cmd);
+        }
 
-        if refs.search_state.terminate.is_none() {
-            if !root_pv.is_empty() {
-                best_move = root_pv.first().copied();
-            }
+        reporter.send(report, control_rx, &mut deferred);
 
-            let elapsed = refs.search_state.start_time.unwrap().elapsed();
+        if matches!(thread_states[0].terminate, Some(SearchTerminate::Quit)) {
+            break;
+        }
+    }
+}
 
-            #[allow(
-                clippy::cast_precision_loss,
-                clippy::cast_possible_truncation,
-                clippy::cast_sign_loss
-            )]
-            let nps = (refs.search_state.nodes as f64 / elapsed.as_secs_f64()) as u64;
+fn search_root(
+    root_board: &Board,
+    history: &[History],
+    options: &SearchOptions,
+    transposition_table: &TableHandle,
+    thread_states: &mut [SearchState],
+    main: MainThread,
+) -> Option<(Move, Option<Move>)> {
+    let (search_state, helper_states) = thread_states
+        .split_first_mut()
+        .expect("there is always a main thread");
+
+    let mut moves = ArrayVec::<_, MAX_MOVES>::new();
+
+    root_board.generate_moves(|mvs| {
+        moves.extend(mvs);
+        false
+    });
 
-            let report = SearchToEngine::Summary {
-                depth,
-                seldepth: refs.search_state.seldepth,
-                time: Duration::from_std(elapsed).unwrap(),
-                cp: eval,
-                nodes: refs.search_state.nodes,
-                nps,
-                hashfull: refs.transposition_table.hashfull(),
-                pv: convert_pv_to_strings(&root_pv, refs.board.clone()),
-            };
+    // nothing to think about, and under any limit the time saved is worth
+    // more than a score for the only move. infinite and ponder searches are
+    // left to run until they're stopped
+    match moves[..] {
+        [] => {
+            main.reporter
+                .info(SearchToEngine::InfoString("no legal moves".to_string()));
 
-            refs.report_tx.send(EngineReport::Search(report)).unwrap();
+            return None;
+        }
+        [only] if !search_state.limits.is_infinite() => {
+            main.reporter.info(SearchToEngine::InfoString(
+                "only one legal move".to_string(),
+            ));
 
-            depth += 1;
+            return Some((only, None));
         }
+        _ => {}
+    }
 
-        let is_time_up = match refs.search_mode {
-            SearchMode::GameTime(_) => {
-                // probably cant finish the next depth in time,
-                // so if we're at 60% of the allocated time,
-                // we stop the search
-                refs.search_state.start_time.unwrap().elapsed()
-                    >= refs.search_state.allocated_time.mul_f32(0.6)
-            }
-            _ => false,
-        };
+    // the lock is only held while taking the table, so another engine
+    // sharing it can swap in its own without waiting for this search
+    let transposition_table = &*Arc::clone(&transposition_table.read().unwrap());
 
-        if is_time_up || refs.search_state.terminate.is_some() {
-            stop = true;
-        }
+    transposition_table.new_search();
+
+    // helpers would make the search depend on how the threads are scheduled
+    let threads = if options.deterministic {
+        1
+    } else {
+        options.threads
+    };
+
+    let smp = Smp::new(threads);
+
+    // the limits only restrict the helpers' root moves, the main thread
+    // decides when they stop
+    for helper_state in helper_states.iter_mut().take(threads - 1) {
+        helper_state.new_search(search_state.limits.clone());
     }
 
-    (
-        best_move.unwrap_or_else(|| first_legal_move(refs.board).unwrap()),
-        refs.search_state.terminate,
-    )
-}
+    // lazy smp: helpers search the same root and only share results through
+    // the hash table, which is enough to speed the main thread up
+    std::thread::scope(|scope| {
+        for (thread, search_state) in (1..threads).zip(helper_states) {
+            let smp = &smp;
+
+            scope.spawn(move || {
+                iterative_deepening(&mut SearchRefs {
+                    root_board,
+                    main: None,
+                    thread,
+                    smp,
+                    options,
+                    search_state,
+                    history,
+                    transposition_table,
+                })
+            });
+        }
 
-fn first_legal_move(board: &Board) -> Option<Move> {
-    let mut first_move = None;
+        let best_move = iterative_deepening(&mut SearchRefs {
+            root_board,
+            main: Some(main),
+            thread: 0,
+            smp: &smp,
+            options,
+            search_state,
+            history,
+            transposition_table,
+        });
 
-    board.generate_moves(|mvs| {
-        first_move = mvs.into_iter().next();
-        true
-    });
+        smp.stop.store(true, Ordering::Relaxed);
 
-    first_move
+        best_move
+    })
 }
 
-#[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
-fn negamax(
-    refs: &mut SearchRefs,
-    pv: &mut Vec<Move>,
-    mut depth: u8,
-    mut alpha: Eval,
-    mut beta: Eval,
-    node_type: NodeType,
-) -> Eval {
-    debug_assert!(alpha < beta);
+/// Info reports never wait: while the channel is full only the newest one is
+/// kept back. Anything else waits for room, taking commands off the control
+/// channel meanwhile so the engine can't deadlock with the search.
+#[derive(Debug)]
+struct Reporter {
+    report_tx: Sender<EngineReport>,
+    pending_info: Option<SearchToEngine>,
+}
 
-    if refs.search_state.nodes % 0x2000 == 0 {
-        check_terminate(refs);
+impl Reporter {
+    const fn new(report_tx: Sender<EngineReport>) -> Self {
+        Self {
+            report_tx,
+            pending_info: None,
+        }
     }
 
-    if refs.search_state.terminate.is_some() {
-        return 0;
-    }
+    fn info(&mut self, report: SearchToEngine) {
+        if let Some(pending) = self.pending_info.take() {
+            // if there's still no room the newer report replaces it
+            let _ = self.report_tx.try_send(EngineReport::Search(pending));
+        }
 
-    refs.search_state.nodes += 1;
+        if let Err(TrySendError::Full(EngineReport::Search(report))) =
+            self.report_tx.try_send(EngineReport::Search(report))
+        {
+            self.pending_info = Some(report);
+        }
+    }
 
-    let is_check = !refs.board.checkers().is_empty();
+    fn send(
+        &mut self,
+        report: SearchToEngine,
+        control_rx: &Receiver<EngineToSearch>,
+        deferred: &mut VecDeque<EngineToSearch>,
+    ) {
+        if let Some(pending) = self.pending_info.take() {
+            send_draining(
+                &self.report_tx,
+                control_rx,
+                EngineReport::Search(pending),
+                deferred,
+            );
+        }
 
-    if is_check {
-        depth += 1;
+        send_draining(
+            &self.report_tx,
+            control_rx,
+            EngineReport::Search(report),
+            deferred,
+        );
     }
+}
 
-    if depth == 0 {
-        return quiescence(refs, pv, alpha, beta);
-    }
+fn send_draining(
+    report_tx: &Sender<EngineReport>,
+    control_rx: &Receiver<EngineToSearch>,
+    report: EngineReport,
+    deferred: &mut VecDeque<EngineToSearch>,
+) {
+    let mut select = Select::new();
+    let send = select.send(report_tx);
+    select.recv(control_rx);
+
+    loop {
+        let oper = select.select();
+
+        if oper.index() == send {
+            let _ = oper.send(report_tx, report);
+            return;
+        }
 
-    let mut tt_value = None;
-    let mut tt_move = None;
+        let Ok(cmd) = oper.recv(control_rx) else {
+            // the engine is gone, nothing left to drain
+            let _ = report_tx.send(report);
+            return;
+        };
 
-    if let Some(data) = refs.transposition_table.probe(refs.board.hash()) {
-        let tt_result = data.get(depth, refs.search_state.ply, alpha, beta);
+        defer(deferred, cmd);
+    }
+}
 
-        tt_value = tt_result.0;
-        tt_move = tt_result.1;
+/// A newer command replaces a queued one of the same kind.
+fn defer(deferred: &mut VecDeque<EngineToSearch>, cmd: EngineToSearch) {
+    let same_kind = |older: &EngineToSearch| match (older, &cmd) {
+        (EngineToSearch::SetParam(older, _), EngineToSearch::SetParam(param, _)) => older == param,
+        (older, cmd) => core::mem::discriminant(older) == core::mem::discriminant(cmd),
+    };
+
+    if let Some(older) = deferred.iter_mut().find(|older| same_kind(older)) {
+        *older = cmd;
+    } else {
+        deferred.push_back(cmd);
     }
+}
 
-    if let Some(tt_value) = tt_value {
-        if refs.search_state.ply > 0 {
-            return tt_value;
-        }
+fn probe_online_tablebase(
+    reporter: &mut Reporter,
+    limits: &SearchLimits,
+    options: &SearchOptions,
+    board: &Board,
+    tablebase: &mut LichessTablebase,
+) -> Option<Move> {
+    if limits.is_infinite() || board.status() != GameStatus::Ongoing {
+        return None;
     }
 
-    let static_eval = tt_value
-        .and_then(|eval| {
-            if (eval < EVAL_INFINITY - 256) && (eval > 256 - EVAL_INFINITY) {
-                Some(eval)
-            } else {
-                None
-            }
-        })
-        .unwrap_or_else(|| evaluate(refs.board));
+    let timeout = online_probe_timeout(limits, board.side_to_move(), options.move_overhead)?;
 
-    if !matches!(node_type, NodeType::Root | NodeType::Pv) {
-        let margin = if depth <= 4 {
-            Some(30 * i16::from(depth))
-        } else {
-            None
-        };
+    let start_time = Instant::now();
 
-        if let Some(margin) = margin {
-            let eval = static_eval.saturating_sub(margin);
+    let probe = match tablebase.probe_root(board, timeout) {
+        Ok(probe) => probe?,
+        Err(error) => {
+            reporter.info(SearchToEngine::InfoString(error));
 
-            if eval >= beta {
-                return eval;
-            }
+            return None;
         }
-    }
+    };
+
+    let allowed = root_moves(board, &limits.search_moves);
+    let probe = probe.best(|mv| allowed.iter().any(|root_move| root_move.mv == mv))?;
+
+    let distance = probe.dtz.unwrap_or_default().unsigned_abs();
+    let distance = Eval::try_from(distance).unwrap_or(Eval::MAX).min(255);
+
+    // a result the fifty move rule might change is left to the search
+    let wdl = probe.wdl?;
+
+    let cp = match wdl {
+        Wdl::Win => TB_WIN - distance,
+        Wdl::Draw => 0,
+        Wdl::Loss => distance - TB_WIN,
+    };
+
+    // within the reach of Gaviota the server knows the distance to mate too
+    #[cfg(feature = "gaviota")]
+    let cp = probe
+        .dtm
+        .and_then(|dtm| crate::gaviota::mate_score(board, dtm))
+        .unwrap_or(cp);
+
+    let report = SearchToEngine::InfoString(format!(
+        "online tablebase hit: {wdl:?} (dtz {}, dtm {})",
+        probe
+            .dtz
+            .map_or_else(|| "-".to_string(), |dtz| dtz.to_string()),
+        probe
+            .dtm
+            .map_or_else(|| "-".to_string(), |dtm| dtm.to_string()),
+    ));
+    reporter.info(report);
+
+    let report = SearchToEngine::Summary {
+        depth: 1,
+        seldepth: 1,
+        time: Duration::from_std(start_time.elapsed()).unwrap_or_default(),
+        cp,
+        bound: Bound::Exact,
+        nodes: 0,
+        nps: 0,
+        hashfull: 0,
+        pv: vec![convert_move_to_uci(board, probe.mv).to_string()],
+    };
+    reporter.info(report);
+
+    Some(probe.mv)
+}
 
-    let mut moves: ArrayVec<cozy_chess::Move, MAX_MOVES> = generate_moves(refs.board, false);
+fn online_probe_timeout(
+    limits: &SearchLimits,
+    side: Color,
+    overhead: Duration,
+) -> Option<core::time::Duration> {
+    let budget = if let Some(move_time) = limits.move_time {
+        move_time / 4
+    } else if let Some(gametime) = &limits.game_time {
+        let clock = match side {
+            Color::White => gametime.white_time,
+            Color::Black => gametime.black_time,
+        };
 
-    order_moves(refs, &mut moves, tt_move);
+        clock / ONLINE_PROBE_DIVISOR
+    } else {
+        return Some(MAX_REQUEST_TIME);
+    };
 
-    let futile = [293, 620]
-        .get(usize::from(depth))
-        .map_or(false, |&margin| static_eval.saturating_add(margin) <= alpha);
+    let timeout = (budget - overhead).to_std().ok()?.min(MAX_REQUEST_TIME);
 
-    let is_game_over = moves.is_empty();
+    (timeout >= MIN_ONLINE_PROBE_TIME).then_some(timeout)
+}
 
-    let mut hash_flag = Flag::Alpha;
+fn iterative_deepening(refs: &mut SearchRefs) -> Option<(Move, Option<Move>)> {
     let mut best_move = None;
-    let mut best_score = -EVAL_INFINITY - 1;
+    // helpers start at different depths so they don't all search the same
+    // tree in lockstep
+    let mut depth = 1 + u8::from(refs.thread % 2 == 1);
+    let mut stop = false;
+    // how many iterations in a row have kept the best move, and how much of
+    // the soft time limit that and the node counts say to use
+    let mut stability = 0;
+    let mut time_scale = 1.0;
+    // the score of the last completed iteration, which the next one's window
+    // is centred on
+    let mut last_eval = None;
 
-    for (move_idx, legal) in moves.into_iter().enumerate() {
-        let old_pos = make_move(refs, legal);
+    refs.search_state.board_stack[0] = BoardSlot::new(Position::new(refs.root_board.clone()));
 
-        refs.transposition_table.prefetch(refs.board);
+    refs.search_state.root_moves =
+        root_moves(refs.root_board, &refs.search_state.limits.search_moves);
 
-        let is_quiet = !is_capture(&old_pos, legal) && legal.promotion.is_none();
-        let gives_check = refs.board.checkers().is_empty();
+    apply_limits(refs);
 
-        if best_move.is_some() && futile && is_quiet && !is_check && !gives_check {
-            unmake_move(refs, old_pos);
-            continue;
-        }
+    while depth <= refs.search_state.depth_limit && !stop {
+        let _span = tracing::debug_span!("iteration", thread = refs.thread, depth).entered();
 
-        let mut node_pv = Vec::new();
+        refs.search_state.depth = depth;
 
-        let mut eval_score = 0;
+        let eval = aspiration_search(refs, depth, last_eval);
 
-        let reduction = if depth >= 3
-            && move_idx >= 3
-            && !is_check
-            && legal.promotion.is_none()
-            && refs.board.checkers().is_empty()
-        {
-            2
-        } else {
-            0
-        };
+        check_terminate(refs);
 
-        if !is_draw(refs) {
-            if move_idx != 0 {
-                eval_score = -negamax(
-                    refs,
-                    &mut node_pv,
-                    (depth - 1).saturating_sub(reduction),
-                    -alpha - 1,
-                    -alpha,
-                    NodeType::Other,
-                );
+        if refs.search_state.terminate.is_none() {
+            let previous_best = best_move.map(|(best, _)| best);
+            let root_pv = refs.search_state.root_pv();
 
-                if eval_score > alpha {
-                    eval_score = -negamax(refs, &mut node_pv, depth - 1, -beta, -alpha, node_type);
-                }
-            } else {
-                eval_score = -negamax(refs, &mut node_pv, depth - 1, -beta, -alpha, NodeType::Pv);
+            if let Some(&first) = root_pv.first() {
+                best_move = Some((first, root_pv.get(1).copied()));
             }
-        }
 
-        unmake_move(refs, old_pos);
+            last_eval = Some(eval);
 
-        if eval_score > best_score {
-            best_score = eval_score;
-            best_move = Some(legal);
-        }
+            tracing::debug!(eval, nodes = refs.search_state.nodes, "iteration complete");
 
-        let mating_value = EVAL_INFINITY - Eval::from(refs.search_state.ply);
+            report_summary(refs, eval, Bound::Exact);
 
-        if beta > mating_value {
-            beta = mating_value;
+            if let Some((best, _)) = best_move {
+                stability = if previous_best == Some(best) {
+                    stability + 1
+                } else {
+                    0
+                };
 
-            if alpha >= beta {
-                return beta;
-            }
-        }
+                time_scale = soft_time_scale(&refs.search_state.root_moves, best, stability);
 
-        let mating_value = Eval::from(refs.search_state.ply) - EVAL_INFINITY;
+                sort_root_moves(&mut refs.search_state.root_moves, best);
+            }
 
-        if mating_value > alpha {
-            alpha = mating_value;
+            depth += 1;
 
-            if beta <= alpha {
-                return alpha;
+            if refs.main.is_some()
+                && refs
+                    .search_state
+                    .limits
+                    .mate
+                    .is_some_and(|mate| mate_distance(eval).is_some_and(|moves| moves <= mate))
+            {
+                stop = true;
             }
         }
 
-        if eval_score >= beta {
-            refs.transposition_table.insert(Entry::new(
-                refs.board.hash(),
-                depth,
-                Flag::Beta,
-                beta,
-                best_move,
-            ));
+        // past the soft limit the next depth probably can't finish before
+        // the hard one, so don't start it
+        let is_time_up = refs.main.is_some()
+            && refs.search_state.limits.game_time.is_some()
+            && search_time(refs)
+                >= refs
+                    .search_state
+                    .soft_time
+                    .mul_f64(time_scale)
+                    .min(refs.search_state.hard_time);
 
-            if !is_capture(refs.board, legal) {
-                store_killer_move(refs, legal);
-            }
+        if is_time_up || refs.search_state.terminate.is_some() {
+            stop = true;
+        }
+    }
 
-            return beta;
+    // a helper's last nodes would otherwise go uncounted
+    refs.smp.publish_nodes(refs.thread, refs.search_state.nodes);
+
+    best_move.or_else(|| {
+        refs.search_state
+            .root_moves
+            .first()
+            .map(|root| (root.mv, None))
+    })
+}
+
+fn aspiration_search(refs: &mut SearchRefs, depth: u8, last_eval: Option<Eval>) -> Eval {
+    let mut delta = refs.options.params.eval(Param::AspirationWindow);
+
+    let (mut alpha, mut beta) = match last_eval {
+        Some(eval)
+            if depth >= ASPIRATION_DEPTH
+                && eval.abs() < MATE_BOUND
+                && refs.options.enabled(Heuristic::AspirationWindows) =>
+        {
+            (
+                eval.saturating_sub(delta).max(-EVAL_INFINITY),
+                eval.saturating_add(delta).min(EVAL_INFINITY),
+            )
         }
+        _ => (-EVAL_INFINITY, EVAL_INFINITY),
+    };
 
-        if eval_score > alpha {
-            alpha = eval_score;
+    loop {
+        refs.search_state.stack[0].pv_length = 0;
 
-            hash_flag = Flag::Exact;
+        let eval = negamax(refs, depth, alpha, beta, NodeType::Root);
+
+        check_terminate(refs);
 
-            pv.clear();
-            pv.push(legal);
-            pv.append(&mut node_pv);
+        if refs.search_state.terminate.is_some() {
+            return eval;
         }
-    }
 
-    if is_game_over {
-        if is_check {
-            return -EVAL_INFINITY + Eval::from(refs.search_state.ply);
+        let bound = Bound::new(eval, alpha, beta);
+
+        match bound {
+            Bound::Exact => return eval,
+            Bound::Upper => alpha = alpha.saturating_sub(delta).max(-EVAL_INFINITY),
+            Bound::Lower => beta = beta.saturating_add(delta).min(EVAL_INFINITY),
         }
 
-        return 0;
-    }
+        tracing::debug!(eval, ?bound, alpha, beta, "aspiration window widened");
 
-    refs.transposition_table.insert(Entry::new(
-        refs.board.hash(),
-        depth,
-        hash_flag,
-        alpha,
-        best_move,
-    ));
+        report_summary(refs, eval, bound);
 
-    alpha
+        delta = delta.saturating_mul(2);
+    }
+}
+
+fn report_summary(refs: &mut SearchRefs, eval: Eval, bound: Bound) {
+    if refs.main.is_none() {
+        return;
+    }
+
+    let elapsed = refs.search_state.start_time.unwrap().elapsed();
+
+    refs.smp.publish_nodes(refs.thread, refs.search_state.nodes);
+    let nodes = refs.smp.total_nodes();
+
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    let nps = (nodes as f64 / elapsed.as_secs_f64()) as u64;
+
+    let report = SearchToEngine::Summary {
+        depth: refs.search_state.depth,
+        seldepth: refs.search_state.seldepth,
+        time: Duration::from_std(elapsed).unwrap(),
+        cp: eval,
+        bound,
+        nodes,
+        nps,
+        hashfull: refs.transposition_table.hashfull(),
+        pv: convert_pv_to_strings(refs.search_state.root_pv(), refs.root_board.clone()),
+    };
+
+    if let Some(main) = &mut refs.main {
+        main.reporter.info(report);
+    }
+}
+
+/// Only the moves in `search_moves`, unless none of them are legal here.
+fn root_moves(board: &Board, search_moves: &[String]) -> Vec<RootMove> {
+    let moves = order_moves(board);
+
+    let is_allowed =
+        |mv: &Move| search_moves.contains(&convert_move_to_uci(board, *mv).to_string());
+
+    if moves.iter().any(is_allowed) {
+        moves
+            .into_iter()
+            .filter(is_allowed)
+            .map(RootMove::new)
+            .collect()
+    } else {
+        moves.into_iter().map(RootMove::new).collect()
+    }
+}
+
+/// A best move that keeps changing gets more time, and one that has been
+/// stable and took most of the nodes gets less.
+fn soft_time_scale(root_moves: &[RootMove], best: Move, stability: usize) -> f64 {
+    const STABILITY_SCALES: [f64; 5] = [1.6, 1.2, 1.0, 0.9, 0.8];
+
+    let total_nodes = root_moves.iter().map(|root| root.nodes).sum::<u64>();
+    let best_nodes = root_moves
+        .iter()
+        .find(|root| root.mv == best)
+        .map_or(0, |root| root.nodes);
+
+    #[allow(clippy::cast_precision_loss)]
+    let best_share = if total_nodes == 0 {
+        0.5
+    } else {
+        best_nodes as f64 / total_nodes as f64
+    };
+
+    // from 2.0 when the best move took none of the nodes, to 0.675 when it
+    // took all of them
+    let node_scale = (1.5 - best_share) * 1.35;
+
+    STABILITY_SCALES[stability.min(STABILITY_SCALES.len() - 1)] * node_scale
+}
+
+fn sort_root_moves(root_moves: &mut [RootMove], best: Move) {
+    root_moves.sort_by_key(|root| (root.mv != best, Reverse(root.score), Reverse(root.nodes)));
+}
+
+fn apply_limits(refs: &mut SearchRefs) {
+    let limits = &refs.search_state.limits;
+
+    let mut depth_limit = limits.depth.unwrap_or(MAX_DEPTH).min(MAX_DEPTH);
+
+    // a mate in n is 2n - 1 plies deep, and the rest leaves room for the
+    // reductions and pruning on the way to it
+    if let Some(mate) = limits.mate {
+        depth_limit = depth_limit.min(mate.max(1).saturating_mul(4));
+    }
+    let mut soft_time = core::time::Duration::ZERO;
+    let mut hard_time = core::time::Duration::ZERO;
+    let soft_time_share = f64::from(refs.options.params.get(Param::SoftTimePercent)) / 100.0;
+
+    let node_limit = limits.nodes.map_or(u64::MAX, |nodes| {
+        (refs.search_state.nodes + refs.search_state.helper_nodes).saturating_add(nodes)
+    });
+
+    if let Some(gametime) = &limits.game_time {
+        let (clock, increment) = match refs.root_board.side_to_move() {
+            Color::White => (gametime.white_time, gametime.white_increment),
+            Color::Black => (gametime.black_time, gametime.black_increment),
+        };
+
+        let time = gametime.moves_to_go.map_or_else(
+            || clock / refs.options.params.get(Param::TimeDivisor),
+            |mtg| {
+                if mtg == 0 {
+                    clock
+                } else {
+                    clock / i32::from(mtg)
+                }
+            },
+        );
+
+        let overhead = refs.options.move_overhead;
+        // the most that can be spent before the move reaches the gui in time
+        let available = clock - overhead;
+
+        if available < EMERGENCY_CLOCK {
+            // too little time left to trust the normal allocation,
+            // so just play whatever a very shallow search finds
+            depth_limit = depth_limit.min(EMERGENCY_DEPTH);
+
+            hard_time = (available / 4).to_std().unwrap_or_default();
+            soft_time = hard_time.mul_f64(soft_time_share);
+        } else {
+            // the increment only arrives after the move is made,
+            // so never plan to use more than what's on the clock
+            let time_slice = ((time + increment) * i32::from(refs.options.time_usage) / 100
+                - overhead)
+                .min(available);
+
+            soft_time = time_slice
+                .to_std()
+                .unwrap_or_default()
+                .mul_f64(soft_time_share);
+            hard_time = (time_slice * refs.options.params.get(Param::HardTimePercent) / 100)
+                .min(available)
+                .to_std()
+                .unwrap_or_default();
+        }
+    }
+
+    // only the main thread decides when the search is over, helpers go on
+    // until it has
+    if refs.main.is_none() {
+        depth_limit = MAX_DEPTH;
+    }
+
+    refs.search_state.depth_limit = depth_limit;
+    refs.search_state.node_limit = if refs.main.is_some() {
+        node_limit
+    } else {
+        u64::MAX
+    };
+    refs.search_state.soft_time = soft_time;
+    refs.search_state.hard_time = hard_time;
+    refs.search_state.start_time = Some(Instant::now());
+    refs.search_state.start_nodes = refs.search_state.nodes;
+}
+
+/// Deterministic mode counts time from the nodes searched instead.
+fn search_time(refs: &SearchRefs) -> core::time::Duration {
+    if refs.options.deterministic {
+        let nodes = refs.search_state.nodes - refs.search_state.start_nodes;
+
+        core::time::Duration::from_millis(nodes / DETERMINISTIC_NODES_PER_MS)
+    } else {
+        refs.search_state.start_time.unwrap().elapsed()
+    }
+}
+
+const fn late_move_count(depth: u8) -> usize {
+    match depth {
+        1..=3 => 3 + depth as usize * depth as usize,
+        _ => usize::MAX,
+    }
+}
+
+fn late_move_reduction(
+    params: &SearchParams,
+    depth: u8,
+    move_idx: usize,
+    node_type: NodeType,
+    is_killer: bool,
+    history: Option<i32>,
+) -> u8 {
+    let mut reduction = i32::from(params.lmr(depth, move_idx));
+
+    reduction += match node_type {
+        NodeType::Root | NodeType::Pv => -1,
+        NodeType::Cut => 1,
+        NodeType::All => 0,
+    };
+    reduction -= i32::from(is_killer);
+
+    if let Some(history) = history {
+        // from +1 for no history down to -2 for the most
+        reduction += 1 - history * 3 / MAX_HISTORY;
+    }
+
+    u8::try_from(reduction.max(0)).unwrap_or(u8::MAX)
+}
+
+fn see_threshold(depth: u8, is_quiet: bool) -> Option<Eval> {
+    let depth = Eval::from(depth);
+
+    match depth {
+        1..=6 if is_quiet => Some(-40 * depth * depth),
+        1..=6 => Some(-150 * depth),
+        _ => None,
+    }
+}
+
+fn mate_distance(eval: Eval) -> Option<u8> {
+    let plies = EVAL_INFINITY - eval;
+
+    (eval > MATE_BOUND).then(|| u8::try_from((plies + 1) / 2).unwrap_or(u8::MAX))
+}
+
+pub fn first_legal_move(board: &Board) -> Option<Move> {
+    let mut first_move = None;
+
+    board.generate_moves(|mvs| {
+        first_move = mvs.into_iter().next();
+        true
+    });
+
+    first_move
+}
+
+fn report_current_move(refs: &mut SearchRefs, mv: Move, move_idx: usize) {
+    let Some(main) = &mut refs.main else {
+        return;
+    };
+
+    if refs
+        .search_state
+        .start_time
+        .is_none_or(|start| start.elapsed() < CURRMOVE_DELAY)
+    {
+        return;
+    }
+
+    main.reporter.info(SearchToEngine::CurrentMove {
+        depth: refs.search_state.depth,
+        mv: convert_move_to_uci(refs.root_board, mv).to_string(),
+        number: move_idx + 1,
+    });
+}
+
+/// Searches within what the [`Oracle`] knows of the node, so endings one side
+/// can't win never score above a draw for it.
+fn negamax(refs: &mut SearchRefs, depth: u8, alpha: Eval, beta: Eval, node_type: NodeType) -> Eval {
+    // mates need a check, and aren't hidden behind a draw
+    let bound = if refs.board().checkers().is_empty() {
+        Oracle::bound(refs.board())
+    } else {
+        None
+    };
+
+    let draw = draw_score(refs);
+
+    let (lower, upper) = match bound {
+        None => return search_node(refs, depth, alpha, beta, node_type),
+        Some(Bound::Exact) => return draw,
+        Some(Bound::Lower) => (draw, EVAL_INFINITY),
+        Some(Bound::Upper) => (-EVAL_INFINITY, draw),
+    };
+
+    if alpha >= upper {
+        return upper;
+    }
+
+    if beta <= lower {
+        return lower;
+    }
+
+    search_node(refs, depth, alpha.max(lower), beta.min(upper), node_type).clamp(lower, upper)
 }
 
-fn quiescence(refs: &mut SearchRefs, pv: &mut Vec<Move>, mut alpha: Eval, beta: Eval) -> Eval {
-    if refs.search_state.nodes % 0x2000 == 0 {
+#[allow(clippy::cognitive_complexity, clippy::too_many_lines)]
+fn search_node(
+    refs: &mut SearchRefs,
+    mut depth: u8,
+    mut alpha: Eval,
+    mut beta: Eval,
+    node_type: NodeType,
+) -> Eval {
+    debug_assert!(alpha < beta);
+
+    if check_due(refs) {
         check_terminate(refs);
     }
 
+    if refs.search_state.nodes + refs.search_state.helper_nodes >= refs.search_state.node_limit {
+        refs.search_state.terminate = Some(SearchTerminate::Stop);
+    }
+
     if refs.search_state.terminate.is_some() {
         return 0;
     }
 
     refs.search_state.nodes += 1;
 
-    let stand_pat = evaluate(refs.board);
+    if usize::from(refs.search_state.ply) >= MAX_PLY - 1 {
+        return leaf_eval(refs);
+    }
+
+    let is_check = !refs.board().checkers().is_empty();
+
+    if is_check {
+        depth += 1;
+    }
+
+    if depth == 0 {
+        let checks = if refs.options.enabled(Heuristic::QuiescenceChecks) {
+            QUIESCENCE_CHECK_PLIES
+        } else {
+            0
+        };
+
+        return quiescence(refs, alpha, beta, checks);
+    }
+
+    let (tt_value, tt_move) = refs
+        .transposition_table
+        .probe(refs.board().hash())
+        .map_or((None, None), |data| {
+            data.get(depth, refs.search_state.ply, alpha, beta)
+        });
+
+    if let Some(tt_value) = tt_value {
+        if refs.search_state.ply > 0 {
+            return tt_value;
+        }
+    }
+
+    // without a hash move the ordering is poor, so a full depth search here
+    // costs a lot for little, and a shallower one fills in the hash move for
+    // the next iteration. an all node searches every move whatever the
+    // ordering, and the root and pv nodes decide the score, so only a cut
+    // node is reduced
+    if tt_move.is_none()
+        && depth >= 4
+        && !node_type.is_pv()
+        && node_type.expects_cutoff()
+        && refs.options.enabled(Heuristic::InternalReduction)
+    {
+        depth -= 1;
+    }
+
+    let static_eval = tt_value
+        .and_then(|eval| {
+            if (eval < MATE_BOUND) && (eval > -MATE_BOUND) {
+                Some(eval)
+            } else {
+                None
+            }
+        })
+        .unwrap_or_else(|| leaf_eval(refs));
+
+    if !node_type.is_pv() && refs.options.enabled(Heuristic::ReverseFutility) {
+        let margin = if depth <= 4 {
+            Some(refs.options.params.eval(Param::RfpMargin) * i16::from(depth))
+        } else {
+            None
+        };
+
+        if let Some(margin) = margin {
+            let eval = static_eval.saturating_sub(margin);
+
+            if eval >= beta {
+                return eval;
+            }
+        }
+    }
+
+    // if passing still fails high, some real move will too. only a cut node,
+    // which expects to fail high, tries it, so the all node a null move
+    // leads to never passes straight back. without pieces zugzwang is too
+    // likely for a pass to prove anything
+    if !node_type.is_pv()
+        && node_type.expects_cutoff()
+        && !is_check
+        && depth >= NULL_MOVE_DEPTH
+        && static_eval >= beta
+        && has_non_pawn_material(refs.board())
+        && refs.options.enabled(Heuristic::NullMove)
+    {
+        let reduction = NULL_MOVE_REDUCTION + depth / 4;
+
+        make_null_move(refs);
+
+        let eval = -negamax(
+            refs,
+            depth.saturating_sub(1 + reduction),
+            -beta,
+            -beta + 1,
+            NodeType::All,
+        );
+
+        unmake_move(refs);
+
+        if refs.search_state.terminate.is_some() {
+            return 0;
+        }
+
+        // a mate after passing isn't one that can be proven
+        if eval >= beta {
+            return if eval >= MATE_BOUND { beta } else { eval };
+        }
+    }
+
+    let is_root = matches!(node_type, NodeType::Root);
+
+    // the root keeps its own move list across iterations
+    if !is_root {
+        generate_moves(refs.search_state, MoveGen::All, tt_move);
+    }
+
+    let futility_margins = [
+        refs.options.params.eval(Param::FutilityMargin1),
+        refs.options.params.eval(Param::FutilityMargin2),
+    ];
+
+    let futile = refs.options.enabled(Heuristic::Futility)
+        && futility_margins
+            .get(usize::from(depth))
+            .is_some_and(|&margin| static_eval.saturating_add(margin) <= alpha);
+
+    let is_game_over = if is_root {
+        refs.search_state.root_moves.is_empty()
+    } else {
+        refs.search_state.stack[usize::from(refs.search_state.ply)]
+            .moves
+            .is_empty()
+    };
+
+    let mut hash_flag = Flag::Alpha;
+    let mut best_move = None;
+    let mut best_score = -EVAL_INFINITY - 1;
+
+    for move_idx in 0.. {
+        let next = if is_root {
+            refs.search_state
+                .root_moves
+                .get(move_idx)
+                .map(|root| root.mv)
+        } else {
+            next_move(refs.search_state)
+        };
+
+        let Some(legal) = next else {
+            break;
+        };
+
+        let is_quiet = !is_capture(refs.board(), legal) && legal.promotion.is_none();
+
+        let is_killer = refs.search_state.stack[usize::from(refs.search_state.ply)]
+            .killers
+            .contains(&Some(legal));
+
+        // only quiet moves have a history score
+        let history = is_quiet.then(|| {
+            refs.search_state.quiet_history[refs.board().side_to_move() as usize]
+                [legal.from as usize][legal.to as usize]
+        });
+
+        // the tt move and killers are exempt from late move pruning
+        let is_late =
+            is_quiet && move_idx >= late_move_count(depth) && tt_move != Some(legal) && !is_killer;
+
+        // skip moves that lose more material in the exchange they start than
+        // a shallow search could make up for
+        if best_move.is_some()
+            && refs.options.enabled(Heuristic::SeePruning)
+            && !node_type.is_pv()
+            && !is_check
+            && see_threshold(depth, is_quiet)
+                .is_some_and(|threshold| see::see(refs.board(), legal) < threshold)
+        {
+            continue;
+        }
+
+        if is_root {
+            report_current_move(refs, legal, move_idx);
+        }
+
+        let nodes_before = refs.search_state.nodes;
+
+        make_move(refs, legal);
+
+        refs.transposition_table.prefetch(refs.board());
 
-    if stand_pat >= beta {
-        return beta;
+        let gives_check = refs.board().checkers().is_empty();
+
+        if best_move.is_some() && futile && is_quiet && !is_check && !gives_check {
+            unmake_move(refs);
+            continue;
+        }
+
+        // at shallow depths, quiet moves this far down the ordering almost
+        // never turn out best
+        if is_late
+            && refs.options.enabled(Heuristic::LateMovePruning)
+            && !node_type.is_pv()
+            && !is_check
+            && refs.board().checkers().is_empty()
+        {
+            unmake_move(refs);
+            continue;
+        }
+
+        let mut eval_score = -draw_score(refs);
+
+        let reduction = if refs.options.enabled(Heuristic::LateMoveReductions)
+            && depth >= 3
+            && move_idx >= 3
+            && !is_check
+            && legal.promotion.is_none()
+            && refs.board().checkers().is_empty()
+        {
+            late_move_reduction(
+                &refs.options.params,
+                depth,
+                move_idx,
+                node_type,
+                is_killer,
+                history,
+            )
+        } else {
+            0
+        };
+
+        // a pv node's window is still open for its children, anywhere else
+        // it's a null window
+        let child_type = if node_type.is_pv() {
+            NodeType::Pv
+        } else {
+            node_type.null_window_child(move_idx == 0)
+        };
+
+        if !is_draw(refs) {
+            if move_idx == 0 {
+                eval_score = -negamax(refs, depth - 1, -beta, -alpha, child_type);
+            } else {
+                let null_window_child = node_type.null_window_child(false);
+
+                eval_score = -negamax(
+                    refs,
+                    (depth - 1).saturating_sub(reduction),
+                    -alpha - 1,
+                    -alpha,
+                    null_window_child,
+                );
+
+                // a reduced move that beats alpha gets its full depth, still
+                // with a null window, before a pv node spends a full window
+                // on it
+                if eval_score > alpha && reduction > 0 {
+                    eval_score = -negamax(refs, depth - 1, -alpha - 1, -alpha, null_window_child);
+                }
+
+                // anywhere else the window is already a null one, so that
+                // search would just be repeated
+                if eval_score > alpha && node_type.is_pv() {
+                    eval_score = -negamax(refs, depth - 1, -beta, -alpha, child_type);
+                }
+            }
+        }
+
+        unmake_move(refs);
+
+        if is_root {
+            let root = &mut refs.search_state.root_moves[move_idx];
+
+            root.nodes = refs.search_state.nodes - nodes_before;
+            // a move that didn't raise alpha only has an upper bound
+            root.score = if eval_score > alpha {
+                eval_score
+            } else {
+                -EVAL_INFINITY
+            };
+        }
+
+        if eval_score > best_score {
+            best_score = eval_score;
+            best_move = Some(legal);
+        }
+
+        let mating_value = EVAL_INFINITY - Eval::from(refs.search_state.ply);
+
+        if beta > mating_value {
+            beta = mating_value;
+
+            if alpha >= beta {
+                return beta;
+            }
+        }
+
+        let mating_value = Eval::from(refs.search_state.ply) - EVAL_INFINITY;
+
+        if mating_value > alpha {
+            alpha = mating_value;
+
+            if beta <= alpha {
+                return alpha;
+            }
+        }
+
+        if eval_score >= beta {
+            // the move that failed high is what a lowerbound report shows
+            if is_root {
+                update_pv(refs, legal);
+            }
+
+            let hash = refs.board().hash();
+
+            refs.transposition_table.insert(Entry::new(
+                hash,
+                depth,
+                refs.search_state.ply,
+                Flag::Beta,
+                beta,
+                best_move,
+            ));
+
+            if is_capture(refs.board(), legal) {
+                if refs.options.enabled(Heuristic::CaptureHistory) {
+                    update_capture_history(refs, legal, depth);
+                }
+            } else {
+                if refs.options.enabled(Heuristic::KillerMoves) {
+                    store_killer_move(refs, legal);
+                }
+
+                if refs.options.enabled(Heuristic::History) {
+                    update_history(refs, legal, depth);
+                }
+            }
+
+            return beta;
+        }
+
+        if eval_score > alpha {
+            alpha = eval_score;
+
+            hash_flag = Flag::Exact;
+
+            update_pv(refs, legal);
+        }
+    }
+
+    if is_game_over {
+        if is_check {
+            return -EVAL_INFINITY + Eval::from(refs.search_state.ply);
+        }
+
+        return draw_score(refs);
+    }
+
+    let hash = refs.board().hash();
+
+    refs.transposition_table.insert(Entry::new(
+        hash,
+        depth,
+        refs.search_state.ply,
+        hash_flag,
+        alpha,
+        best_move,
+    ));
+
+    alpha
+}
+
+/// Quiet checks are searched as well for the first `checks` plies.
+fn quiescence(refs: &mut SearchRefs, mut alpha: Eval, beta: Eval, checks: u8) -> Eval {
+    if check_due(refs) {
+        check_terminate(refs);
+    }
+
+    if refs.search_state.nodes + refs.search_state.helper_nodes >= refs.search_state.node_limit {
+        refs.search_state.terminate = Some(SearchTerminate::Stop);
+    }
+
+    if refs.search_state.terminate.is_some() {
+        return 0;
+    }
+
+    refs.search_state.nodes += 1;
+
+    if usize::from(refs.search_state.ply) >= MAX_PLY - 1 {
+        return leaf_eval(refs);
+    }
+
+    // quiescence entries are stored at depth 0, so any entry will do here
+    let tt_entry = refs.transposition_table.probe(refs.board().hash());
+    let (tt_value, tt_move) = tt_entry.map_or((None, None), |data| {
+        data.get(0, refs.search_state.ply, alpha, beta)
+    });
+
+    // an entry the main search stored is worth more than what this finds
+    let store = tt_entry.is_none_or(|data| *data.info().depth == 0);
+
+    if let Some(tt_value) = tt_value {
+        return tt_value;
+    }
+
+    let is_check = !refs.board().checkers().is_empty();
+
+    let mode = if is_check {
+        MoveGen::All
+    } else {
+        let stand_pat = leaf_eval(refs);
+
+        if stand_pat >= beta {
+            return beta;
+        }
+
+        if stand_pat > alpha {
+            alpha = stand_pat;
+        }
+
+        if checks > 0 {
+            MoveGen::CapturesAndChecks
+        } else {
+            MoveGen::Captures
+        }
+    };
+
+    generate_moves(refs.search_state, mode, tt_move);
+
+    if is_check
+        && refs.search_state.stack[usize::from(refs.search_state.ply)]
+            .moves
+            .is_empty()
+    {
+        return -EVAL_INFINITY + Eval::from(refs.search_state.ply);
+    }
+
+    let mut hash_flag = Flag::Alpha;
+    let mut best_move = None;
+
+    while let Some(legal) = next_move(refs.search_state) {
+        make_move(refs, legal);
+
+        let eval_score = -quiescence(refs, -beta, -alpha, checks.saturating_sub(1));
+
+        unmake_move(refs);
+
+        if refs.search_state.terminate.is_some() {
+            return 0;
+        }
+
+        if eval_score >= beta {
+            if store {
+                refs.transposition_table.insert(Entry::new(
+                    refs.board().hash(),
+                    0,
+                    refs.search_state.ply,
+                    Flag::Beta,
+                    beta,
+                    Some(legal),
+                ));
+            }
+
+            return beta;
+        }
+
+        if eval_score > alpha {
+            alpha = eval_score;
+
+            hash_flag = Flag::Exact;
+            best_move = Some(legal);
+
+            update_pv(refs, legal);
+        }
+    }
+
+    if store {
+        refs.transposition_table.insert(Entry::new(
+            refs.board().hash(),
+            0,
+            refs.search_state.ply,
+            hash_flag,
+            alpha,
+            best_move,
+        ));
+    }
+
+    alpha
+}
+
+pub const MAX_MOVES: usize = 218;
+
+pub const MAX_PLY: usize = 128;
+
+#[allow(clippy::cast_possible_truncation)]
+const MAX_DEPTH: u8 = (MAX_PLY - 1) as u8;
+
+const NULL_MOVE: Move = Move {
+    from: cozy_chess::Square::A1,
+    to: cozy_chess::Square::A1,
+    promotion: None,
+};
+
+const CONTROL_CAPACITY: usize = 64;
+
+pub const NO_MOVE: &str = "0000";
+
+/// By `[color][from][to]`.
+type QuietHistory = [[[i32; Square::NUM]; Square::NUM]; Color::NUM];
+
+static EMPTY_HISTORY: QuietHistory = [[[0; Square::NUM]; Square::NUM]; Color::NUM];
+
+/// By `[piece][to][captured]`.
+type CaptureHistory = [[[i32; Piece::NUM]; Square::NUM]; Piece::NUM];
+
+static EMPTY_CAPTURE_HISTORY: CaptureHistory = [[[0; Piece::NUM]; Square::NUM]; Piece::NUM];
+
+const MAX_HISTORY: i32 = 16384;
+
+const QUIESCENCE_CHECK_PLIES: u8 = 1;
+
+const ONLINE_PROBE_DIVISOR: i32 = 80;
+const MIN_ONLINE_PROBE_TIME: core::time::Duration = core::time::Duration::from_millis(50);
+
+const MIN_MOVE_TIME: Duration = Duration::milliseconds(10);
+
+const EMERGENCY_CLOCK: Duration = Duration::milliseconds(200);
+const EMERGENCY_DEPTH: u8 = 2;
+
+const DETERMINISTIC_NODES_PER_MS: u64 = 1000;
+
+const CHECK_INTERVAL_MS: u64 = 2;
+
+const MIN_CHECK_NODES: u64 = 64;
+const MAX_CHECK_NODES: u64 = 0x4000;
+
+const NULL_MOVE_DEPTH: u8 = 3;
+const NULL_MOVE_REDUCTION: u8 = 3;
+
+const ASPIRATION_DEPTH: u8 = 5;
+
+const CURRMOVE_DELAY: core::time::Duration = core::time::Duration::from_secs(1);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MoveGen {
+    All,
+    Captures,
+    CapturesAndChecks,
+}
+
+fn generate_moves(state: &mut SearchState, mode: MoveGen, pv: Option<Move>) {
+    let ply = usize::from(state.ply);
+
+    let board = state.board_stack[ply].position.board();
+    let entry = &mut state.stack[ply];
+    let killers = entry.killers;
+    let history = &state.quiet_history;
+    let capture_history = &state.capture_history;
+
+    entry.moves.clear();
+    entry.next_move = 0;
+    entry.mode = mode;
+
+    // captures that lose material are only found once they come up, see
+    // next_move
+    board.generate_moves(|mvs| {
+        for mv in mvs {
+            let keep = match mode {
+                MoveGen::All => true,
+                MoveGen::Captures => is_capture(board, mv),
+                MoveGen::CapturesAndChecks => {
+                    is_capture(board, mv) || (gives_check(board, mv) && see::see(board, mv) >= 0)
+                }
+            };
+
+            if !keep {
+                continue;
+            }
+
+            entry.moves.push((
+                mv,
+                order_score(board, killers, history, capture_history, mv, pv),
+            ));
+        }
+
+        false
+    });
+}
+
+/// Selects the best remaining move on each call, so nodes that cut off early
+/// don't pay for sorting the moves they never search.
+fn next_move(state: &mut SearchState) -> Option<Move> {
+    let ply = usize::from(state.ply);
+
+    let board = state.board_stack[ply].position.board();
+    let entry = &mut state.stack[ply];
+
+    loop {
+        let moves = entry.moves.get_mut(entry.next_move..)?;
+        let best = select_next(board, moves)?;
+        let losing = matches!(moves[0].1, MoveScore::LosingCapture(_));
+
+        entry.next_move += 1;
+
+        if !losing || entry.mode == MoveGen::All {
+            return Some(best);
+        }
+    }
+}
+
+/// Captures are ordered by MVV-LVA, and only checked with SEE once they come
+/// up.
+fn select_next(board: &Board, moves: &mut [(Move, MoveScore)]) -> Option<Move> {
+    loop {
+        let best = select_best(moves)?;
+
+        if matches!(moves[0].1, MoveScore::Capture(..)) && may_lose_material(board, best) {
+            let see_eval = see::see(board, best);
+
+            if see_eval < 0 {
+                moves[0].1 = MoveScore::LosingCapture(see_eval);
+                continue;
+            }
+        }
+
+        return Some(best);
+    }
+}
+
+fn select_best(moves: &mut [(Move, MoveScore)]) -> Option<Move> {
+    let best = moves
+        .iter()
+        .enumerate()
+        .max_by(|(_, (_, a)), (_, (_, b))| a.cmp(b))
+        .map(|(idx, _)| idx)?;
+
+    moves.swap(0, best);
+
+    Some(moves[0].0)
+}
+
+pub fn order_moves(board: &Board) -> ArrayVec<Move, MAX_MOVES> {
+    scored_moves(board, None)
+        .into_iter()
+        .map(|(mv, _)| mv)
+        .collect()
+}
+
+pub fn scored_moves(board: &Board, pv: Option<Move>) -> ArrayVec<(Move, MoveScore), MAX_MOVES> {
+    let mut moves = ArrayVec::<_, MAX_MOVES>::new();
+
+    board.generate_moves(|mvs| {
+        for mv in mvs {
+            moves.push((
+                mv,
+                order_score(
+                    board,
+                    [None; 2],
+                    &EMPTY_HISTORY,
+                    &EMPTY_CAPTURE_HISTORY,
+                    mv,
+                    pv,
+                ),
+            ));
+        }
+
+        false
+    });
+
+    for idx in 0..moves.len() {
+        select_next(board, &mut moves[idx..]);
+    }
+
+    moves
+}
+
+fn order_score(
+    board: &Board,
+    killers: [Option<Move>; 2],
+    history: &QuietHistory,
+    capture_history: &CaptureHistory,
+    mv: cozy_chess::Move,
+    pv: Option<Move>,
+) -> MoveScore {
+    if let Some(pv) = pv {
+        if mv == pv {
+            return MoveScore::Pv;
+        }
+    }
+
+    if matches!(mv.promotion, Some(piece) if piece != Piece::Queen) {
+        return MoveScore::UnderPromotion;
+    }
+
+    if is_capture(board, mv) {
+        let [piece, to, captured] = capture_index(board, mv);
+
+        return MoveScore::Capture(mvv_lva(board, mv), capture_history[piece][to][captured]);
+    }
+
+    if killers.contains(&Some(mv)) {
+        return MoveScore::Killer;
+    }
+
+    MoveScore::Quiet(history[board.side_to_move() as usize][mv.from as usize][mv.to as usize])
+}
+
+fn mvv_lva(board: &Board, mv: Move) -> i16 {
+    let [piece, _, captured] = capture_index(board, mv);
+
+    // piece indices go up with value
+    #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+    {
+        (captured * Piece::NUM + Piece::NUM - 1 - piece) as i16
+    }
+}
+
+fn may_lose_material(board: &Board, mv: Move) -> bool {
+    let [piece, _, captured] = capture_index(board, mv);
+
+    piece > captured && piece != Piece::King as usize
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MoveScore {
+    UnderPromotion,
+    Quiet(i32),
+    LosingCapture(i16),
+    Killer,
+    Capture(i16, i32),
+    Pv,
+}
+
+impl core::fmt::Display for MoveScore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::UnderPromotion => write!(f, "underpromotion"),
+            Self::Quiet(history) => write!(f, "quiet, history {history}"),
+            Self::LosingCapture(see) => write!(f, "losing capture, see {see}"),
+            Self::Killer => write!(f, "killer"),
+            Self::Capture(mvv_lva, history) => {
+                write!(f, "capture, mvv-lva {mvv_lva}, history {history}")
+            }
+            Self::Pv => write!(f, "hash move"),
+        }
+    }
+}
+
+/// Castling is encoded as the king moving onto its own rook.
+pub fn is_capture(board: &Board, legal: Move) -> bool {
+    board.colors(!board.side_to_move()).has(legal.to) || is_en_passant(board, legal)
+}
+
+fn gives_check(board: &Board, legal: Move) -> bool {
+    let mut after = board.clone();
+    after.play_unchecked(legal);
+
+    !after.checkers().is_empty()
+}
+
+pub fn is_en_passant(board: &Board, legal: Move) -> bool {
+    legal.from.file() != legal.to.file()
+        && !board.occupied().has(legal.to)
+        && board.pieces(Piece::Pawn).has(legal.from)
+}
+
+fn make_move(refs: &mut SearchRefs, legal: Move) {
+    let ply = usize::from(refs.search_state.ply);
+
+    let (current, next) = refs.search_state.board_stack.split_at_mut(ply + 1);
+    let next = &mut next[0];
+
+    next.position.clone_from(&current[ply].position);
+    next.position.play_unchecked(legal);
+    next.hash = next.position.board().hash();
+    next.after_null_move = false;
+
+    advance_ply(refs);
+}
+
+fn make_null_move(refs: &mut SearchRefs) {
+    let ply = usize::from(refs.search_state.ply);
+
+    let (current, next) = refs.search_state.board_stack.split_at_mut(ply + 1);
+    let next = &mut next[0];
+
+    next.position.clone_from(&current[ply].position);
+    next.position.play_null();
+    next.hash = next.position.board().hash();
+    next.after_null_move = true;
+
+    advance_ply(refs);
+}
+
+fn advance_ply(refs: &mut SearchRefs) {
+    refs.search_state.ply += 1;
+
+    if let Some(entry) = refs
+        .search_state
+        .stack
+        .get_mut(usize::from(refs.search_state.ply))
+    {
+        entry.pv_length = usize::from(refs.search_state.ply);
+    }
+
+    if refs.search_state.ply > refs.search_state.seldepth {
+        refs.search_state.seldepth = refs.search_state.ply;
+    }
+}
+
+const fn unmake_move(refs: &mut SearchRefs) {
+    refs.search_state.ply -= 1;
+}
+
+/// Checks come about every `CHECK_INTERVAL_MS` however fast the nodes are.
+fn check_due(refs: &mut SearchRefs) -> bool {
+    let state = &mut *refs.search_state;
+
+    state.check_countdown = state.check_countdown.saturating_sub(1);
+
+    if state.check_countdown > 0 {
+        return false;
+    }
+
+    state.check_countdown = if refs.options.deterministic {
+        DETERMINISTIC_NODES_PER_MS * CHECK_INTERVAL_MS
+    } else {
+        let now = Instant::now();
+
+        let next = state.last_check.map_or(MIN_CHECK_NODES, |(time, nodes)| {
+            check_nodes(state.nodes - nodes, now.duration_since(time))
+        });
+
+        state.last_check = Some((now, state.nodes));
+
+        next
+    };
+
+    true
+}
+
+fn check_nodes(nodes: u64, elapsed: core::time::Duration) -> u64 {
+    let per_interval =
+        u128::from(nodes) * u128::from(CHECK_INTERVAL_MS) * 1000 / elapsed.as_micros().max(1);
+
+    u64::try_from(per_interval)
+        .unwrap_or(MAX_CHECK_NODES)
+        .clamp(MIN_CHECK_NODES, MAX_CHECK_NODES)
+}
+
+fn check_terminate(refs: &mut SearchRefs) {
+    let Some(control_rx) = refs.main.as_ref().map(|main| main.control_rx) else {
+        refs.smp.publish_nodes(refs.thread, refs.search_state.nodes);
+
+        // helpers run until the main thread is done
+        if refs.smp.stop.load(Ordering::Relaxed) {
+            refs.search_state.terminate = Some(SearchTerminate::Stop);
+        }
+
+        return;
+    };
+
+    refs.smp.publish_nodes(refs.thread, refs.search_state.nodes);
+    refs.search_state.helper_nodes = refs.smp.total_nodes() - refs.search_state.nodes;
+
+    while let Ok(cmd) = control_rx.try_recv() {
+        match cmd {
+            EngineToSearch::Stop => refs.search_state.terminate = Some(SearchTerminate::Stop),
+            EngineToSearch::Quit => refs.search_state.terminate = Some(SearchTerminate::Quit),
+            EngineToSearch::ThinkHit(limits) => {
+                refs.search_state.limits = limits;
+                apply_limits(refs);
+
+                // the iteration already running is deeper than asked for
+                if refs.search_state.depth > refs.search_state.depth_limit {
+                    refs.search_state.terminate = Some(SearchTerminate::Stop);
+                }
+            }
+
+            cmd @ (EngineToSearch::Start { .. }
+            | EngineToSearch::Think { .. }
+            | EngineToSearch::SetHash(_)
+            | EngineToSearch::ClearHash
+            | EngineToSearch::SetOnlineTablebase(_)
+            | EngineToSearch::SetTimeUsage(_)
+            | EngineToSearch::SetMoveOverhead(_)
+            | EngineToSearch::SetThreads(_)
+            | EngineToSearch::SetContempt(_)
+            | EngineToSearch::SetEvalNoise { .. }
+            | EngineToSearch::SetDeterministic(_)
+            | EngineToSearch::SetParam(..)
+            | EngineToSearch::GetHeuristics(_)
+            | EngineToSearch::SetHeuristics(_)) => defer(&mut refs.search_state.deferred, cmd),
+        }
+    }
+
+    // depth and mate limits are checked between iterations, and the node
+    // limit on every node
+    let elapsed = search_time(refs);
+
+    let move_time_up =
+        refs.search_state.limits.move_time.is_some_and(|movetime| {
+            elapsed > move_time_limit(movetime, refs.options.move_overhead)
+        });
+
+    // depth 1 is always allowed to finish so there's a real move to play
+    let game_time_up = refs.search_state.limits.game_time.is_some()
+        && refs.search_state.depth > 1
+        && elapsed > refs.search_state.hard_time;
+
+    if move_time_up || game_time_up {
+        refs.search_state.terminate = Some(SearchTerminate::Stop);
+    }
+}
+
+fn move_time_limit(move_time: Duration, overhead: Duration) -> core::time::Duration {
+    (move_time - overhead)
+        .max(MIN_MOVE_TIME.min(move_time))
+        .to_std()
+        .unwrap_or_default()
+}
+
+fn leaf_eval(refs: &mut SearchRefs) -> Eval {
+    let key = refs.board().hash();
+
+    let eval = refs.search_state.eval_cache.get(key).unwrap_or_else(|| {
+        let eval = evaluate(refs.position());
+        refs.search_state.eval_cache.insert(key, eval);
+        eval
+    });
+
+    if refs.options.eval_noise == 0 {
+        return eval;
+    }
+
+    // splitmix64, so the same position gets the same noise for the whole game
+    let mut x = (refs.board().hash() ^ refs.options.noise_seed).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+
+    let amount = refs.options.eval_noise.unsigned_abs();
+    let offset = Eval::try_from(x % (2 * u64::from(amount) + 1)).unwrap_or_default();
+
+    eval.saturating_add(offset - Eval::try_from(amount).unwrap_or_default())
+}
+
+/// Positive contempt makes the side to move at the root avoid draws.
+const fn draw_score(refs: &SearchRefs) -> Eval {
+    if refs.search_state.ply.is_multiple_of(2) {
+        -refs.options.contempt
+    } else {
+        refs.options.contempt
+    }
+}
+
+fn is_draw(refs: &SearchRefs) -> bool {
+    Oracle::bound(refs.board()) == Some(Bound::Exact)
+        || is_repetition(refs)
+        || is_fifty_move_rule(refs)
+}
+
+fn is_repetition(refs: &SearchRefs) -> bool {
+    let board = refs.board();
+    let ply = usize::from(refs.search_state.ply);
+
+    // the last history entry is the root position, which is already on the board stack
+    let game = refs
+        .history
+        .split_last()
+        .map_or(&[][..], |(_, earlier)| earlier);
+
+    // nothing before a null move can be reached again by real moves
+    let since_null_move = refs.search_state.board_stack[..=ply]
+        .iter()
+        .rev()
+        .position(|slot| slot.after_null_move)
+        .unwrap_or(usize::MAX);
+
+    refs.search_state.board_stack[..ply]
+        .iter()
+        .rev()
+        .map(|slot| slot.hash)
+        .chain(game.iter().rev().map(|entry| entry.hash))
+        .take((board.halfmove_clock() as usize).min(since_null_move))
+        .skip(1)
+        .step_by(2)
+        .any(|hash| hash == board.hash())
+}
+
+fn has_non_pawn_material(board: &Board) -> bool {
+    let pawns_and_kings = board.pieces(Piece::Pawn) | board.pieces(Piece::King);
+
+    !(board.colors(board.side_to_move()) - pawns_and_kings).is_empty()
+}
+
+fn is_fifty_move_rule(refs: &SearchRefs) -> bool {
+    refs.board().halfmove_clock() >= 100
+}
+
+fn update_pv(refs: &mut SearchRefs, mv: Move) {
+    let ply = usize::from(refs.search_state.ply);
+
+    if ply + 1 >= MAX_PLY {
+        return;
+    }
+
+    let (parent, child) = refs.search_state.stack.split_at_mut(ply + 1);
+    let (parent, child) = (&mut parent[ply], &child[0]);
+
+    let child_length = child.pv_length.max(ply + 1);
+
+    parent.pv[ply] = mv;
+    parent.pv[ply + 1..child_length].copy_from_slice(&child.pv[ply + 1..child_length]);
+
+    parent.pv_length = child_length;
+}
+
+/// The bonus shrinks as the score nears [`MAX_HISTORY`], so it never
+/// overflows.
+fn update_history(refs: &mut SearchRefs, mv: Move, depth: u8) {
+    let color = refs.board().side_to_move() as usize;
+    let bonus = i32::from(depth) * i32::from(depth);
+
+    let score = &mut refs.search_state.quiet_history[color][mv.from as usize][mv.to as usize];
+
+    *score += bonus - *score * bonus / MAX_HISTORY;
+}
+
+fn update_capture_history(refs: &mut SearchRefs, mv: Move, depth: u8) {
+    let bonus = i32::from(depth) * i32::from(depth);
+
+    let [piece, to, captured] = capture_index(refs.board(), mv);
+    let score = &mut refs.search_state.capture_history[piece][to][captured];
+
+    *score += bonus - *score * bonus / MAX_HISTORY;
+}
+
+fn capture_index(board: &Board, mv: Move) -> [usize; 3] {
+    let piece = board.piece_on(mv.from).unwrap_or(Piece::Pawn);
+    // en passant is the only capture onto an empty square
+    let captured = board.piece_on(mv.to).unwrap_or(Piece::Pawn);
+
+    [piece as usize, mv.to as usize, captured as usize]
+}
+
+fn store_killer_move(refs: &mut SearchRefs, mv: Move) {
+    let ply = usize::from(refs.search_state.ply);
+
+    let killers = &mut refs.search_state.stack[ply].killers;
+
+    if killers[0] != Some(mv) {
+        killers[1] = killers[0];
+
+        killers[0] = Some(mv);
+    }
+}
+
+#[derive(Debug)]
+struct SearchRefs<'a> {
+    root_board: &'a Board,
+    main: Option<MainThread<'a>>,
+    thread: usize,
+    smp: &'a Smp,
+    options: &'a SearchOptions,
+    search_state: &'a mut SearchState,
+    history: &'a [History],
+    transposition_table: &'a TranspositionTable,
+}
+
+#[derive(Debug)]
+struct MainThread<'a> {
+    control_rx: &'a Receiver<EngineToSearch>,
+    reporter: &'a mut Reporter,
+}
+
+#[derive(Debug)]
+struct Smp {
+    stop: AtomicBool,
+    nodes: Box<[AtomicU64]>,
+}
+
+impl Smp {
+    fn new(threads: usize) -> Self {
+        Self {
+            stop: AtomicBool::new(false),
+            nodes: (0..threads).map(|_| AtomicU64::new(0)).collect(),
+        }
     }
 
-    if stand_pat > alpha {
-        alpha = stand_pat;
+    fn publish_nodes(&self, thread: usize, nodes: u64) {
+        self.nodes[thread].store(nodes, Ordering::Relaxed);
     }
 
-    let mut moves: ArrayVec<cozy_chess::Move, MAX_MOVES> = generate_moves(refs.board, true);
-
-    order_moves(refs, &mut moves, None);
-
-    for legal in moves {
-        let old_pos = make_move(refs, legal);
-
-        let mut node_pv = Vec::new();
-
-        let eval_score = -quiescence(refs, &mut node_pv, -beta, -alpha);
+    fn total_nodes(&self) -> u64 {
+        self.nodes
+            .iter()
+            .map(|nodes| nodes.load(Ordering::Relaxed))
+            .sum()
+    }
+}
 
-        unmake_move(refs, old_pos);
+#[derive(Debug)]
+struct SearchOptions {
+    time_usage: u16,
+    move_overhead: Duration,
+    threads: usize,
+    contempt: Eval,
+    eval_noise: Eval,
+    noise_seed: u64,
+    deterministic: bool,
+    params: SearchParams,
+    disabled_heuristics: [bool; Heuristic::ALL.len()],
+}
 
-        if eval_score >= beta {
-            return beta;
+impl SearchOptions {
+    fn set(&mut self, cmd: &EngineToSearch) {
+        match *cmd {
+            EngineToSearch::SetMoveOverhead(overhead) => self.move_overhead = overhead,
+            EngineToSearch::SetTimeUsage(percent) => self.time_usage = percent,
+            EngineToSearch::SetThreads(threads) => self.threads = threads.max(1),
+            EngineToSearch::SetContempt(contempt) => self.contempt = contempt,
+            EngineToSearch::SetEvalNoise { amount, seed } => {
+                self.eval_noise = amount;
+                self.noise_seed = seed;
+            }
+            EngineToSearch::SetDeterministic(deterministic) => self.deterministic = deterministic,
+            EngineToSearch::SetParam(param, value) => self.params.set(param, value),
+            _ => {}
         }
+    }
 
-        if eval_score > alpha {
-            alpha = eval_score;
+    const fn enabled(&self, heuristic: Heuristic) -> bool {
+        !self.disabled_heuristics[heuristic as usize]
+    }
+}
 
-            pv.clear();
-            pv.push(legal);
-            pv.append(&mut node_pv);
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            time_usage: 100,
+            move_overhead: Duration::milliseconds(100),
+            threads: 1,
+            contempt: 0,
+            eval_noise: 0,
+            noise_seed: 0,
+            deterministic: false,
+            params: SearchParams::default(),
+            disabled_heuristics: [false; Heuristic::ALL.len()],
         }
     }
+}
 
-    alpha
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Heuristic {
+    ReverseFutility,
+    Futility,
+    LateMoveReductions,
+    KillerMoves,
+    History,
+    LateMovePruning,
+    SeePruning,
+    InternalReduction,
+    QuiescenceChecks,
+    CaptureHistory,
+    AspirationWindows,
+    NullMove,
 }
 
-const MAX_MOVES: usize = 218;
+impl Heuristic {
+    pub const ALL: [Self; 12] = [
+        Self::ReverseFutility,
+        Self::Futility,
+        Self::LateMoveReductions,
+        Self::KillerMoves,
+        Self::History,
+        Self::LateMovePruning,
+        Self::SeePruning,
+        Self::InternalReduction,
+        Self::QuiescenceChecks,
+        Self::CaptureHistory,
+        Self::AspirationWindows,
+        Self::NullMove,
+    ];
+
+    #[must_use]
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::ReverseFutility => "reverse futility pruning",
+            Self::Futility => "futility pruning",
+            Self::LateMoveReductions => "late move reductions",
+            Self::KillerMoves => "killer moves",
+            Self::History => "history heuristic",
+            Self::LateMovePruning => "late move pruning",
+            Self::SeePruning => "see pruning",
+            Self::InternalReduction => "internal iterative reduction",
+            Self::QuiescenceChecks => "quiescence checks",
+            Self::CaptureHistory => "capture history",
+            Self::AspirationWindows => "aspiration windows",
+            Self::NullMove => "null move pruning",
+        }
+    }
+}
 
 #[must_use]
-pub fn generate_moves(board: &Board, captures_only: bool) -> ArrayVec<Move, MAX_MOVES> {
-    let mut moves = ArrayVec::new();
+pub fn fixed_depth_search(board: &Board, depth: u8, disabled: &[Heuristic]) -> u64 {
+    let mut options = SearchOptions {
+        deterministic: true,
+        ..SearchOptions::default()
+    };
+
+    for &heuristic in disabled {
+        options.disabled_heuristics[heuristic as usize] = true;
+    }
 
-    board.generate_moves(|mvs| {
-        if captures_only {
-            moves.extend(
-                mvs.into_iter()
-                    .filter(|mv| is_capture(board, *mv) && see::see(board, *mv) >= 0),
-            );
-        } else {
-            moves.extend(mvs);
-        }
+    let limits = SearchLimits {
+        depth: Some(depth),
+        ..SearchLimits::default()
+    };
 
-        false
-    });
+    let tt = TranspositionTable::new(16);
+    let (_, reports) = blocking_search(board, &[], limits, &options, &tt);
 
-    moves
+    reports
+        .into_iter()
+        .rev()
+        .find_map(|report| match report {
+            SearchToEngine::Summary { nodes, .. } => Some(nodes),
+            _ => None,
+        })
+        .unwrap_or_default()
 }
 
-fn order_moves(refs: &SearchRefs, moves: &mut [Move], pv: Option<Move>) {
-    pdqsort::sort_by(moves, |a, b| {
-        let a_score = order_score(refs, *a, pv);
-        let b_score = order_score(refs, *b, pv);
+#[must_use]
+pub fn timed_search(board: &Board, move_time: Duration) -> Option<Move> {
+    let limits = SearchLimits {
+        move_time: Some(move_time),
+        ..SearchLimits::default()
+    };
+
+    let tt = TranspositionTable::new(16);
 
-        b_score.cmp(&a_score)
-    });
+    blocking_search(board, &[], limits, &SearchOptions::default(), &tt).0
 }
 
-fn order_score(refs: &SearchRefs, mv: cozy_chess::Move, pv: Option<Move>) -> MoveScore {
-    if let Some(pv) = pv {
-        if mv == pv {
-            return MoveScore::Pv;
-        }
-    }
+#[must_use]
+pub fn node_limited_search(
+    board: &Board,
+    history: &[History],
+    nodes: u64,
+    transposition_table: &TranspositionTable,
+) -> Option<(Move, Eval)> {
+    let limits = SearchLimits {
+        nodes: Some(nodes),
+        ..SearchLimits::default()
+    };
+
+    let (best_move, reports) = blocking_search(
+        board,
+        history,
+        limits,
+        &SearchOptions::default(),
+        transposition_table,
+    );
+
+    let score = reports.into_iter().rev().find_map(|report| match report {
+        SearchToEngine::Summary {
+            cp,
+            bound: Bound::Exact,
+            ..
+        } => Some(cp),
+        _ => None,
+    })?;
+
+    Some((best_move?, score))
+}
 
-    if matches!(mv.promotion, Some(piece) if piece != Piece::Queen) {
-        return MoveScore::UnderPromotion;
-    }
+#[cfg(feature = "online")]
+#[must_use]
+pub fn game_search(
+    board: &Board,
+    history: &[History],
+    game_time: GameTime,
+    transposition_table: &TranspositionTable,
+) -> Option<Move> {
+    let limits = SearchLimits {
+        game_time: Some(game_time),
+        ..SearchLimits::default()
+    };
+
+    blocking_search(
+        board,
+        history,
+        limits,
+        &SearchOptions::default(),
+        transposition_table,
+    )
+    .0
+}
 
-    if is_capture(refs.board, mv) {
-        let see_eval = see::see(refs.board, mv);
+fn blocking_search(
+    board: &Board,
+    history: &[History],
+    limits: SearchLimits,
+    options: &SearchOptions,
+    transposition_table: &TranspositionTable,
+) -> (Option<Move>, Vec<SearchToEngine>) {
+    let (_control_tx, control_rx) = crossbeam_channel::unbounded();
+    // read back on this thread once the search is done, so it can't be bounded
+    let (report_tx, report_rx) = crossbeam_channel::unbounded();
+
+    let mut refs = SearchRefs {
+        root_board: board,
+        main: Some(MainThread {
+            control_rx: &control_rx,
+            reporter: &mut Reporter::new(report_tx),
+        }),
+        thread: 0,
+        smp: &Smp::new(1),
+        options,
+        search_state: &mut SearchState {
+            limits,
+            ..SearchState::default()
+        },
+        history,
+        transposition_table,
+    };
+
+    let best_move = iterative_deepening(&mut refs).map(|(best, _)| best);
+
+    let reports = report_rx
+        .try_iter()
+        .filter_map(|report| match report {
+            EngineReport::Search(report) => Some(report),
+            _ => None,
+        })
+        .collect();
 
-        if see_eval >= 0 {
-            return MoveScore::Capture(see_eval);
-        }
+    (best_move, reports)
+}
 
-        return MoveScore::LosingCapture(see_eval);
+impl SearchRefs<'_> {
+    fn position(&self) -> &Position {
+        &self.search_state.board_stack[usize::from(self.search_state.ply)].position
     }
 
-    let ply = usize::from(refs.search_state.ply);
-
-    for i in 0..2 {
-        if refs.search_state.killer_moves[ply][i] == Some(mv) {
-            return MoveScore::Killer;
-        }
+    fn board(&self) -> &Board {
+        self.position().board()
     }
-
-    MoveScore::NonCapture
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-enum MoveScore {
-    UnderPromotion,
-    NonCapture,
-    LosingCapture(i16),
-    Killer,
-    Capture(i16),
-    Pv,
+#[derive(Clone, Debug)]
+pub struct History {
+    pub hash: u64,
 }
 
-pub fn is_capture(board: &Board, legal: Move) -> bool {
-    board.occupied().has(legal.to)
+#[derive(Clone, Debug)]
+pub struct Heuristics {
+    quiet_history: Box<QuietHistory>,
+    capture_history: Box<CaptureHistory>,
 }
 
-fn make_move(refs: &mut SearchRefs, legal: Move) -> Board {
-    let old_pos = refs.board.clone();
-
-    refs.board.play_unchecked(legal);
+impl Heuristics {
+    /// In the order [`Self::scores_mut`] takes them.
+    pub fn scores(&self) -> impl Iterator<Item = i32> + '_ {
+        let quiet = self.quiet_history.iter().flatten().flatten();
+        let capture = self.capture_history.iter().flatten().flatten();
 
-    refs.history.push(History {
-        hash: refs.board.hash(),
-    });
+        quiet.chain(capture).copied()
+    }
 
-    refs.search_state.ply += 1;
+    pub fn scores_mut(&mut self) -> impl Iterator<Item = &mut i32> {
+        let quiet = self.quiet_history.iter_mut().flatten().flatten();
+        let capture = self.capture_history.iter_mut().flatten().flatten();
 
-    if refs.search_state.ply > refs.search_state.seldepth {
-        refs.search_state.seldepth = refs.search_state.ply;
+        quiet.chain(capture)
     }
-
-    old_pos
 }
 
-fn unmake_move(refs: &mut SearchRefs, old_pos: Board) {
-    refs.search_state.ply -= 1;
+impl Default for Heuristics {
+    fn default() -> Self {
+        Self {
+            quiet_history: Box::new(EMPTY_HISTORY),
+            capture_history: Box::new(EMPTY_CAPTURE_HISTORY),
+        }
+    }
+}
 
-    refs.history.pop();
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u8>,
+    pub nodes: Option<u64>,
+    pub move_time: Option<Duration>,
+    pub game_time: Option<GameTime>,
+    pub mate: Option<u8>,
+    pub search_moves: Vec<String>,
+}
 
-    *refs.board = old_pos;
+impl SearchLimits {
+    #[must_use]
+    pub const fn is_infinite(&self) -> bool {
+        self.depth.is_none()
+            && self.nodes.is_none()
+            && self.move_time.is_none()
+            && self.game_time.is_none()
+            && self.mate.is_none()
+    }
 }
 
-fn check_terminate(refs: &mut SearchRefs) {
-    if let Ok(cmd) = refs.control_rx.try_recv() {
-        match cmd {
-            EngineToSearch::Stop => refs.search_state.terminate = Some(SearchTerminate::Stop),
-            EngineToSearch::Quit => refs.search_state.terminate = Some(SearchTerminate::Quit),
+#[derive(Debug)]
+struct SearchState {
+    limits: SearchLimits,
+    nodes: u64,
+    ply: u8,
+    depth: u8,
+    seldepth: u8,
+    terminate: Option<SearchTerminate>,
+    start_time: Option<Instant>,
+    start_nodes: u64,
+    soft_time: core::time::Duration,
+    hard_time: core::time::Duration,
+    depth_limit: u8,
+    node_limit: u64,
+    helper_nodes: u64,
+    check_countdown: u64,
+    last_check: Option<(Instant, u64)>,
+    board_stack: Box<[BoardSlot]>,
+    stack: Box<[StackEntry]>,
+    deferred: VecDeque<EngineToSearch>,
+    quiet_history: Box<QuietHistory>,
+    capture_history: Box<CaptureHistory>,
+    root_moves: Vec<RootMove>,
+    eval_cache: EvalCache,
+}
 
-            EngineToSearch::Start(_) | EngineToSearch::SetHash(_) | EngineToSearch::ClearHash => {}
+impl SearchState {
+    /// The history tables and evaluation cache carry over.
+    fn new_search(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+        self.nodes = 0;
+        self.ply = 0;
+        self.depth = 0;
+        self.seldepth = 0;
+        self.terminate = None;
+        self.start_time = None;
+        self.start_nodes = 0;
+        self.soft_time = core::time::Duration::ZERO;
+        self.hard_time = core::time::Duration::ZERO;
+        self.depth_limit = MAX_DEPTH;
+        self.node_limit = u64::MAX;
+        self.helper_nodes = 0;
+        self.check_countdown = MIN_CHECK_NODES;
+        self.last_check = None;
+
+        // killers are by ply, which means a different position at a new root
+        for entry in &mut self.stack {
+            *entry = StackEntry::default();
         }
     }
 
-    match refs.search_mode {
-        SearchMode::Infinite => {}
-        SearchMode::MoveTime(movetime) => {
-            if refs.search_state.start_time.unwrap().elapsed().as_millis()
-                > movetime.num_milliseconds().try_into().unwrap()
-            {
-                refs.search_state.terminate = Some(SearchTerminate::Stop);
-            }
-        }
-        SearchMode::GameTime(_) => {
-            if refs.search_state.start_time.unwrap().elapsed() > refs.search_state.allocated_time {
-                refs.search_state.terminate = Some(SearchTerminate::Stop);
-            }
-        }
-        SearchMode::Depth(depth) => {
-            if refs.search_state.depth > *depth {
-                refs.search_state.terminate = Some(SearchTerminate::Stop);
-            }
+    fn heuristics(&self) -> Heuristics {
+        Heuristics {
+            quiet_history: self.quiet_history.clone(),
+            capture_history: self.capture_history.clone(),
         }
     }
-}
 
-fn is_draw(refs: &mut SearchRefs) -> bool {
-    Oracle::is_draw(refs.board) || is_threefold_repetition(refs) || is_fifty_move_rule(refs)
-}
+    fn set_heuristics(&mut self, heuristics: &Heuristics) {
+        self.quiet_history.clone_from(&heuristics.quiet_history);
+        self.capture_history.clone_from(&heuristics.capture_history);
+    }
 
-fn is_threefold_repetition(refs: &mut SearchRefs) -> bool {
-    refs.history
-        .iter()
-        .rev()
-        .take(refs.board.halfmove_clock() as usize + 1)
-        .step_by(2)
-        .filter(|entry| entry.hash == refs.board.hash())
-        .count()
-        >= 2
+    fn root_pv(&self) -> &[Move] {
+        &self.stack[0].pv[..self.stack[0].pv_length]
+    }
 }
 
-fn is_fifty_move_rule(refs: &mut SearchRefs) -> bool {
-    refs.board.halfmove_clock() >= 100
+/// There's a slot for every ply, written over as moves are made, so making a
+/// move never allocates.
+#[derive(Clone, Debug)]
+struct BoardSlot {
+    position: Position,
+    hash: u64,
+    /// Repetition checks don't look past a null move.
+    after_null_move: bool,
 }
 
-fn store_killer_move(refs: &mut SearchRefs, mv: Move) {
-    let ply = usize::from(refs.search_state.ply);
-
-    let first_killer = refs.search_state.killer_moves[ply][0];
+impl BoardSlot {
+    fn new(position: Position) -> Self {
+        let hash = position.board().hash();
 
-    if first_killer != Some(mv) {
-        refs.search_state.killer_moves[ply][1] = first_killer;
-
-        refs.search_state.killer_moves[ply][0] = Some(mv);
+        Self {
+            position,
+            hash,
+            after_null_move: false,
+        }
     }
 }
 
 #[derive(Debug)]
-struct SearchRefs<'a> {
-    board: &'a mut Board,
-    control_rx: &'a Receiver<EngineToSearch>,
-    report_tx: &'a Sender<EngineReport>,
-    search_mode: &'a SearchMode,
-    search_state: &'a mut SearchState,
-    history: &'a mut Vec<History>,
-    transposition_table: &'a mut TranspositionTable,
+struct StackEntry {
+    moves: ArrayVec<(Move, MoveScore), MAX_MOVES>,
+    next_move: usize,
+    mode: MoveGen,
+    pv: [Move; MAX_PLY],
+    pv_length: usize,
+    killers: [Option<Move>; 2],
 }
 
 #[derive(Debug)]
-pub struct History {
-    pub hash: u64,
+struct RootMove {
+    mv: Move,
+    nodes: u64,
+    score: Eval,
 }
 
-#[derive(Debug)]
-pub enum SearchMode {
-    Infinite,
-    MoveTime(Duration),
-    GameTime(GameTime),
-    Depth(u8),
+impl RootMove {
+    const fn new(mv: Move) -> Self {
+        Self {
+            mv,
+            nodes: 0,
+            score: -EVAL_INFINITY,
+        }
+    }
 }
 
-#[derive(Debug)]
-struct SearchState {
-    nodes: u64,
-    ply: u8,
-    depth: u8,
-    seldepth: u8,
-    terminate: Option<SearchTerminate>,
-    start_time: Option<Instant>,
-    allocated_time: core::time::Duration,
-    killer_moves: [[Option<Move>; 2]; 128],
+impl Default for StackEntry {
+    fn default() -> Self {
+        Self {
+            moves: ArrayVec::new(),
+            next_move: 0,
+            mode: MoveGen::All,
+            pv: [NULL_MOVE; MAX_PLY],
+            pv_length: 0,
+            killers: [None; 2],
+        }
+    }
 }
 
 impl Default for SearchState {
     fn default() -> Self {
         Self {
+            limits: SearchLimits::default(),
             nodes: Default::default(),
             ply: Default::default(),
             depth: Default::default(),
             seldepth: Default::default(),
             terminate: Option::default(),
             start_time: Option::default(),
-            allocated_time: core::time::Duration::default(),
-            killer_moves: [[None; 2]; 128],
+            start_nodes: 0,
+            soft_time: core::time::Duration::default(),
+            hard_time: core::time::Duration::default(),
+            depth_limit: MAX_DEPTH,
+            node_limit: u64::MAX,
+            helper_nodes: 0,
+            check_countdown: MIN_CHECK_NODES,
+            last_check: None,
+            board_stack: (0..MAX_PLY)
+                .map(|_| BoardSlot::new(Position::new(Board::default())))
+                .collect(),
+            stack: (0..MAX_PLY).map(|_| StackEntry::default()).collect(),
+            deferred: VecDeque::new(),
+            quiet_history: Box::new(EMPTY_HISTORY),
+            capture_history: Box::new(EMPTY_CAPTURE_HISTORY),
+            root_moves: Vec::new(),
+            eval_cache: EvalCache::new(),
         }
     }
 }
@@ -726,11 +2657,33 @@ enum SearchTerminate {
     Quit,
 }
 
-#[derive(Clone, Copy, Debug)]
+/// Searched with a null window, a node is expected to fail high (cut) or low
+/// (all), and the two alternate down the tree.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum NodeType {
     Root,
     Pv,
-    Other,
+    Cut,
+    All,
+}
+
+impl NodeType {
+    const fn is_pv(self) -> bool {
+        matches!(self, Self::Root | Self::Pv)
+    }
+
+    const fn expects_cutoff(self) -> bool {
+        !matches!(self, Self::All)
+    }
+
+    /// Only the first move of a cut node is expected to refute it, so only that
+    /// child is an all node.
+    const fn null_window_child(self, is_first: bool) -> Self {
+        match self {
+            Self::Cut if is_first => Self::All,
+            Self::Root | Self::Pv | Self::Cut | Self::All => Self::Cut,
+        }
+    }
 }
 
 fn convert_pv_to_strings(pv: &[Move], mut board: Board) -> Vec<String> {
@@ -742,3 +2695,397 @@ fn convert_pv_to_strings(pv: &[Move], mut board: Board) -> Vec<String> {
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::RwLock;
+
+    fn next_report(report_rx: &Receiver<EngineReport>) -> SearchToEngine {
+        loop {
+            if let EngineReport::Search(report) = report_rx.recv().unwrap() {
+                if !matches!(
+                    report,
+                    SearchToEngine::Summary { .. } | SearchToEngine::InfoString(_)
+                ) {
+                    return report;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_order_moves() {
+        let board = "1k1r4/1pp4p/p7/4p3/8/P5P1/1PP4P/2K1R3 w - - 0 1"
+            .parse::<Board>()
+            .unwrap();
+
+        let ordered = order_moves(&board);
+
+        let mut legal = 0;
+        board.generate_moves(|mvs| {
+            legal += mvs.len();
+            false
+        });
+
+        assert_eq!(ordered.len(), legal);
+        assert_eq!(ordered[0].to_string(), "e1e5");
+    }
+
+    #[test]
+    fn test_history_orders_quiet_moves() {
+        let board = Board::default();
+        let mut history = EMPTY_HISTORY;
+
+        let rewarded = "g1f3".parse::<Move>().unwrap();
+        history[Color::White as usize][rewarded.from as usize][rewarded.to as usize] = 100;
+
+        let other = "e2e4".parse::<Move>().unwrap();
+
+        assert!(
+            order_score(
+                &board,
+                [None; 2],
+                &history,
+                &EMPTY_CAPTURE_HISTORY,
+                rewarded,
+                None
+            ) > order_score(
+                &board,
+                [None; 2],
+                &history,
+                &EMPTY_CAPTURE_HISTORY,
+                other,
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn test_new_search_keeps_history() {
+        let mut state = SearchState::default();
+        let mv = "g1f3".parse::<Move>().unwrap();
+
+        state.nodes = 1000;
+        state.terminate = Some(SearchTerminate::Stop);
+        state.stack[3].killers[0] = Some(mv);
+        state.quiet_history[Color::White as usize][mv.from as usize][mv.to as usize] = 100;
+        state.eval_cache.insert(42, 17);
+
+        state.new_search(SearchLimits {
+            depth: Some(5),
+            ..SearchLimits::default()
+        });
+
+        assert_eq!(state.nodes, 0);
+        assert!(state.terminate.is_none());
+        assert_eq!(state.limits.depth, Some(5));
+        assert_eq!(state.stack[3].killers, [None; 2]);
+        assert_eq!(
+            state.quiet_history[Color::White as usize][mv.from as usize][mv.to as usize],
+            100
+        );
+        assert_eq!(state.eval_cache.get(42), Some(17));
+    }
+
+    #[test]
+    fn test_total_nodes() {
+        let smp = Smp::new(3);
+
+        smp.publish_nodes(0, 100);
+        smp.publish_nodes(2, 20);
+        smp.publish_nodes(2, 50);
+
+        assert_eq!(smp.total_nodes(), 150);
+    }
+
+    #[test]
+    fn test_check_nodes() {
+        let ms = core::time::Duration::from_millis;
+
+        // a thousand nodes a millisecond
+        assert_eq!(check_nodes(5000, ms(5)), 1000 * CHECK_INTERVAL_MS);
+
+        assert_eq!(check_nodes(10, ms(100)), MIN_CHECK_NODES);
+        assert_eq!(check_nodes(u64::MAX, ms(1)), MAX_CHECK_NODES);
+        assert_eq!(
+            check_nodes(1000, core::time::Duration::ZERO),
+            MAX_CHECK_NODES
+        );
+    }
+
+    #[test]
+    fn test_late_move_reduction() {
+        let params = SearchParams::default();
+        let reduction = |node_type, is_killer, history| {
+            late_move_reduction(&params, 8, 10, node_type, is_killer, history)
+        };
+
+        assert!(
+            late_move_reduction(&params, 12, 30, NodeType::All, false, None)
+                > reduction(NodeType::All, false, None)
+        );
+        assert!(reduction(NodeType::Pv, false, None) < reduction(NodeType::All, false, None));
+        assert!(reduction(NodeType::Cut, false, None) > reduction(NodeType::All, false, None));
+        assert!(reduction(NodeType::All, true, None) < reduction(NodeType::All, false, None));
+        assert!(
+            reduction(NodeType::All, false, Some(MAX_HISTORY))
+                < reduction(NodeType::All, false, Some(0))
+        );
+    }
+
+    #[test]
+    fn test_quiescence_keeps_deeper_entries() {
+        let board = "4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1"
+            .parse::<Board>()
+            .unwrap();
+        let transposition_table = TranspositionTable::with_buckets(16);
+
+        // what the main search found, which the capture doesn't cut off
+        transposition_table.insert(Entry::new(board.hash(), 6, 0, Flag::Alpha, -500, None));
+
+        let mut refs = SearchRefs {
+            root_board: &board,
+            main: None,
+            thread: 0,
+            smp: &Smp::new(1),
+            options: &SearchOptions::default(),
+            search_state: &mut SearchState::default(),
+            history: &[],
+            transposition_table: &transposition_table,
+        };
+
+        refs.search_state.board_stack[0] = BoardSlot::new(Position::new(board.clone()));
+
+        quiescence(&mut refs, -EVAL_INFINITY, EVAL_INFINITY, 0);
+
+        let entry = transposition_table.probe(board.hash()).unwrap();
+
+        assert_eq!(*entry.info().depth, 6);
+    }
+
+    #[test]
+    fn test_node_type() {
+        // cut and all nodes alternate down the first moves of a null window
+        // line
+        let mut node_type = NodeType::Root;
+
+        for expected in [NodeType::Cut, NodeType::All, NodeType::Cut] {
+            node_type = node_type.null_window_child(true);
+
+            assert_eq!(node_type, expected);
+        }
+
+        assert_eq!(NodeType::Pv.null_window_child(false), NodeType::Cut);
+        assert_eq!(NodeType::All.null_window_child(false), NodeType::Cut);
+        // the first move of a cut node didn't refute it, so the next should
+        assert_eq!(NodeType::Cut.null_window_child(false), NodeType::Cut);
+
+        assert!(NodeType::Pv.expects_cutoff());
+        assert!(NodeType::Cut.expects_cutoff());
+        assert!(!NodeType::All.expects_cutoff());
+    }
+
+    #[test]
+    fn test_soft_time_scale() {
+        let root_moves = |nodes: [u64; 2]| {
+            ["e2e4", "d2d4"]
+                .iter()
+                .zip(nodes)
+                .map(|(mv, nodes)| RootMove {
+                    nodes,
+                    ..RootMove::new(mv.parse().unwrap())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let best = "e2e4".parse().unwrap();
+
+        // a settled move that took nearly all the nodes stops early
+        assert!(soft_time_scale(&root_moves([95, 5]), best, 4) < 1.0);
+        // a new best move that took few of the nodes gets extra time
+        assert!(soft_time_scale(&root_moves([30, 70]), best, 0) > 1.0);
+    }
+
+    #[test]
+    fn test_move_time_limit() {
+        let ms = Duration::milliseconds;
+
+        assert_eq!(
+            move_time_limit(ms(1000), ms(100)),
+            core::time::Duration::from_millis(900)
+        );
+        assert_eq!(
+            move_time_limit(ms(50), ms(100)),
+            core::time::Duration::from_millis(10)
+        );
+        assert_eq!(
+            move_time_limit(ms(5), ms(100)),
+            core::time::Duration::from_millis(5)
+        );
+    }
+
+    #[test]
+    fn test_online_probe_timeout() {
+        let overhead = Duration::milliseconds(100);
+        let timeout = |limits: &SearchLimits| online_probe_timeout(limits, Color::White, overhead);
+
+        let limits = SearchLimits {
+            move_time: Some(Duration::seconds(2)),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            timeout(&limits),
+            Some(core::time::Duration::from_millis(400))
+        );
+
+        let game_time = |white_time| SearchLimits {
+            game_time: Some(GameTime {
+                white_time,
+                black_time: Duration::minutes(10),
+                white_increment: Duration::zero(),
+                black_increment: Duration::zero(),
+                moves_to_go: None,
+            }),
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            timeout(&game_time(Duration::minutes(10))),
+            Some(MAX_REQUEST_TIME)
+        );
+        // too short a clock to wait on the network at all
+        assert_eq!(timeout(&game_time(Duration::seconds(5))), None);
+
+        assert_eq!(
+            timeout(&SearchLimits {
+                depth: Some(10),
+                ..SearchLimits::default()
+            }),
+            Some(MAX_REQUEST_TIME)
+        );
+    }
+
+    #[test]
+    fn test_bound() {
+        assert_eq!(Bound::new(-20, -20, 30), Bound::Upper);
+        assert_eq!(Bound::new(30, -20, 30), Bound::Lower);
+        assert_eq!(Bound::new(5, -20, 30), Bound::Exact);
+        assert_eq!(Bound::new(0, -EVAL_INFINITY, EVAL_INFINITY), Bound::Exact);
+    }
+
+    #[test]
+    fn test_capture_history_breaks_mvv_lva_ties() {
+        let board = Board::from_fen("4k3/8/8/8/p6p/8/8/R3K2R w - - 0 1", false).unwrap();
+        let mut capture_history = EMPTY_CAPTURE_HISTORY;
+
+        let rewarded = "h1h4".parse::<Move>().unwrap();
+        let [piece, to, captured] = capture_index(&board, rewarded);
+        capture_history[piece][to][captured] = 100;
+
+        let other = "a1a4".parse::<Move>().unwrap();
+
+        let score = |mv| {
+            order_score(
+                &board,
+                [None; 2],
+                &EMPTY_HISTORY,
+                &capture_history,
+                mv,
+                None,
+            )
+        };
+
+        assert_eq!(mvv_lva(&board, rewarded), mvv_lva(&board, other));
+        assert!(score(rewarded) > score(other));
+    }
+
+    #[test]
+    fn test_losing_captures_demoted() {
+        // the queen takes a pawn the knight defends, the pawn one it doesn't
+        // need to
+        let board = Board::from_fen("4k3/8/5n2/3p4/4P3/8/8/3QK3 w - - 0 1", false).unwrap();
+
+        let moves = scored_moves(&board, None);
+        let score = |uci: &str| {
+            let mv = uci.parse::<Move>().unwrap();
+
+            &moves.iter().find(|(legal, _)| *legal == mv).unwrap().1
+        };
+
+        assert_eq!(moves[0].0, "e4d5".parse::<Move>().unwrap());
+        assert!(matches!(score("e4d5"), MoveScore::Capture(..)));
+        assert_eq!(score("d1d5"), &MoveScore::LosingCapture(-480));
+
+        assert!(!may_lose_material(&board, "e4d5".parse().unwrap()));
+        assert!(may_lose_material(&board, "d1d5".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_respawn_after_panic() {
+        let (report_tx, report_rx) = crossbeam_channel::unbounded();
+        let transposition_table = Arc::new(RwLock::new(Arc::new(TranspositionTable::new(1))));
+
+        let mut search = Search::new();
+        search.init(report_tx, Arc::clone(&transposition_table), false);
+
+        let depth_one = || EngineToSearch::Start {
+            limits: SearchLimits {
+                depth: Some(1),
+                ..SearchLimits::default()
+            },
+            board: Box::default(),
+            history: Vec::new(),
+        };
+
+        // the next search panics when it tries to lock the poisoned table
+        let table = Arc::clone(&transposition_table);
+        let _ = std::thread::spawn(move || {
+            let _guard = table.write().unwrap();
+            panic!("poisoning the hash table");
+        })
+        .join();
+
+        search.send(depth_one());
+
+        assert!(matches!(
+            next_report(&report_rx),
+            SearchToEngine::Panicked(_)
+        ));
+
+        search.respawn();
+        search.send(depth_one());
+
+        assert!(matches!(
+            next_report(&report_rx),
+            SearchToEngine::BestMove { .. }
+        ));
+
+        assert!(search.shutdown());
+    }
+
+    #[test]
+    fn test_shutdown_stops_search() {
+        let (report_tx, report_rx) = crossbeam_channel::unbounded();
+
+        let mut search = Search::new();
+        search.init(
+            report_tx,
+            Arc::new(RwLock::new(Arc::new(TranspositionTable::new(1)))),
+            false,
+        );
+
+        search.send(EngineToSearch::Start {
+            limits: SearchLimits::default(),
+            board: Box::default(),
+            history: Vec::new(),
+        });
+
+        // the infinite search is under way
+        while !matches!(
+            report_rx.recv().unwrap(),
+            EngineReport::Search(SearchToEngine::Summary { .. })
+        ) {}
+
+        assert!(search.shutdown());
+    }
+}
@@ -1,18 +1,38 @@
-use crate::{evaluate::Eval, search::is_capture};
+use crate::{evaluate::Eval, search::is_en_passant};
 use cozy_chess::{
     get_bishop_moves, get_king_moves, get_knight_moves, get_pawn_attacks, get_rook_moves, Board,
-    Color, Piece,
+    Color, Piece, Rank, Square,
 };
 
+/// Quiet moves score how much the moved piece is exposed.
 // thanks to https://github.com/analog-hors/tantabus ♡
-pub fn see(board: &Board, capture: cozy_chess::Move) -> Eval {
-    debug_assert!(is_capture(board, capture));
+pub fn see(board: &Board, mv: cozy_chess::Move) -> Eval {
+    see_traced(board, mv, |_| {})
+}
 
-    let target_square = capture.to;
-    let initial_capture = board.piece_on(target_square).unwrap();
+/// The exchange is played out in full, even where a side would rather stop.
+pub fn see_traced(board: &Board, mv: cozy_chess::Move, mut on_capture: impl FnMut(Square)) -> Eval {
+    let target_square = mv.to;
     let initial_colour = board.side_to_move();
 
-    let mut blockers = board.occupied() ^ capture.from.bitboard();
+    // castling, as the king moving onto its own rook
+    if board.colors(initial_colour).has(target_square) {
+        return 0;
+    }
+
+    let mut blockers = board.occupied() ^ mv.from.bitboard();
+
+    let initial_gain = if is_en_passant(board, mv) {
+        // the captured pawn sits behind the target square, off the exchange
+        blockers ^= Square::new(target_square.file(), mv.from.rank()).bitboard();
+
+        piece_value(Piece::Pawn)
+    } else {
+        board.piece_on(target_square).map_or(0, piece_value)
+    };
+
+    let promotion_rank =
+        target_square.rank() == Rank::First || target_square.rank() == Rank::Eighth;
 
     let mut attackers = get_king_moves(target_square) & blockers & board.pieces(Piece::King)
         | get_knight_moves(target_square) & blockers & board.pieces(Piece::Knight)
@@ -29,28 +49,43 @@ pub fn see(board: &Board, capture: cozy_chess::Move) -> Eval {
             & blockers
             & board.colored_pieces(Color::Black, Piece::Pawn);
 
-    let mut target_piece = board.piece_on(capture.from).unwrap();
+    let mut target_piece = board.piece_on(mv.from).unwrap();
     let mut colour = !initial_colour;
 
-    let mut gains = vec![piece_value(initial_capture)];
+    let mut gains = vec![initial_gain];
+
+    if let Some(promotion) = mv.promotion {
+        gains[0] += piece_value(promotion) - piece_value(Piece::Pawn);
+        target_piece = promotion;
+    }
 
     'exchange: loop {
         for attacker_piece in Piece::ALL {
             let our_attacker = attackers & board.colored_pieces(colour, attacker_piece);
 
             if let Some(attacker_square) = our_attacker.next_square() {
-                let victim_value = piece_value(target_piece);
-                gains.push(victim_value);
+                let mut gain = piece_value(target_piece);
 
                 if target_piece == Piece::King {
+                    gains.push(gain);
                     break;
                 }
 
+                on_capture(attacker_square);
+
                 blockers ^= attacker_square.bitboard();
                 attackers ^= attacker_square.bitboard();
 
                 target_piece = attacker_piece;
 
+                // recaptures onto the last rank promote, assume to a queen
+                if attacker_piece == Piece::Pawn && promotion_rank {
+                    gain += piece_value(Piece::Queen) - piece_value(Piece::Pawn);
+                    target_piece = Piece::Queen;
+                }
+
+                gains.push(gain);
+
                 if matches!(attacker_piece, Piece::Rook | Piece::Queen) {
                     attackers |= get_rook_moves(target_square, blockers)
                         & blockers
@@ -96,3 +131,75 @@ const fn piece_value(piece: Piece) -> Eval {
         Piece::King => 10000,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::is_capture;
+
+    fn see_uci(fen: &str, mv: &str) -> Eval {
+        let board: Board = fen.parse().unwrap();
+        let mv = cozy_chess::util::parse_uci_move(&board, mv).unwrap();
+
+        assert!(is_capture(&board, mv));
+
+        see(&board, mv)
+    }
+
+    #[test]
+    fn test_see_en_passant() {
+        assert_eq!(see_uci("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 100);
+        assert_eq!(see_uci("4k3/2p5/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"), 0);
+    }
+
+    #[test]
+    fn test_see_promotion() {
+        assert_eq!(see_uci("1r2k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8q"), 1300);
+        assert_eq!(see_uci("1rr1k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7b8q"), 400);
+    }
+
+    #[test]
+    fn test_see_recapture_promotes() {
+        // the rook wins the knight, but the b2 pawn recaptures and promotes
+        assert_eq!(
+            see_uci("2R5/8/8/k7/8/8/1p6/2n4K w - - 0 1", "c8c1"),
+            320 - 1300
+        );
+    }
+
+    #[test]
+    fn test_see_quiet_moves() {
+        let see_quiet = |fen: &str, mv: &str| {
+            let board: Board = fen.parse().unwrap();
+            see(
+                &board,
+                cozy_chess::util::parse_uci_move(&board, mv).unwrap(),
+            )
+        };
+
+        // the knight steps onto a square the pawn covers
+        assert_eq!(see_quiet("4k3/8/4p3/8/8/2N5/8/4K3 w - - 0 1", "c3d5"), -320);
+        assert_eq!(see_quiet("4k3/8/8/8/8/2N5/8/4K3 w - - 0 1", "c3d5"), 0);
+        assert_eq!(see_quiet("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"), 0);
+    }
+
+    #[test]
+    fn test_see_traced() {
+        let board: Board = "4k3/8/5n2/3p4/4P3/8/8/3QK3 w - - 0 1".parse().unwrap();
+        let mv = cozy_chess::util::parse_uci_move(&board, "e4d5").unwrap();
+
+        let mut captures = Vec::new();
+        let value = see_traced(&board, mv, |square| captures.push(square));
+
+        assert_eq!(value, see(&board, mv));
+        assert_eq!(captures, [Square::F6, Square::D1]);
+    }
+
+    #[test]
+    fn test_castling_is_not_a_capture() {
+        let board: Board = "4k3/8/8/8/8/8/8/4K2R w K - 0 1".parse().unwrap();
+        let castle = cozy_chess::util::parse_uci_move(&board, "e1g1").unwrap();
+
+        assert!(!is_capture(&board, castle));
+    }
+}
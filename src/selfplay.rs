@@ -0,0 +1,514 @@
+use crate::random_board::{random_board, Rng};
+use core::{
+    fmt::{self, Display, Formatter},
+    time::Duration,
+};
+use cozy_chess::{util::parse_uci_move, Board, Color, GameStatus, Piece};
+use std::{
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    time::Instant,
+};
+
+pub const DEFAULT_TIME_CONTROL: &str = "8+0.08";
+pub const DEFAULT_GAMES: usize = 20_000;
+
+const RANDOM_OPENING_PLIES: usize = 8;
+const MAX_GAME_PLIES: usize = 500;
+
+/// Written as `path[,Name=value...]`.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub path: PathBuf,
+    pub options: Vec<(String, String)>,
+}
+
+impl EngineConfig {
+    #[must_use]
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut parts = spec.split(',');
+
+        let path = PathBuf::from(parts.next().filter(|path| !path.is_empty())?);
+        let options = parts
+            .map(|option| {
+                let (name, value) = option.split_once('=')?;
+
+                Some((name.trim().to_string(), value.trim().to_string()))
+            })
+            .collect::<Option<_>>()?;
+
+        Some(Self { path, options })
+    }
+}
+
+/// Written as `seconds[+increment]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl TimeControl {
+    #[must_use]
+    pub fn parse(text: &str) -> Option<Self> {
+        let (base, increment) = text.split_once('+').unwrap_or((text, "0"));
+
+        Some(Self {
+            base: Duration::try_from_secs_f64(base.parse().ok()?).ok()?,
+            increment: Duration::try_from_secs_f64(increment.parse().ok()?).ok()?,
+        })
+    }
+}
+
+/// From the first engine's side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Score {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Score {
+    #[must_use]
+    pub const fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    #[must_use]
+    pub fn ratio(&self) -> f64 {
+        (f64::from(self.wins) + f64::from(self.draws) / 2.0) / f64::from(self.games().max(1))
+    }
+
+    #[must_use]
+    pub fn elo(&self) -> f64 {
+        let ratio = self.ratio().clamp(1e-3, 1.0 - 1e-3);
+
+        -400.0 * (1.0 / ratio - 1.0).log10()
+    }
+
+    const fn add(&mut self, result: GameResult) {
+        match result {
+            GameResult::Win => self.wins += 1,
+            GameResult::Draw => self.draws += 1,
+            GameResult::Loss => self.losses += 1,
+        }
+    }
+}
+
+impl Display for Score {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "+{} ={} -{}", self.wins, self.draws, self.losses)
+    }
+}
+
+/// Wrong at most `alpha` of the time when the first engine is `elo0` stronger,
+/// and `beta` of the time when it's `elo1`.
+#[derive(Debug, Clone, Copy)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Default for Sprt {
+    fn default() -> Self {
+        Self {
+            elo0: 0.0,
+            elo1: 5.0,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SprtResult {
+    Accept,
+    Reject,
+}
+
+impl Sprt {
+    /// Approximates the results as normally distributed.
+    #[must_use]
+    pub fn llr(&self, score: &Score) -> f64 {
+        let games = f64::from(score.games());
+        let ratio = score.ratio();
+
+        let deviations = [
+            (score.wins, 1.0 - ratio),
+            (score.draws, 0.5 - ratio),
+            (score.losses, -ratio),
+        ];
+        let variance = deviations
+            .into_iter()
+            .map(|(count, deviation)| f64::from(count) * deviation.powi(2))
+            .sum::<f64>()
+            / games.max(1.0);
+
+        if variance <= 0.0 {
+            return 0.0;
+        }
+
+        let expected = |elo: f64| 1.0 / (1.0 + 10f64.powf(-elo / 400.0));
+        let (s0, s1) = (expected(self.elo0), expected(self.elo1));
+
+        games * (s1 - s0) * (2.0f64.mul_add(ratio, -s0) - s1) / (2.0 * variance)
+    }
+
+    #[must_use]
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    #[must_use]
+    pub fn result(&self, score: &Score) -> Option<SprtResult> {
+        let llr = self.llr(score);
+        let (lower, upper) = self.bounds();
+
+        if llr >= upper {
+            Some(SprtResult::Accept)
+        } else if llr <= lower {
+            Some(SprtResult::Reject)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MatchConfig {
+    pub engines: [EngineConfig; 2],
+    pub time_control: TimeControl,
+    /// Random openings are used if there are none.
+    pub openings: Vec<Board>,
+    pub games: usize,
+    pub sprt: Sprt,
+}
+
+pub fn run(
+    config: &MatchConfig,
+    mut on_game: impl FnMut(&Score, Option<SprtResult>),
+) -> std::io::Result<(Score, Option<SprtResult>)> {
+    let mut engines = [
+        UciProcess::start(&config.engines[0])?,
+        UciProcess::start(&config.engines[1])?,
+    ];
+
+    let mut rng = Rng::new(0x5eed);
+    let mut score = Score::default();
+
+    for pair in 0.. {
+        let opening = if config.openings.is_empty() {
+            random_board(&mut rng, RANDOM_OPENING_PLIES)
+        } else {
+            config.openings[pair % config.openings.len()].clone()
+        };
+
+        for first_is_white in [true, false] {
+            if score.games() as usize >= config.games {
+                return Ok((score, None));
+            }
+
+            let (white, black) = engines.split_at_mut(1);
+            let (white, black) = if first_is_white {
+                (&mut white[0], &mut black[0])
+            } else {
+                (&mut black[0], &mut white[0])
+            };
+
+            let result = play_game(white, black, &opening, config.time_control)?;
+            let result = if first_is_white {
+                result
+            } else {
+                result.flip()
+            };
+
+            score.add(result);
+
+            let sprt = config.sprt.result(&score);
+
+            on_game(&score, sprt);
+
+            if sprt.is_some() {
+                return Ok((score, sprt));
+            }
+        }
+    }
+
+    unreachable!()
+}
+
+pub fn load_openings(path: &Path) -> std::io::Result<(Vec<Board>, usize)> {
+    let mut openings = Vec::new();
+    let mut skipped = 0;
+
+    for line in BufReader::new(std::fs::File::open(path)?).lines() {
+        let line = line?;
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+
+        if fields.is_empty() {
+            continue;
+        }
+
+        let fen = if fields.len() >= 6 && fields[4].parse::<u32>().is_ok() {
+            fields[..6].join(" ")
+        } else {
+            format!("{} 0 1", fields[..fields.len().min(4)].join(" "))
+        };
+
+        match Board::from_fen(&fen, false) {
+            Ok(board) => openings.push(board),
+            Err(_) => skipped += 1,
+        }
+    }
+
+    Ok((openings, skipped))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GameResult {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl GameResult {
+    const fn flip(self) -> Self {
+        match self {
+            Self::Win => Self::Loss,
+            Self::Draw => Self::Draw,
+            Self::Loss => Self::Win,
+        }
+    }
+
+    const fn loss_for(color: Color) -> Self {
+        match color {
+            Color::White => Self::Loss,
+            Color::Black => Self::Win,
+        }
+    }
+}
+
+fn play_game(
+    white: &mut UciProcess,
+    black: &mut UciProcess,
+    opening: &Board,
+    time_control: TimeControl,
+) -> std::io::Result<GameResult> {
+    for engine in [&mut *white, &mut *black] {
+        engine.send("ucinewgame")?;
+        engine.send("isready")?;
+        engine.read_until("readyok")?;
+    }
+
+    let start_fen = opening.to_string();
+    let mut board = opening.clone();
+    let mut moves = Vec::new();
+    let mut hashes = vec![board.hash()];
+    let mut clocks = [time_control.base; Color::NUM];
+
+    loop {
+        match board.status() {
+            GameStatus::Won => return Ok(GameResult::loss_for(board.side_to_move())),
+            GameStatus::Drawn => return Ok(GameResult::Draw),
+            GameStatus::Ongoing => {}
+        }
+
+        let is_repetition = hashes.iter().filter(|&&hash| hash == board.hash()).count() >= 3;
+
+        if is_repetition || is_insufficient_material(&board) || moves.len() >= MAX_GAME_PLIES {
+            return Ok(GameResult::Draw);
+        }
+
+        let side = board.side_to_move();
+        let engine = match side {
+            Color::White => &mut *white,
+            Color::Black => &mut *black,
+        };
+
+        if moves.is_empty() {
+            engine.send(&format!("position fen {start_fen}"))?;
+        } else {
+            engine.send(&format!(
+                "position fen {start_fen} moves {}",
+                moves.join(" ")
+            ))?;
+        }
+        engine.send(&format!(
+            "go wtime {} btime {} winc {} binc {}",
+            clocks[Color::White as usize].as_millis(),
+            clocks[Color::Black as usize].as_millis(),
+            time_control.increment.as_millis(),
+            time_control.increment.as_millis(),
+        ))?;
+
+        let start = Instant::now();
+        let reply = engine.read_until("bestmove")?;
+        let elapsed = start.elapsed();
+
+        let Some(clock) = clocks[side as usize].checked_sub(elapsed) else {
+            return Ok(GameResult::loss_for(side));
+        };
+        clocks[side as usize] = clock + time_control.increment;
+
+        let mv = reply.split_whitespace().nth(1).unwrap_or_default();
+
+        match parse_uci_move(&board, mv) {
+            Ok(legal) if board.try_play(legal).is_ok() => {}
+            // an illegal move loses
+            _ => return Ok(GameResult::loss_for(side)),
+        }
+
+        moves.push(mv.to_string());
+        hashes.push(board.hash());
+    }
+}
+
+#[must_use]
+pub fn is_insufficient_material(board: &Board) -> bool {
+    let minors = board.pieces(Piece::Knight) | board.pieces(Piece::Bishop);
+
+    board.occupied().len() == 2 || (board.occupied().len() == 3 && minors.len() == 1)
+}
+
+#[derive(Debug)]
+struct UciProcess {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl UciProcess {
+    fn start(config: &EngineConfig) -> std::io::Result<Self> {
+        let mut child = Command::new(&config.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        let mut process = Self {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+        };
+
+        process.send("uci")?;
+        process.read_until("uciok")?;
+
+        for (name, value) in &config.options {
+            process.send(&format!("setoption name {name} value {value}"))?;
+        }
+
+        Ok(process)
+    }
+
+    fn send(&mut self, command: &str) -> std::io::Result<()> {
+        writeln!(self.stdin, "{command}")?;
+        self.stdin.flush()
+    }
+
+    fn read_until(&mut self, prefix: &str) -> std::io::Result<String> {
+        loop {
+            let mut line = String::new();
+
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "engine exited",
+                ));
+            }
+
+            if line.starts_with(prefix) {
+                return Ok(line);
+            }
+        }
+    }
+}
+
+impl Drop for UciProcess {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let engine = EngineConfig::parse("./eccat,Hash=32,Threads=1").unwrap();
+
+        assert_eq!(engine.path, Path::new("./eccat"));
+        assert_eq!(
+            engine.options,
+            [
+                ("Hash".to_string(), "32".to_string()),
+                ("Threads".to_string(), "1".to_string())
+            ]
+        );
+        assert!(EngineConfig::parse("./eccat,Hash").is_none());
+
+        let time_control = TimeControl::parse("8+0.08").unwrap();
+
+        assert_eq!(time_control.base, Duration::from_secs(8));
+        assert_eq!(time_control.increment, Duration::from_millis(80));
+        assert_eq!(TimeControl::parse("60").unwrap().increment, Duration::ZERO);
+        assert!(TimeControl::parse("-1").is_none());
+    }
+
+    #[test]
+    fn test_sprt() {
+        let sprt = Sprt::default();
+
+        let even = Score {
+            wins: 100,
+            draws: 200,
+            losses: 100,
+        };
+        let ahead = Score {
+            wins: 700,
+            draws: 1000,
+            losses: 500,
+        };
+
+        assert!(even.elo().abs() < 1e-9);
+        assert!(ahead.elo() > 30.0);
+        assert!(sprt.llr(&even) < 0.0);
+        assert!(sprt.llr(&ahead) > 0.0);
+        assert_eq!(sprt.result(&ahead), Some(SprtResult::Accept));
+        assert_eq!(sprt.result(&Score::default()), None);
+
+        let behind = Score {
+            wins: ahead.losses,
+            draws: ahead.draws,
+            losses: ahead.wins,
+        };
+
+        assert_eq!(sprt.result(&behind), Some(SprtResult::Reject));
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        let fen = |fen: &str| Board::from_fen(fen, false).unwrap();
+
+        assert!(is_insufficient_material(&fen(
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1"
+        )));
+        assert!(is_insufficient_material(&fen(
+            "8/8/4k3/8/8/3KN3/8/8 w - - 0 1"
+        )));
+        assert!(!is_insufficient_material(&fen(
+            "8/8/4k3/8/8/3K3R/8/8 w - - 0 1"
+        )));
+        assert!(!is_insufficient_material(&fen(
+            "8/8/4k3/8/8/3KP3/8/8 w - - 0 1"
+        )));
+    }
+}
@@ -0,0 +1,166 @@
+use crate::{
+    io::{ReaderInput, WriterOutput},
+    Engine, SharedHash,
+};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+};
+
+pub const DEFAULT_SESSIONS: usize = 1;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Address {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(std::path::PathBuf),
+}
+
+impl Address {
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        #[cfg(unix)]
+        if let Some(path) = text.strip_prefix("unix:") {
+            return Self::Unix(path.into());
+        }
+
+        Self::Tcp(text.to_string())
+    }
+}
+
+#[derive(Debug)]
+pub struct ServerConfig {
+    pub address: Address,
+    pub sessions: usize,
+    /// `None` for a table per session.
+    pub shared_hash: Option<usize>,
+    pub options: Vec<(String, String)>,
+}
+
+#[derive(Debug)]
+pub enum ServerEvent {
+    /// With the port picked if `0` was asked for.
+    Listening {
+        address: String,
+    },
+    Connected {
+        session: usize,
+    },
+    Disconnected {
+        session: usize,
+    },
+    Refused,
+    AcceptFailed(String),
+    Error {
+        session: usize,
+        error: String,
+    },
+}
+
+pub fn run(config: &ServerConfig, on_event: impl Fn(ServerEvent) + Sync) -> std::io::Result<()> {
+    let shared_hash = config.shared_hash.map(SharedHash::new);
+    let active = AtomicUsize::new(0);
+    let mut sessions = 0;
+
+    std::thread::scope(|scope| {
+        let mut serve = |stream: std::io::Result<(Box<dyn Read + Send>, Box<dyn Write + Send>)>| {
+            let (reader, mut writer) = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    on_event(ServerEvent::AcceptFailed(error.to_string()));
+                    return;
+                }
+            };
+
+            if active.fetch_add(1, Ordering::Relaxed) >= config.sessions {
+                active.fetch_sub(1, Ordering::Relaxed);
+
+                let _ = writeln!(writer, "info string error: every session is taken");
+                on_event(ServerEvent::Refused);
+                return;
+            }
+
+            sessions += 1;
+
+            let session = sessions;
+            let (shared_hash, active, on_event) = (&shared_hash, &active, &on_event);
+
+            scope.spawn(move || {
+                on_event(ServerEvent::Connected { session });
+
+                let mut engine = Engine::new()
+                    .with_options(config.options.clone())
+                    .with_io(ReaderInput::new(reader), WriterOutput(writer));
+
+                if let Some(hash) = shared_hash {
+                    engine = engine.with_shared_hash(hash.clone());
+                }
+
+                if let Err(error) = engine.main_loop() {
+                    on_event(ServerEvent::Error {
+                        session,
+                        error: error.to_string(),
+                    });
+                }
+
+                active.fetch_sub(1, Ordering::Relaxed);
+                on_event(ServerEvent::Disconnected { session });
+            });
+        };
+
+        match &config.address {
+            Address::Tcp(address) => {
+                let listener = TcpListener::bind(address)?;
+
+                on_event(ServerEvent::Listening {
+                    address: listener.local_addr()?.to_string(),
+                });
+
+                for stream in listener.incoming() {
+                    serve(stream.and_then(|stream| Ok(split(stream.try_clone()?, stream))));
+                }
+            }
+            #[cfg(unix)]
+            Address::Unix(path) => {
+                let listener = std::os::unix::net::UnixListener::bind(path)?;
+
+                on_event(ServerEvent::Listening {
+                    address: format!("unix:{}", path.display()),
+                });
+
+                for stream in listener.incoming() {
+                    serve(stream.and_then(|stream| Ok(split(stream.try_clone()?, stream))));
+                }
+            }
+        }
+
+        Ok(())
+    })
+}
+
+fn split<S: Read + Write + Send + 'static>(
+    stream: S,
+    clone: S,
+) -> (Box<dyn Read + Send>, Box<dyn Write + Send>) {
+    (Box::new(clone), Box::new(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address() {
+        assert_eq!(
+            Address::parse("127.0.0.1:7000"),
+            Address::Tcp("127.0.0.1:7000".to_string())
+        );
+
+        #[cfg(unix)]
+        assert_eq!(
+            Address::parse("unix:/tmp/eccat.sock"),
+            Address::Unix("/tmp/eccat.sock".into())
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::{
+    gamedb::GameRecord,
+    search::{Heuristics, History},
+    tt::{Entry, TranspositionTable},
+};
+use cozy_chess::Board;
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    path::Path,
+};
+
+const MAGIC: &[u8; 4] = b"ECST";
+const FORMAT_VERSION: u8 = 3;
+
+#[derive(Debug)]
+pub struct EngineState {
+    pub board: Board,
+    pub history: Vec<History>,
+    pub options: Vec<(String, String)>,
+    pub game: Option<GameRecord>,
+    pub heuristics: Heuristics,
+}
+
+pub fn save(
+    path: &Path,
+    state: &EngineState,
+    transposition_table: &TranspositionTable,
+) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    out.write_all(MAGIC)?;
+    out.write_all(&[FORMAT_VERSION])?;
+
+    write_str(&mut out, &state.board.to_string())?;
+
+    write_len(&mut out, state.history.len())?;
+
+    for entry in &state.history {
+        out.write_all(&entry.hash.to_le_bytes())?;
+    }
+
+    write_len(&mut out, state.options.len())?;
+
+    for (name, value) in &state.options {
+        write_str(&mut out, name)?;
+        write_str(&mut out, value)?;
+    }
+
+    match &state.game {
+        Some(game) => {
+            let encoded = game.encode();
+
+            write_len(&mut out, encoded.len())?;
+            out.write_all(&encoded)?;
+        }
+        None => write_len(&mut out, 0)?,
+    }
+
+    for score in state.heuristics.scores() {
+        out.write_all(&score.to_le_bytes())?;
+    }
+
+    write_len(&mut out, transposition_table.buckets())?;
+    write_len(&mut out, transposition_table.occupied().count())?;
+
+    for (bucket, slot, entry) in transposition_table.occupied() {
+        write_len(&mut out, bucket)?;
+        out.write_all(&[u8::try_from(slot).map_err(Error::other)?])?;
+        out.write_all(&entry.to_bytes())?;
+    }
+
+    out.flush()
+}
+
+pub fn load(path: &Path) -> std::io::Result<(EngineState, TranspositionTable)> {
+    let mut input = BufReader::new(File::open(path)?);
+
+    if &read_array::<4>(&mut input)? != MAGIC {
+        return Err(invalid_data("not an eccat state file"));
+    }
+
+    let [version] = read_array(&mut input)?;
+
+    if version != FORMAT_VERSION {
+        return Err(invalid_data(format!(
+            "unsupported state file version {version}"
+        )));
+    }
+
+    let board = read_str(&mut input)?
+        .parse::<Board>()
+        .map_err(|err| invalid_data(format!("invalid position: {err}")))?;
+
+    let history = (0..read_len(&mut input)?)
+        .map(|_| {
+            Ok(History {
+                hash: u64::from_le_bytes(read_array(&mut input)?),
+            })
+        })
+        .collect::<std::io::Result<_>>()?;
+
+    let options = (0..read_len(&mut input)?)
+        .map(|_| Ok((read_str(&mut input)?, read_str(&mut input)?)))
+        .collect::<std::io::Result<_>>()?;
+
+    let game = match read_len(&mut input)? {
+        0 => None,
+        len => Some(
+            GameRecord::decode(&read_vec(&mut input, len)?)
+                .ok_or_else(|| invalid_data("invalid recorded game"))?,
+        ),
+    };
+
+    let mut heuristics = Heuristics::default();
+
+    for score in heuristics.scores_mut() {
+        *score = i32::from_le_bytes(read_array(&mut input)?);
+    }
+
+    let transposition_table = TranspositionTable::with_buckets(read_len(&mut input)?);
+
+    for _ in 0..read_len(&mut input)? {
+        let bucket = read_len(&mut input)?;
+        let [slot] = read_array(&mut input)?;
+
+        let entry = Entry::from_bytes(read_array(&mut input)?)
+            .ok_or_else(|| invalid_data("invalid hash table entry"))?;
+
+        if !transposition_table.restore(bucket, usize::from(slot), entry) {
+            return Err(invalid_data("hash table entry out of range"));
+        }
+    }
+
+    Ok((
+        EngineState {
+            board,
+            history,
+            options,
+            game,
+            heuristics,
+        },
+        transposition_table,
+    ))
+}
+
+fn invalid_data(error: impl Into<Box<dyn core::error::Error + Send + Sync>>) -> Error {
+    Error::new(ErrorKind::InvalidData, error)
+}
+
+fn write_len(out: &mut impl Write, len: usize) -> std::io::Result<()> {
+    let len = u32::try_from(len).map_err(Error::other)?;
+
+    out.write_all(&len.to_le_bytes())
+}
+
+fn write_str(out: &mut impl Write, s: &str) -> std::io::Result<()> {
+    write_len(out, s.len())?;
+    out.write_all(s.as_bytes())
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+
+    input.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+fn read_len(input: &mut impl Read) -> std::io::Result<usize> {
+    usize::try_from(u32::from_le_bytes(read_array(input)?)).map_err(Error::other)
+}
+
+fn read_vec(input: &mut impl Read, len: usize) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+
+    input.take(len as u64).read_to_end(&mut bytes)?;
+
+    if bytes.len() == len {
+        Ok(bytes)
+    } else {
+        Err(ErrorKind::UnexpectedEof.into())
+    }
+}
+
+fn read_str(input: &mut impl Read) -> std::io::Result<String> {
+    let len = read_len(input)?;
+
+    String::from_utf8(read_vec(input, len)?).map_err(invalid_data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tt::Flag;
+
+    #[test]
+    fn test_roundtrip() {
+        let board: Board = "r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3"
+            .parse()
+            .unwrap();
+
+        let mut state = EngineState {
+            board: board.clone(),
+            history: vec![History { hash: 1 }, History { hash: board.hash() }],
+            options: vec![("Hash".to_string(), "1".to_string())],
+            game: None,
+            heuristics: Heuristics::default(),
+        };
+
+        // one quiet history score and one capture history score
+        *state.heuristics.scores_mut().nth(1000).unwrap() = -300;
+        *state.heuristics.scores_mut().last().unwrap() = 1200;
+
+        let transposition_table = TranspositionTable::new(1);
+        let best_move = "f1b5".parse().unwrap();
+
+        transposition_table.insert(Entry::new(
+            board.hash(),
+            7,
+            0,
+            Flag::Beta,
+            42,
+            Some(best_move),
+        ));
+
+        let path = std::env::temp_dir().join(format!("eccat-state-{}.bin", std::process::id()));
+
+        save(&path, &state, &transposition_table).unwrap();
+        let loaded = load(&path);
+        let _ = std::fs::remove_file(&path);
+
+        let (loaded, loaded_table) = loaded.unwrap();
+
+        assert_eq!(loaded.board, state.board);
+        assert_eq!(loaded.history.len(), 2);
+        assert_eq!(loaded.options, state.options);
+        assert!(loaded.heuristics.scores().eq(state.heuristics.scores()));
+        assert_eq!(loaded_table.hashfull(), transposition_table.hashfull());
+
+        let (_, tt_move) = loaded_table.probe(board.hash()).unwrap().get(7, 0, 0, 1);
+        assert_eq!(tt_move, Some(best_move));
+    }
+}
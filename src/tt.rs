@@ -1,34 +1,52 @@
-use crate::evaluate::{Eval, EVAL_INFINITY};
+use crate::evaluate::{Eval, MATE_BOUND};
 use assert_size::assert_size;
-use cozy_chess::{Board, Move};
+use core::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use cozy_chess::{Board, Move, Piece, Square};
+use std::sync::{Arc, RwLock};
 
+/// Each slot is one atomic word holding the entry and the top 16 bits of its
+/// key, so threads writing it at once can't mix entries.
 #[derive(Debug)]
 pub struct TranspositionTable {
     table: Box<[Bucket]>,
-    total_entries: usize,
-    used_entries: usize,
+    generation: AtomicU8,
 }
 
+/// The lock is only taken to swap in a new table or take the current one, so
+/// a search never holds it.
+pub type TableHandle = Arc<RwLock<Arc<TranspositionTable>>>;
+
 impl TranspositionTable {
     #[must_use]
     pub fn new(mb_size: usize) -> Self {
         let bytes = mb_size * 1024 * 1024;
         let bucket_size = core::mem::size_of::<Bucket>();
-        let total_buckets = bytes / bucket_size;
 
+        Self::with_buckets(bytes / bucket_size)
+    }
+
+    #[must_use]
+    pub fn with_buckets(total_buckets: usize) -> Self {
         debug_assert!(u32::try_from(total_buckets).is_ok());
 
-        let table = vec![Bucket::default(); total_buckets];
+        let table = (0..total_buckets).map(|_| Bucket::default()).collect();
 
         Self {
-            table: table.into_boxed_slice(),
-            total_entries: total_buckets * Bucket::ENTRIES,
-            used_entries: 0,
+            table,
+            generation: AtomicU8::new(0),
         }
     }
 
+    pub fn new_search(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed) & GENERATION_MASK
+    }
+
     #[must_use]
-    pub fn probe(&self, key: u64) -> Option<&Entry> {
+    pub fn probe(&self, key: u64) -> Option<Entry> {
         if self.table.is_empty() {
             return None;
         }
@@ -38,49 +56,93 @@ impl TranspositionTable {
         self.table[index]
             .entries
             .iter()
-            .find(|&entry| entry.key == key)
+            .filter_map(Slot::load)
+            .find(|entry| verification_key(entry.key) == verification_key(key))
+            .map(|entry| Entry { key, ..entry })
     }
 
-    pub fn insert(&mut self, entry: Entry) {
+    pub fn insert(&self, entry: Entry) {
         if self.table.is_empty() {
             return;
         }
 
         let index = self.hash_idx(entry.key);
-
-        self.table[index].store(entry, &mut self.used_entries);
+        let generation = self.generation();
+
+        self.table[index].store(
+            Entry {
+                generation,
+                ..entry
+            },
+            generation,
+        );
     }
 
     const fn hash_idx(&self, key: u64) -> usize {
         (((key & 0xffff_ffff) * self.table.len() as u64) >> u32::BITS) as usize
     }
 
-    pub fn resize(&mut self, mb_size: usize) {
-        *self = Self::new(mb_size);
-    }
-
     #[must_use]
     pub fn hashfull(&self) -> u16 {
-        #[allow(
-            clippy::cast_precision_loss,
-            clippy::cast_possible_truncation,
-            clippy::cast_sign_loss
-        )]
-        if self.table.len() > 0 {
-            ((self.used_entries as f64 / self.total_entries as f64) * 1000f64).floor() as u16
-        } else {
-            0
-        }
+        let sample = &self.table[..self.table.len().min(1000 / Bucket::ENTRIES)];
+
+        let slots = sample.len() * Bucket::ENTRIES;
+        let generation = self.generation();
+        let used = sample
+            .iter()
+            .flat_map(|bucket| &bucket.entries)
+            .filter_map(Slot::load)
+            .filter(|entry| entry.generation == generation)
+            .count();
+
+        #[allow(clippy::cast_possible_truncation)]
+        let hashfull = (used * 1000).checked_div(slots).unwrap_or(0) as u16;
+
+        hashfull
     }
 
-    pub fn clear(&mut self) {
-        for bucket in &mut self.table {
-            for entry in &mut bucket.entries {
-                *entry = Entry::default();
-            }
-        }
+    pub fn buckets(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Only the top 16 bits of the entries' keys are known.
+    pub fn occupied(&self) -> impl Iterator<Item = (usize, usize, Entry)> + '_ {
+        self.table
+            .iter()
+            .enumerate()
+            .flat_map(|(bucket_idx, bucket)| {
+                bucket
+                    .entries
+                    .iter()
+                    .map(Slot::load)
+                    .enumerate()
+                    .filter_map(move |(slot, entry)| Some((bucket_idx, slot, entry?)))
+            })
+    }
 
-        self.used_entries = 0;
+    pub fn restore(&self, bucket: usize, slot: usize, entry: Entry) -> bool {
+        let Some(target) = self
+            .table
+            .get(bucket)
+            .and_then(|bucket| bucket.entries.get(slot))
+        else {
+            return false;
+        };
+
+        target.store(Entry {
+            generation: self.generation(),
+            ..entry
+        });
+
+        true
+    }
+
+    pub fn clear(&self) {
+        let _span = tracing::debug_span!("tt_clear", buckets = self.table.len()).entered();
+
+        for slot in self.table.iter().flat_map(|bucket| &bucket.entries) {
+            slot.clear();
+        }
     }
 
     #[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
@@ -108,32 +170,83 @@ impl TranspositionTable {
 }
 
 assert_size!(Bucket, 64);
-assert_size!(Entry, 16);
+assert_size!(Slot, 8);
 
-#[derive(Clone, Copy, Debug, Default)]
+/// Only 6 bits of the generation are kept.
+const GENERATION_MASK: u8 = 0x3f;
+
+/// The bucket is picked by the low bits of the key, so a slot keeps the high
+/// ones.
+#[allow(clippy::cast_possible_truncation)]
+const fn verification_key(key: u64) -> u16 {
+    (key >> 48) as u16
+}
+
+#[derive(Debug, Default)]
 struct Bucket {
-    entries: [Entry; Self::ENTRIES],
+    entries: [Slot; Self::ENTRIES],
 }
 
 impl Bucket {
-    const ENTRIES: usize = 64 / core::mem::size_of::<Entry>();
+    const ENTRIES: usize = 64 / core::mem::size_of::<Slot>();
+
+    /// Plies of depth each generation of age is worth when picking an entry to
+    /// replace.
+    const AGE_WEIGHT: i32 = 8;
+
+    /// An entry for the same position is only replaced by one searched at least
+    /// as deep or with an exact score. Otherwise the shallowest, oldest entry goes.
+    fn store(&self, entry: Entry, generation: u8) {
+        let same_position = self.entries.iter().find_map(|slot| {
+            slot.load()
+                .filter(|stored| verification_key(stored.key) == verification_key(entry.key))
+                .map(|stored| (slot, stored))
+        });
+
+        if let Some((slot, stored)) = same_position {
+            if entry.depth >= stored.depth || matches!(entry.flag, Flag::Exact) {
+                slot.store(entry);
+            }
+
+            return;
+        }
+
+        let mut worst_index = 0;
+        let mut worst_value = None;
 
-    fn store(&mut self, entry: Entry, used_entries: &mut usize) {
-        let mut lowest_depth_index = 0;
-        let mut lowest_depth = self.entries[lowest_depth_index].depth;
+        for (i, slot) in self.entries.iter().enumerate() {
+            let Some(stored) = slot.load() else {
+                slot.store(entry);
+                return;
+            };
 
-        for i in 1..Self::ENTRIES {
-            if self.entries[i].depth < lowest_depth {
-                lowest_depth_index = i;
-                lowest_depth = self.entries[i].depth;
+            let age = generation.wrapping_sub(stored.generation) & GENERATION_MASK;
+            let value = i32::from(stored.depth) - Self::AGE_WEIGHT * i32::from(age);
+
+            if worst_value.is_none_or(|worst| value < worst) {
+                worst_index = i;
+                worst_value = Some(value);
             }
         }
 
-        if self.entries[lowest_depth_index].depth == 0 {
-            *used_entries += 1;
-        }
+        self.entries[worst_index].store(entry);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Slot(AtomicU64);
+
+impl Slot {
+    fn load(&self) -> Option<Entry> {
+        Entry::unpack(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, entry: Entry) {
+        self.0.store(entry.pack(), Ordering::Relaxed);
+    }
 
-        self.entries[lowest_depth_index] = entry;
+    fn clear(&self) {
+        self.0.store(0, Ordering::Relaxed);
     }
 }
 
@@ -144,23 +257,35 @@ pub struct Entry {
     flag: Flag,
     score: Eval,
     best_move: Option<Move>,
+    generation: u8,
 }
 
 impl Entry {
     #[must_use]
+    /// Mate scores are stored counting from the position rather than the root.
     pub const fn new(
         key: u64,
         depth: u8,
+        ply: u8,
         flag: Flag,
         score: Eval,
         best_move: Option<Move>,
     ) -> Self {
+        let score = if score > MATE_BOUND {
+            score + ply as Eval
+        } else if score < -MATE_BOUND {
+            score - ply as Eval
+        } else {
+            score
+        };
+
         Self {
             key,
             depth,
             flag,
             score,
             best_move,
+            generation: 0,
         }
     }
 
@@ -175,25 +300,19 @@ impl Entry {
         let mut value = None;
 
         if self.depth >= depth {
+            let score = self.score(ply);
+
             match self.flag {
                 Flag::Exact => {
-                    let mut score = self.score;
-
-                    if score > EVAL_INFINITY - 256 {
-                        score -= ply as Eval;
-                    } else if score < 256 - EVAL_INFINITY {
-                        score += ply as Eval;
-                    }
-
                     value = Some(score);
                 }
                 Flag::Alpha => {
-                    if self.score <= alpha {
+                    if score <= alpha {
                         value = Some(alpha);
                     }
                 }
                 Flag::Beta => {
-                    if self.score >= beta {
+                    if score >= beta {
                         value = Some(beta);
                     }
                 }
@@ -203,7 +322,58 @@ impl Entry {
         (value, self.best_move)
     }
 
-    pub const fn info(&self) -> EntryInfo {
+    #[must_use]
+    pub const fn score(&self, ply: u8) -> Eval {
+        if self.score > MATE_BOUND {
+            self.score - ply as Eval
+        } else if self.score < -MATE_BOUND {
+            self.score + ply as Eval
+        } else {
+            self.score
+        }
+    }
+
+    /// Depth in bits 0-7, flag 8-9, generation 10-15, score 16-31, move 32-46 and
+    /// verification key 48-63. Bit 47 is set so no entry packs to 0, an empty slot.
+    const fn pack(self) -> u64 {
+        1 << 47
+            | self.depth as u64
+            | (encode_flag(self.flag) as u64) << 8
+            | ((self.generation & GENERATION_MASK) as u64) << 10
+            | (self.score.cast_unsigned() as u64) << 16
+            | (encode_move(self.best_move) as u64) << 32
+            | (verification_key(self.key) as u64) << 48
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn unpack(data: u64) -> Option<Self> {
+        if data == 0 {
+            return None;
+        }
+
+        Some(Self {
+            key: data & 0xffff << 48,
+            depth: data as u8,
+            flag: decode_flag((data >> 8) as u8 & 0x3)?,
+            generation: (data >> 10) as u8 & GENERATION_MASK,
+            score: ((data >> 16) as u16).cast_signed(),
+            best_move: decode_move((data >> 32) as u16 & 0x7fff)?,
+        })
+    }
+
+    pub const ENCODED_SIZE: usize = 8;
+
+    #[must_use]
+    pub const fn to_bytes(self) -> [u8; Self::ENCODED_SIZE] {
+        self.pack().to_le_bytes()
+    }
+
+    #[must_use]
+    pub fn from_bytes(bytes: [u8; Self::ENCODED_SIZE]) -> Option<Self> {
+        Self::unpack(u64::from_le_bytes(bytes))
+    }
+
+    pub const fn info(&self) -> EntryInfo<'_> {
         EntryInfo {
             key: &self.key,
             depth: &self.depth,
@@ -214,6 +384,53 @@ impl Entry {
     }
 }
 
+const fn encode_flag(flag: Flag) -> u8 {
+    match flag {
+        Flag::Exact => 0,
+        Flag::Alpha => 1,
+        Flag::Beta => 2,
+    }
+}
+
+const fn decode_flag(flag: u8) -> Option<Flag> {
+    match flag {
+        0 => Some(Flag::Exact),
+        1 => Some(Flag::Alpha),
+        2 => Some(Flag::Beta),
+        _ => None,
+    }
+}
+
+/// 0 is never a real move, as from and to would be the same square.
+const fn encode_move(mv: Option<Move>) -> u16 {
+    let Some(mv) = mv else {
+        return 0;
+    };
+
+    let promotion = match mv.promotion {
+        Some(piece) => piece as u16 + 1,
+        None => 0,
+    };
+
+    mv.from as u16 | (mv.to as u16) << 6 | promotion << 12
+}
+
+#[allow(clippy::option_option)]
+fn decode_move(mv: u16) -> Option<Option<Move>> {
+    if mv == 0 {
+        return Some(None);
+    }
+
+    Some(Some(Move {
+        from: Square::try_index(usize::from(mv & 0x3f))?,
+        to: Square::try_index(usize::from(mv >> 6 & 0x3f))?,
+        promotion: match mv >> 12 {
+            0 => None,
+            piece => Some(Piece::try_index(usize::from(piece - 1))?),
+        },
+    }))
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub enum Flag {
     #[default]
@@ -229,3 +446,141 @@ pub struct EntryInfo<'a> {
     pub score: &'a Eval,
     pub best_move: &'a Option<Move>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::EVAL_INFINITY;
+
+    #[allow(clippy::cast_possible_truncation)]
+    const fn derived_entry(key: u64) -> Entry {
+        Entry::new(
+            key,
+            (key >> 48) as u8,
+            0,
+            Flag::Exact,
+            (key >> 52) as Eval,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_mate_scores_count_from_the_position() {
+        let tt = TranspositionTable::with_buckets(1);
+
+        // mated at ply 9 of the search, 5 plies after the position at ply 4
+        tt.insert(Entry::new(
+            1 << 48,
+            5,
+            4,
+            Flag::Exact,
+            EVAL_INFINITY - 9,
+            None,
+        ));
+        tt.insert(Entry::new(
+            2 << 48,
+            5,
+            4,
+            Flag::Exact,
+            -EVAL_INFINITY + 9,
+            None,
+        ));
+
+        // reached again at ply 2, the mates are 7 plies from the root
+        assert_eq!(tt.probe(1 << 48).unwrap().score(2), EVAL_INFINITY - 7);
+        assert_eq!(tt.probe(2 << 48).unwrap().score(2), -EVAL_INFINITY + 7);
+
+        // and the bounds are compared with the adjusted score too
+        tt.insert(Entry::new(
+            3 << 48,
+            5,
+            4,
+            Flag::Beta,
+            EVAL_INFINITY - 9,
+            None,
+        ));
+
+        let (value, _) = tt.probe(3 << 48).unwrap().get(5, 2, 0, EVAL_INFINITY - 8);
+        assert_eq!(value, Some(EVAL_INFINITY - 8));
+
+        // plain scores are left alone
+        tt.insert(Entry::new(4 << 48, 5, 4, Flag::Exact, 150, None));
+        assert_eq!(tt.probe(4 << 48).unwrap().score(2), 150);
+    }
+
+    #[test]
+    fn test_old_generations_are_replaced_first() {
+        let tt = TranspositionTable::with_buckets(1);
+
+        for key in 0..Bucket::ENTRIES as u64 {
+            tt.insert(Entry::new(key << 48, 20, 0, Flag::Exact, 0, None));
+        }
+
+        for _ in 0..3 {
+            tt.new_search();
+        }
+
+        // the stale deep entries go before the fresh shallow one does
+        tt.insert(Entry::new(100 << 48, 5, 0, Flag::Exact, 0, None));
+        tt.insert(Entry::new(101 << 48, 6, 0, Flag::Exact, 0, None));
+
+        assert!(tt.probe(100 << 48).is_some());
+        assert!(tt.probe(101 << 48).is_some());
+        assert_eq!(tt.hashfull(), 250);
+    }
+
+    #[test]
+    fn test_same_position_replacement() {
+        let tt = TranspositionTable::with_buckets(1);
+
+        // an empty slot ahead of the one already holding the position
+        tt.insert(Entry::new(1 << 48, 1, 0, Flag::Exact, 0, None));
+        tt.insert(Entry::new(2 << 48, 8, 0, Flag::Beta, 40, None));
+        tt.table[0].entries[0].clear();
+
+        // a shallower bound leaves the deeper entry where it is
+        tt.insert(Entry::new(2 << 48, 0, 0, Flag::Beta, 10, None));
+
+        assert_eq!(tt.probe(2 << 48).unwrap().depth, 8);
+        assert_eq!(tt.occupied().count(), 1);
+
+        // an exact score replaces it whatever its depth
+        tt.insert(Entry::new(2 << 48, 0, 0, Flag::Exact, 25, None));
+
+        assert_eq!(tt.probe(2 << 48).unwrap().score, 25);
+        assert_eq!(tt.occupied().count(), 1);
+    }
+
+    #[test]
+    fn test_concurrent_writes_never_mix_entries() {
+        // a handful of buckets, so the threads keep overwriting each other
+        let tt = TranspositionTable::with_buckets(4);
+
+        std::thread::scope(|scope| {
+            for thread in 0..4_u64 {
+                let tt = &tt;
+
+                scope.spawn(move || {
+                    let mut key = thread.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+
+                    for _ in 0..100_000 {
+                        key = key.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+
+                        tt.insert(derived_entry(key));
+
+                        if let Some(entry) = tt.probe(key) {
+                            let expected = derived_entry(entry.key);
+
+                            assert_eq!(entry.depth, expected.depth);
+                            assert_eq!(entry.score, expected.score);
+                        }
+                    }
+                });
+            }
+        });
+
+        for (_, _, entry) in tt.occupied() {
+            assert_eq!(entry.score, derived_entry(entry.key).score);
+        }
+    }
+}
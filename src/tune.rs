@@ -0,0 +1,481 @@
+use crate::{
+    evaluate::{
+        evaluate_traced, Eval, Term, Trace, KING_SAFETY_TABLE_SIZE, PAWN_SHIELD_SIZE,
+        PAWN_STORM_SIZE,
+    },
+    position::Position,
+};
+use core::fmt::{self, Display, Formatter};
+use cozy_chess::{Board, Piece};
+use std::{
+    io::{BufRead, BufReader},
+    path::Path,
+};
+
+pub const DEFAULT_EPOCHS: usize = 2000;
+
+const LEARNING_RATE: f64 = 1.0;
+
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+const PIECE_SQUARE_START: usize = 6;
+const PASSED_PAWN_START: usize = PIECE_SQUARE_START + 6 * 64;
+const PAWN_SHIELD_START: usize = PASSED_PAWN_START + 8;
+const PAWN_STORM_START: usize = PAWN_SHIELD_START + PAWN_SHIELD_SIZE;
+const SINGLE_START: usize = PAWN_STORM_START + PAWN_STORM_SIZE;
+const KING_SAFETY_START: usize = SINGLE_START + SINGLE_TERMS.len();
+const TERM_COUNT: usize = KING_SAFETY_START + KING_SAFETY_TABLE_SIZE;
+
+const fn index(term: Term) -> usize {
+    match term {
+        Term::Material(piece) => piece as usize,
+        Term::PieceSquare(piece, square_idx) => {
+            PIECE_SQUARE_START + piece as usize * 64 + square_idx
+        }
+        Term::PassedPawn(rank) => PASSED_PAWN_START + rank,
+        Term::PawnShield(distance) => PAWN_SHIELD_START + distance,
+        Term::PawnStorm(distance) => PAWN_STORM_START + distance,
+        Term::DoubledPawns => SINGLE_START,
+        Term::IsolatedPawn => SINGLE_START + 1,
+        Term::BackwardPawn => SINGLE_START + 2,
+        Term::BishopPair => SINGLE_START + 3,
+        Term::Tempo => SINGLE_START + 4,
+        Term::KingSafety(units) => KING_SAFETY_START + units,
+    }
+}
+
+const SINGLE_TERMS: [(&str, Term); 5] = [
+    ("DOUBLED_PAWNS_PENALTY", Term::DoubledPawns),
+    ("ISOLATED_PAWN_PENALTY", Term::IsolatedPawn),
+    ("BACKWARD_PAWN_PENALTY", Term::BackwardPawn),
+    ("BISHOP_PAIR_BONUS", Term::BishopPair),
+    ("TEMPO", Term::Tempo),
+];
+
+fn terms() -> Vec<Term> {
+    let mut terms = Piece::ALL.map(Term::Material).to_vec();
+
+    for piece in Piece::ALL {
+        terms.extend((0..64).map(|square_idx| Term::PieceSquare(piece, square_idx)));
+    }
+
+    terms.extend((0..8).map(Term::PassedPawn));
+    terms.extend((0..PAWN_SHIELD_SIZE).map(Term::PawnShield));
+    terms.extend((0..PAWN_STORM_SIZE).map(Term::PawnStorm));
+    terms.extend(SINGLE_TERMS.map(|(_, term)| term));
+    terms.extend((0..KING_SAFETY_TABLE_SIZE).map(Term::KingSafety));
+
+    terms
+}
+
+#[derive(Debug)]
+struct Entry {
+    counts: Vec<(u16, Eval)>,
+    /// Middlegame share, from 0 to 1.
+    phase: f64,
+    /// For white: 1 for a win, 0.5 for a draw, 0 for a loss.
+    result: f64,
+}
+
+impl Entry {
+    fn new(board: Board, result: f64) -> Self {
+        struct Counts([Eval; TERM_COUNT]);
+
+        impl Trace for Counts {
+            fn add(&mut self, term: Term, count: Eval) {
+                self.0[index(term)] += count;
+            }
+        }
+
+        let position = Position::new(board);
+        let mut counts = Counts([0; TERM_COUNT]);
+
+        evaluate_traced(&position, &mut counts);
+
+        #[allow(clippy::cast_possible_truncation)]
+        let counts = (0..TERM_COUNT)
+            .filter(|&idx| counts.0[idx] != 0)
+            .map(|idx| (idx as u16, counts.0[idx]))
+            .collect();
+
+        Self {
+            counts,
+            phase: f64::from(position.phase().min(24)) / 24.0,
+            result,
+        }
+    }
+}
+
+/// Texel tuning: the evaluation, squashed through a sigmoid, predicts each
+/// position's result, and the squared error of that is minimised.
+#[derive(Debug)]
+pub struct Tuner {
+    entries: Vec<Entry>,
+    weights: Vec<[f64; 2]>,
+    /// Scales centipawns to the sigmoid.
+    k: f64,
+}
+
+impl Tuner {
+    /// One position per line: a FEN and the game's result, as `1-0`, `0-1`,
+    /// `1/2-1/2` or `[1.0]`, `[0.5]`, `[0.0]`. Also returns how many lines couldn't
+    /// be read.
+    pub fn load(path: &Path) -> std::io::Result<(Self, usize)> {
+        let mut entries = Vec::new();
+        let mut skipped = 0;
+
+        for line in BufReader::new(std::fs::File::open(path)?).lines() {
+            match parse_line(&line?) {
+                Some((board, result)) => entries.push(Entry::new(board, result)),
+                None => skipped += 1,
+            }
+        }
+
+        Ok((Self::new(entries), skipped))
+    }
+
+    fn new(entries: Vec<Entry>) -> Self {
+        let weights = terms()
+            .into_iter()
+            .map(|term| {
+                let (mg, eg) = term.value();
+                [f64::from(mg), f64::from(eg)]
+            })
+            .collect();
+
+        let mut tuner = Self {
+            entries,
+            weights,
+            k: 1.0,
+        };
+
+        tuner.k = tuner.best_k();
+
+        tuner
+    }
+
+    #[must_use]
+    pub const fn positions(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[must_use]
+    pub const fn k(&self) -> f64 {
+        self.k
+    }
+
+    pub fn tune(&mut self, epochs: usize, mut progress: impl FnMut(usize, f64)) {
+        let mut momentum = vec![[0.0; 2]; TERM_COUNT];
+        let mut velocity = vec![[0.0; 2]; TERM_COUNT];
+
+        for epoch in 1..=epochs {
+            let gradient = self.gradient();
+
+            #[allow(clippy::cast_possible_truncation, clippy::cast_possible_wrap)]
+            let step = epoch as i32;
+
+            for idx in 0..TERM_COUNT {
+                for phase in 0..2 {
+                    let grad = gradient[idx][phase];
+
+                    momentum[idx][phase] =
+                        BETA1.mul_add(momentum[idx][phase], (1.0 - BETA1) * grad);
+                    velocity[idx][phase] =
+                        BETA2.mul_add(velocity[idx][phase], (1.0 - BETA2) * grad * grad);
+
+                    let momentum = momentum[idx][phase] / (1.0 - BETA1.powi(step));
+                    let velocity = velocity[idx][phase] / (1.0 - BETA2.powi(step));
+
+                    self.weights[idx][phase] -= LEARNING_RATE * momentum / (velocity.sqrt() + 1e-8);
+                }
+            }
+
+            progress(epoch, self.error(self.k));
+        }
+    }
+
+    #[must_use]
+    pub fn error(&self, k: f64) -> f64 {
+        let total = self.par_sum(0.0, |total: &mut f64, entry| {
+            *total += (entry.result - sigmoid(k, self.evaluate(entry))).powi(2);
+        });
+
+        #[allow(clippy::cast_precision_loss)]
+        let error = total / self.entries.len().max(1) as f64;
+
+        error
+    }
+
+    fn gradient(&self) -> Vec<[f64; 2]> {
+        self.par_sum(vec![[0.0; 2]; TERM_COUNT], |gradient, entry| {
+            let prediction = sigmoid(self.k, self.evaluate(entry));
+            let slope = (prediction - entry.result) * prediction * (1.0 - prediction);
+
+            for &(idx, count) in &entry.counts {
+                let count = f64::from(count) * slope;
+
+                gradient[idx as usize][0] += count * entry.phase;
+                gradient[idx as usize][1] += count * (1.0 - entry.phase);
+            }
+        })
+    }
+
+    fn par_sum<T>(&self, zero: T, fold: impl Fn(&mut T, &Entry) + Sync) -> T
+    where
+        T: Clone + Send + Sum,
+    {
+        let threads = std::thread::available_parallelism().map_or(1, Into::into);
+        let chunk_size = self.entries.len().div_ceil(threads).max(1);
+
+        std::thread::scope(|scope| {
+            let handles = self
+                .entries
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    let fold = &fold;
+                    let mut acc = zero.clone();
+
+                    scope.spawn(move || {
+                        for entry in chunk {
+                            fold(&mut acc, entry);
+                        }
+
+                        acc
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles.into_iter().fold(zero, |mut total, handle| {
+                total.add(handle.join().unwrap());
+                total
+            })
+        })
+    }
+
+    fn evaluate(&self, entry: &Entry) -> f64 {
+        let (mg, eg) = entry
+            .counts
+            .iter()
+            .fold((0.0, 0.0), |(mg, eg), &(idx, count)| {
+                let [mg_weight, eg_weight] = self.weights[idx as usize];
+                let count = f64::from(count);
+
+                (mg_weight.mul_add(count, mg), eg_weight.mul_add(count, eg))
+            });
+
+        mg.mul_add(entry.phase, eg * (1.0 - entry.phase))
+    }
+
+    fn best_k(&self) -> f64 {
+        let mut best = 1.0;
+        let mut step: f64 = 0.1;
+
+        for _ in 0..4 {
+            let start = step.mul_add(-10.0, best).max(0.0);
+
+            best = (0..=20)
+                .map(|i| f64::from(i).mul_add(step, start))
+                .min_by(|&a, &b| self.error(a).total_cmp(&self.error(b)))
+                .unwrap_or(best);
+
+            step /= 10.0;
+        }
+
+        best
+    }
+
+    fn rounded(&self, term: Term) -> [Eval; 2] {
+        #[allow(clippy::cast_possible_truncation)]
+        self.weights[index(term)].map(|weight| weight.round() as Eval)
+    }
+}
+
+trait Sum {
+    fn add(&mut self, other: Self);
+}
+
+impl Sum for f64 {
+    fn add(&mut self, other: Self) {
+        *self += other;
+    }
+}
+
+impl Sum for Vec<[f64; 2]> {
+    fn add(&mut self, other: Self) {
+        for (total, part) in self.iter_mut().zip(other) {
+            total[0] += part[0];
+            total[1] += part[1];
+        }
+    }
+}
+
+/// As the constants of `evaluate.rs`, ready to be pasted over the old ones.
+impl Display for Tuner {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        self.write_list(f, "PIECE_VALUES", 6, |idx| Term::Material(Piece::ALL[idx]))?;
+        self.write_list(f, "PASSED_PAWN_BONUS", 8, Term::PassedPawn)?;
+        self.write_list(f, "PAWN_SHIELD", PAWN_SHIELD_SIZE, Term::PawnShield)?;
+        self.write_list(f, "PAWN_STORM", PAWN_STORM_SIZE, Term::PawnStorm)?;
+
+        for (name, term) in SINGLE_TERMS {
+            let [mg, eg] = self.rounded(term);
+
+            writeln!(f, "const MG_{name}: Eval = {mg};")?;
+            writeln!(f, "const EG_{name}: Eval = {eg};")?;
+            writeln!(f)?;
+        }
+
+        for piece in Piece::ALL {
+            let name = format!("{piece:?}").to_uppercase();
+
+            self.write_table(f, &format!("{name}_TABLE"), 64, |square_idx| {
+                Term::PieceSquare(piece, square_idx)
+            })?;
+        }
+
+        self.write_table(
+            f,
+            "KING_SAFETY_TABLE",
+            KING_SAFETY_TABLE_SIZE,
+            Term::KingSafety,
+        )
+    }
+}
+
+impl Tuner {
+    fn write_list(
+        &self,
+        f: &mut Formatter<'_>,
+        name: &str,
+        len: usize,
+        term: impl Fn(usize) -> Term,
+    ) -> fmt::Result {
+        for (prefix, phase) in [("MG", 0), ("EG", 1)] {
+            let weights = (0..len)
+                .map(|idx| self.rounded(term(idx))[phase].to_string())
+                .collect::<Vec<_>>();
+
+            writeln!(
+                f,
+                "const {prefix}_{name}: [Eval; {len}] = [{}];",
+                weights.join(", ")
+            )?;
+        }
+
+        writeln!(f)
+    }
+
+    fn write_table(
+        &self,
+        f: &mut Formatter<'_>,
+        name: &str,
+        len: usize,
+        term: impl Fn(usize) -> Term,
+    ) -> fmt::Result {
+        for (prefix, phase) in [("MG", 0), ("EG", 1)] {
+            writeln!(f, "#[rustfmt::skip]")?;
+            writeln!(f, "const {prefix}_{name}: [Eval; {len}] = [")?;
+
+            for row in (0..len).step_by(8) {
+                for idx in row..row + 8 {
+                    write!(f, "{:>4},", self.rounded(term(idx))[phase])?;
+                }
+
+                writeln!(f)?;
+            }
+
+            writeln!(f, "];")?;
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn sigmoid(k: f64, eval: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval / 400.0))
+}
+
+fn parse_line(line: &str) -> Option<(Board, f64)> {
+    let mut fields = line.split_whitespace().collect::<Vec<_>>();
+
+    let result = match fields
+        .pop()?
+        .trim_matches(|c| matches!(c, '"' | ';' | '[' | ']'))
+    {
+        "1-0" | "1.0" | "1" => 1.0,
+        "1/2-1/2" | "0.5" => 0.5,
+        "0-1" | "0.0" | "0" => 0.0,
+        _ => return None,
+    };
+
+    if fields.last() == Some(&"c9") {
+        fields.pop();
+    }
+
+    let fen = match fields.len() {
+        4 => format!("{} 0 1", fields.join(" ")),
+        6 => fields.join(" "),
+        _ => return None,
+    };
+
+    Some((Board::from_fen(&fen, false).ok()?, result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::evaluate;
+    use cozy_chess::Color;
+
+    #[test]
+    fn test_index_matches_terms() {
+        let terms = terms();
+
+        assert_eq!(terms.len(), TERM_COUNT);
+
+        for (idx, &term) in terms.iter().enumerate() {
+            assert_eq!(index(term), idx);
+        }
+    }
+
+    #[test]
+    fn test_counts_reproduce_evaluation() {
+        let tuner = Tuner::new(Vec::new());
+
+        for fen in crate::bench::BENCH_POSITIONS
+            .iter()
+            .chain(&crate::bench::TACTICAL_POSITIONS)
+        {
+            let board = fen.parse::<Board>().unwrap();
+            let side = board.side_to_move();
+
+            let eval = evaluate(&Position::new(board.clone()));
+            let white_eval = match side {
+                Color::White => eval,
+                Color::Black => -eval,
+            };
+
+            let traced = tuner.evaluate(&Entry::new(board, 0.5));
+
+            assert!((traced - f64::from(white_eval)).abs() < 1.0, "{fen}");
+        }
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let startpos = Board::default();
+
+        assert_eq!(
+            parse_line("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 [1.0]"),
+            Some((startpos.clone(), 1.0))
+        );
+        assert_eq!(
+            parse_line(r#"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - c9 "1/2-1/2";"#),
+            Some((startpos, 0.5))
+        );
+        assert_eq!(parse_line("8/8/8/8/8/8/8/8 w - - 0 1 0-1"), None);
+        assert_eq!(parse_line("not a position"), None);
+    }
+}
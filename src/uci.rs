@@ -1,18 +1,30 @@
+#[cfg(feature = "gaviota")]
+use crate::GaviotaTbPathOption;
 use crate::{
-    evaluate::{Eval, EVAL_INFINITY},
+    evaluate::{Eval, EVAL_INFINITY, MATE_BOUND},
+    io::{Input, Output},
     pkg_authors,
-    search::History,
-    EngineOption as _, EngineReport, HashOption, ThreadsOption, VERSION_STR,
+    random_board::Start,
+    search::{Bound, History, SearchLimits},
+    wdl::Wdl,
+    AnalyseModeOption, ContemptOption, DeterministicOption, EngineError, EngineOption,
+    EngineReport, EvalNoiseOption, GameDatabaseOption, HashOption, MetricsAddressOption,
+    MoveOverheadOption, OnlineTablebaseOption, OpponentOption, OpponentScalingOption,
+    PermanentBrainOption, ShowWdlOption, SpinOption, ThreadsOption, TimeUsageOption, VERSION_STR,
 };
 use chrono::Duration;
-use core::{fmt::Display, str::FromStr};
+use core::{
+    fmt::{Debug, Display, Write as _},
+    str::FromStr,
+};
 use cozy_chess::{
     util::{display_uci_move, parse_uci_move},
-    Board, Move, MoveParseError,
+    Board, Move,
 };
 use crossbeam_channel::Sender;
 use std::thread::JoinHandle;
-use vampirc_uci::{UciInfoAttribute, UciMessage, UciMove, UciOptionConfig, UciTimeControl};
+
+const OUTPUT_CAPACITY: usize = 256;
 
 pub enum EngineToUci {
     Identify,
@@ -24,11 +36,20 @@ pub enum EngineToUci {
         seldepth: u8,
         time: Duration,
         cp: Eval,
+        bound: Bound,
+        wdl: Option<Wdl>,
         nodes: u64,
         nps: u64,
         hashfull: u16,
         pv: Vec<String>,
     },
+    CurrentMove {
+        depth: u8,
+        mv: String,
+        number: usize,
+    },
+    InfoString(String),
+    Text(String),
 }
 
 #[derive(Debug)]
@@ -37,42 +58,102 @@ pub enum UciToEngine {
     Debug(bool),
     IsReady,
     Register,
-    Position(Board, Vec<History>),
-    SetOption { name: String, value: Option<String> },
+    Position {
+        board: Board,
+        history: Vec<History>,
+        start_fen: String,
+        moves: Vec<String>,
+    },
+    SetOption {
+        name: String,
+        value: Option<String>,
+    },
     UciNewGame,
     Stop,
     PonderHit,
     Quit,
-    GoInfinite,
-    GoMoveTime(Duration),
-    GoGameTime(GameTime),
-    GoDepth(u8),
-    Unknown(Option<String>),
+    Go {
+        limits: SearchLimits,
+        ponder: bool,
+    },
 
     Eval,
     PrintBoard,
+    PrintFen,
     PrintOptions,
     PlayMove(String),
+    Flip,
+    Undo,
+    RandomPosition {
+        start: Start,
+        plies: Option<usize>,
+    },
+    See(String),
+    Moves,
     Help,
     Sleep(u64),
     Probe,
+    Games(Option<usize>),
+    Perft {
+        depth: u8,
+        divide: bool,
+    },
+    Bench(Option<u8>),
+    Metrics,
+    SaveState(String),
+    LoadState(String),
 }
 
-#[derive(Debug, Default)]
 pub struct Uci {
     report_handle: Option<JoinHandle<()>>,
     control_handle: Option<JoinHandle<()>>,
     control_tx: Option<Sender<EngineToUci>>,
+    input: Option<Box<dyn Input>>,
+    output: Option<Box<dyn Output>>,
+}
+
+impl Debug for Uci {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Uci")
+            .field("report_handle", &self.report_handle)
+            .field("control_handle", &self.control_handle)
+            .field("control_tx", &self.control_tx)
+            .finish_non_exhaustive()
+    }
 }
 
 impl Uci {
     pub fn new() -> Self {
-        Self::default()
+        Self::with_io(Box::new(std::io::stdin()), Box::new(std::io::stdout()))
+    }
+
+    pub fn with_io(input: Box<dyn Input>, output: Box<dyn Output>) -> Self {
+        Self {
+            report_handle: None,
+            control_handle: None,
+            control_tx: None,
+            input: Some(input),
+            output: Some(output),
+        }
     }
 
     pub fn init(&mut self, report_tx: Sender<EngineReport>) {
-        self.report_thread(report_tx);
-        self.control_thread();
+        if let (Some(input), Some(output)) = (self.input.take(), self.output.take()) {
+            self.report_thread(report_tx, input);
+            self.control_thread(output);
+        }
+    }
+
+    /// Anything reported after this goes to stderr.
+    pub fn shutdown(&mut self) {
+        self.control_tx = None;
+
+        for handle in [self.control_handle.take(), self.report_handle.take()]
+            .into_iter()
+            .flatten()
+        {
+            crate::join_timeout(handle);
+        }
     }
 
     pub fn send(&self, msg: EngineToUci) -> Result<(), crossbeam_channel::SendError<EngineToUci>> {
@@ -83,226 +164,137 @@ impl Uci {
         Ok(())
     }
 
-    fn report_thread(&mut self, report_tx: Sender<EngineReport>) {
-        let mut incoming_data = String::new();
-
-        let report_handle = std::thread::spawn(move || {
-            let mut quit = false;
-
-            while !quit {
-                std::io::stdin().read_line(&mut incoming_data).unwrap();
-
-                let msgs = vampirc_uci::parse_with_unknown(&incoming_data);
-
-                for msg in msgs {
-                    let report = match Self::handle_msg(msg) {
-                        Ok(report) => report,
-                        Err(err) => {
-                            report_tx.send(EngineReport::Error(err)).unwrap();
+    /// As an info string, since most guis never show stderr.
+    pub fn error(&self, message: impl Display) {
+        self.report("error", message);
+    }
 
-                            continue;
-                        }
-                    };
+    pub fn warning(&self, message: impl Display) {
+        self.report("warning", message);
+    }
 
-                    if matches!(report, UciToEngine::Quit) {
-                        quit = true;
-                    }
+    pub fn print(&self, text: impl Display) {
+        let _ = self.send(EngineToUci::Text(text.to_string()));
+    }
 
-                    report_tx.send(EngineReport::Uci(report)).unwrap();
-                }
+    fn report(&self, level: &str, message: impl Display) {
+        let info = format!("{level}: {message}");
 
-                incoming_data.clear();
-            }
-        });
+        let sent = self
+            .control_tx
+            .as_ref()
+            .is_some_and(|tx| tx.send(EngineToUci::InfoString(info.clone())).is_ok());
 
-        self.report_handle = Some(report_handle);
+        if !sent {
+            eprintln!("{info}");
+        }
     }
 
-    fn handle_msg(msg: UciMessage) -> Result<UciToEngine, String> {
-        match msg {
-            UciMessage::Uci => Ok(UciToEngine::Uci),
-
-            UciMessage::Debug(debug) => Ok(UciToEngine::Debug(debug)),
-
-            UciMessage::IsReady => Ok(UciToEngine::IsReady),
-
-            UciMessage::Register {
-                later: _,
-                name: _,
-                code: _,
-            } => Ok(UciToEngine::Register),
-
-            UciMessage::Position {
-                startpos,
-                fen,
-                moves,
-            } => {
-                let fen = if startpos {
-                    String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
-                } else {
-                    fen.ok_or_else(|| "no fen string provided".to_string())?
-                        .to_string()
+    fn report_thread(&mut self, report_tx: Sender<EngineReport>, mut input: Box<dyn Input>) {
+        let report_handle = std::thread::spawn(move || {
+            let mut quit = false;
+
+            while !quit {
+                // nothing else can come, so the engine quits as if told to
+                let Some(incoming_data) = input.read_line() else {
+                    let _ = report_tx.send(EngineReport::Uci(UciToEngine::Quit));
+                    break;
                 };
 
-                let mut board = Board::from_str(&fen).map_err(|err| err.to_string())?;
-                let mut history = Vec::with_capacity(moves.len());
+                let report = match parse_line(&incoming_data) {
+                    Some(Ok(report)) => EngineReport::Uci(report),
+                    Some(Err(err)) => EngineReport::Error(err),
+                    None => continue,
+                };
 
-                for m in &moves {
-                    board
-                        .try_play(convert_move_from_uci(&board, m).map_err(|err| err.to_string())?)
-                        .map_err(|err| format!("{m}: {err}"))?;
+                quit = matches!(report, EngineReport::Uci(UciToEngine::Quit));
 
-                    history.push(History { hash: board.hash() });
+                // the engine has shut down
+                if report_tx.send(report).is_err() {
+                    break;
                 }
-
-                Ok(UciToEngine::Position(board, history))
-            }
-
-            UciMessage::SetOption { name, value } => Ok(UciToEngine::SetOption { name, value }),
-
-            UciMessage::UciNewGame => Ok(UciToEngine::UciNewGame),
-
-            UciMessage::Stop => Ok(UciToEngine::Stop),
-
-            UciMessage::PonderHit => Ok(UciToEngine::PonderHit),
-
-            UciMessage::Quit => Ok(UciToEngine::Quit),
-
-            UciMessage::Go {
-                time_control,
-                search_control,
-            } => time_control.map_or_else(
-                || {
-                    search_control.map_or_else(
-                        || unreachable!(),
-                        |search_control| {
-                            search_control.depth.map_or_else(
-                                || Err(format!("{search_control:?} not supported")),
-                                |depth| Ok(UciToEngine::GoDepth(depth)),
-                            )
-                        },
-                    )
-                },
-                |time_control| match time_control {
-                    UciTimeControl::Ponder => Err("ponder not supported".to_string()),
-                    UciTimeControl::Infinite => Ok(UciToEngine::GoInfinite),
-                    UciTimeControl::TimeLeft {
-                        white_time,
-                        black_time,
-                        white_increment,
-                        black_increment,
-                        moves_to_go,
-                    } => Ok(UciToEngine::GoGameTime(GameTime {
-                        white_time: white_time.unwrap_or_default(),
-                        black_time: black_time.unwrap_or_default(),
-                        white_increment: white_increment.unwrap_or_default(),
-                        black_increment: black_increment.unwrap_or_default(),
-                        moves_to_go,
-                    })),
-                    UciTimeControl::MoveTime(movetime) => Ok(UciToEngine::GoMoveTime(movetime)),
-                },
-            ),
-
-            UciMessage::Unknown(text, maybe_error) => {
-                custom_command(&text, maybe_error.map(|e| e.to_string()))
             }
+        });
 
-            UciMessage::Id { .. }
-            | UciMessage::UciOk
-            | UciMessage::ReadyOk
-            | UciMessage::BestMove { .. }
-            | UciMessage::CopyProtection(_)
-            | UciMessage::Registration(_)
-            | UciMessage::Option(_)
-            | UciMessage::Info(_) => Err("unexpected message".to_string()),
-        }
+        self.report_handle = Some(report_handle);
     }
 
-    fn control_thread(&mut self) {
-        let (control_tx, control_rx) = crossbeam_channel::unbounded();
+    fn control_thread(&mut self, mut output: Box<dyn Output>) {
+        let (control_tx, control_rx) = crossbeam_channel::bounded(OUTPUT_CAPACITY);
 
         let control_handle = std::thread::spawn(move || {
             let mut quit = false;
 
             while !quit {
-                let msg = control_rx.recv().unwrap();
+                // the engine shut down without quitting
+                let Ok(msg) = control_rx.recv() else {
+                    break;
+                };
 
                 match msg {
-                    EngineToUci::Identify => {
-                        println!("{}", UciMessage::id_name(VERSION_STR));
-                        println!("{}", UciMessage::id_author(&pkg_authors()));
-
-                        println!(
-                            "{}",
-                            UciMessage::Option(UciOptionConfig::Spin {
-                                name: HashOption::name().to_owned(),
-                                default: Some(HashOption::default()),
-                                min: Some(HashOption::min()),
-                                max: Some(HashOption::max()),
-                            })
-                        );
-
-                        println!(
-                            "{}",
-                            UciMessage::Option(UciOptionConfig::Spin {
-                                name: ThreadsOption::name().to_owned(),
-                                default: Some(ThreadsOption::default()),
-                                min: Some(ThreadsOption::min()),
-                                max: Some(ThreadsOption::max()),
-                            })
-                        );
-
-                        println!("{}", UciMessage::UciOk);
-                    }
-                    EngineToUci::Ready => println!("{}", UciMessage::ReadyOk),
+                    EngineToUci::Identify => identify(output.as_mut()),
+                    EngineToUci::Ready => output.write_line("readyok"),
                     EngineToUci::Quit => quit = true,
                     EngineToUci::BestMove(bestmove) => {
-                        println!("bestmove {bestmove}");
+                        output.write_line(&format!("bestmove {bestmove}"));
+                    }
+                    EngineToUci::InfoString(info) => {
+                        output.write_line(&format!("info string {info}"));
+                    }
+                    EngineToUci::CurrentMove { depth, mv, number } => {
+                        output.write_line(&format!(
+                            "info depth {depth} currmove {mv} currmovenumber {number}"
+                        ));
+                    }
+                    EngineToUci::Text(text) => {
+                        for line in text.lines() {
+                            output.write_line(line);
+                        }
                     }
                     EngineToUci::Summary {
                         depth,
                         seldepth,
                         time,
                         cp,
+                        bound,
+                        wdl,
                         nodes,
                         nps,
                         hashfull,
                         pv,
                     } => {
-                        let score = if cp.abs() > EVAL_INFINITY - 256 {
+                        let score = if cp.abs() > MATE_BOUND {
                             let mate_in_plies = EVAL_INFINITY - cp.abs();
-                            let sign = cp.signum();
-
                             let mate_in_moves = mate_in_plies / 2 + mate_in_plies % 2;
 
-                            UciInfoAttribute::from_mate((mate_in_moves * sign).try_into().unwrap())
+                            format!("mate {}", mate_in_moves * cp.signum())
                         } else {
-                            UciInfoAttribute::from_centipawns(cp.into())
+                            format!("cp {cp}")
+                        };
+
+                        let score = match bound {
+                            Bound::Exact => score,
+                            Bound::Lower => format!("{score} lowerbound"),
+                            Bound::Upper => format!("{score} upperbound"),
                         };
 
-                        println!(
-                            "{}{}",
-                            UciMessage::Info(vec![
-                                UciInfoAttribute::Depth(depth),
-                                UciInfoAttribute::SelDepth(seldepth),
-                                UciInfoAttribute::Time(time),
-                                score,
-                                UciInfoAttribute::Nodes(nodes),
-                                UciInfoAttribute::Nps(nps),
-                                UciInfoAttribute::HashFull(hashfull),
-                            ]),
-                            if pv.is_empty() {
-                                String::new()
-                            } else {
-                                format!(
-                                    " pv {}",
-                                    pv.iter()
-                                        .map(ToString::to_string)
-                                        .collect::<Vec<_>>()
-                                        .join(" ")
-                                )
-                            }
+                        let mut line = format!(
+                            "info depth {depth} seldepth {seldepth} time {} score {score}",
+                            time.num_milliseconds()
                         );
+
+                        // guis expect it right after the score
+                        if let Some(wdl) = wdl {
+                            let _ = write!(line, " wdl {wdl}");
+                        }
+
+                        let _ = write!(line, " nodes {nodes} nps {nps} hashfull {hashfull}");
+
+                        if !pv.is_empty() {
+                            let _ = write!(line, " pv {}", pv.join(" "));
+                        }
+
+                        output.write_line(&line);
                     }
                 }
             }
@@ -313,43 +305,346 @@ impl Uci {
     }
 }
 
-fn custom_command(text: &str, maybe_error: Option<String>) -> Result<UciToEngine, String> {
-    let split_cmd = text.split_whitespace().collect::<Vec<_>>();
+fn identify(output: &mut dyn Output) {
+    output.write_line(&format!("id name {VERSION_STR}"));
+    output.write_line(&format!("id author {}", pkg_authors()));
+
+    spin_option::<HashOption>(output);
+    spin_option::<ThreadsOption>(output);
+    spin_option::<TimeUsageOption>(output);
+    spin_option::<MoveOverheadOption>(output);
+    spin_option::<ContemptOption>(output);
+    string_option::<OpponentOption>(output);
+    check_option::<OpponentScalingOption>(output);
+    spin_option::<EvalNoiseOption>(output);
+    check_option::<AnalyseModeOption>(output);
+    check_option::<ShowWdlOption>(output);
+    check_option::<PermanentBrainOption>(output);
+    check_option::<OnlineTablebaseOption>(output);
+    check_option::<DeterministicOption>(output);
+    #[cfg(feature = "gaviota")]
+    string_option::<GaviotaTbPathOption>(output);
+    string_option::<GameDatabaseOption>(output);
+    string_option::<MetricsAddressOption>(output);
+
+    #[cfg(feature = "tune")]
+    for param in crate::params::Param::ALL {
+        let (min, max) = param.range();
+
+        output.write_line(&format!(
+            "option name {} type spin default {} min {min} max {max}",
+            param.name(),
+            param.default()
+        ));
+    }
+
+    output.write_line("uciok");
+}
+
+fn spin_option<O: SpinOption<Value = i64>>(output: &mut dyn Output) {
+    output.write_line(&format!(
+        "option name {} type spin default {} min {} max {}",
+        O::name(),
+        O::default(),
+        O::min(),
+        O::max()
+    ));
+}
+
+fn check_option<O: EngineOption<Value = bool>>(output: &mut dyn Output) {
+    output.write_line(&format!(
+        "option name {} type check default {}",
+        O::name(),
+        O::default()
+    ));
+}
+
+fn string_option<O: EngineOption<Value = String>>(output: &mut dyn Output) {
+    output.write_line(&format!(
+        "option name {} type string default {}",
+        O::name(),
+        O::default()
+    ));
+}
+
+/// Words before the first command are skipped, as the protocol asks.
+fn parse_line(line: &str) -> Option<Result<UciToEngine, EngineError>> {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+
+    if words.is_empty() {
+        return None;
+    }
+
+    let command =
+        (0..words.len()).find_map(|start| parse_command(&words[start..], after_words(line, start)));
+
+    Some(command.unwrap_or_else(|| Err(EngineError::UnknownCommand(line.trim().to_string()))))
+}
+
+fn after_words(text: &str, count: usize) -> &str {
+    (0..count).fold(text.trim(), |rest, _| {
+        rest.trim_start_matches(|c: char| !c.is_whitespace())
+            .trim_start()
+    })
+}
+
+fn parse_command(words: &[&str], text: &str) -> Option<Result<UciToEngine, EngineError>> {
+    let args = &words[1..];
+
+    let command = match words[0] {
+        "uci" => Ok(UciToEngine::Uci),
+        "debug" => match args.first() {
+            Some(&"on") => Ok(UciToEngine::Debug(true)),
+            Some(&"off") => Ok(UciToEngine::Debug(false)),
+            _ => Err(EngineError::Expected {
+                command: "debug",
+                expected: "on or off",
+            }),
+        },
+        "isready" => Ok(UciToEngine::IsReady),
+        "register" => Ok(UciToEngine::Register),
+        "setoption" => parse_setoption(args, text),
+        "ucinewgame" => Ok(UciToEngine::UciNewGame),
+        "position" => parse_position(args),
+        "go" => parse_go(args),
+        "stop" => Ok(UciToEngine::Stop),
+        "ponderhit" => Ok(UciToEngine::PonderHit),
+        "quit" => Ok(UciToEngine::Quit),
+        _ => return custom_command(words),
+    };
+
+    Some(command)
+}
+
+fn parse_setoption(args: &[&str], text: &str) -> Result<UciToEngine, EngineError> {
+    if args.first() != Some(&"name") {
+        return Err(EngineError::Expected {
+            command: "setoption",
+            expected: "name",
+        });
+    }
+
+    let value_at = args.iter().position(|&word| word == "value");
+    let name = args[1..value_at.unwrap_or(args.len())].join(" ");
+
+    if name.is_empty() {
+        return Err(missing("option name"));
+    }
 
-    match split_cmd.first() {
-        Some(&"eval") => Ok(UciToEngine::Eval),
-        Some(&"board") => Ok(UciToEngine::PrintBoard),
-        Some(&"options") => Ok(UciToEngine::PrintOptions),
-        Some(&"make") => {
-            let mv = split_cmd
-                .get(1)
-                .copied()
-                .ok_or_else(|| "no move provided".to_string())?;
+    // setoption and everything up to value come before it
+    let value = value_at.map(|at| after_words(text, at + 2).to_string());
 
-            Ok(UciToEngine::PlayMove(mv.to_string()))
+    Ok(UciToEngine::SetOption { name, value })
+}
+
+fn parse_position(args: &[&str]) -> Result<UciToEngine, EngineError> {
+    let moves_at = args
+        .iter()
+        .position(|&word| word == "moves")
+        .unwrap_or(args.len());
+
+    let fen = match args.first() {
+        Some(&"startpos") => {
+            String::from("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1")
         }
-        Some(&"help") => Ok(UciToEngine::Help),
-        Some(&"probe") => Ok(UciToEngine::Probe),
-        Some(&"sleep") => {
-            let sleep_time = split_cmd
-                .get(1)
-                .copied()
-                .ok_or_else(|| "no time provided".to_string())?;
-
-            let sleep_time = sleep_time
-                .parse::<u64>()
-                .map_err(|err| format!("invalid time: {err}"))?;
+        Some(&"fen") if moves_at > 1 => args[1..moves_at].join(" "),
+        Some(&"fen") => return Err(missing("fen string")),
+        _ => {
+            return Err(EngineError::Expected {
+                command: "position",
+                expected: "startpos or fen",
+            })
+        }
+    };
 
-            std::thread::sleep(core::time::Duration::from_millis(sleep_time));
+    let moves = args.get(moves_at + 1..).unwrap_or_default();
 
-            Ok(UciToEngine::Sleep(sleep_time))
+    let mut board = Board::from_str(&fen)?;
+    let mut history = Vec::with_capacity(moves.len());
+
+    let start_fen = board.to_string();
+
+    for m in moves {
+        let mv = parse_uci_move(&board, m).map_err(|_| EngineError::InvalidMove(m.to_string()))?;
+
+        board
+            .try_play(mv)
+            .map_err(|_| EngineError::IllegalMove(m.to_string()))?;
+
+        history.push(History { hash: board.hash() });
+    }
+
+    Ok(UciToEngine::Position {
+        board,
+        history,
+        start_fen,
+        moves: moves.iter().map(ToString::to_string).collect(),
+    })
+}
+
+const GO_PARAMETERS: [&str; 12] = [
+    "searchmoves",
+    "ponder",
+    "wtime",
+    "btime",
+    "winc",
+    "binc",
+    "movestogo",
+    "depth",
+    "nodes",
+    "mate",
+    "movetime",
+    "infinite",
+];
+
+fn parse_go(args: &[&str]) -> Result<UciToEngine, EngineError> {
+    let mut limits = SearchLimits::default();
+    let mut ponder = false;
+    let mut infinite = false;
+
+    let mut args = args.iter().copied().peekable();
+
+    while let Some(parameter) = args.next() {
+        match parameter {
+            "searchmoves" => {
+                while let Some(mv) = args.next_if(|word| !GO_PARAMETERS.contains(word)) {
+                    limits.search_moves.push(mv.to_string());
+                }
+            }
+            "ponder" => ponder = true,
+            "infinite" => infinite = true,
+            "wtime" => game_time(&mut limits).white_time = parse_time(parameter, args.next())?,
+            "btime" => game_time(&mut limits).black_time = parse_time(parameter, args.next())?,
+            "winc" => game_time(&mut limits).white_increment = parse_time(parameter, args.next())?,
+            "binc" => game_time(&mut limits).black_increment = parse_time(parameter, args.next())?,
+            "movestogo" => {
+                game_time(&mut limits).moves_to_go = Some(parse_value(parameter, args.next())?);
+            }
+            "depth" => limits.depth = Some(parse_value(parameter, args.next())?),
+            "nodes" => limits.nodes = Some(parse_value(parameter, args.next())?),
+            "mate" => limits.mate = Some(parse_value(parameter, args.next())?),
+            "movetime" => limits.move_time = Some(parse_time(parameter, args.next())?),
+            // unknown words are skipped, as the protocol asks
+            _ => {}
         }
+    }
 
-        _ => Err(maybe_error.unwrap_or_else(|| "unknown command".to_string())),
+    // infinite means only stop on stop, whatever else is given
+    if infinite {
+        limits = SearchLimits {
+            search_moves: limits.search_moves,
+            ..SearchLimits::default()
+        };
     }
+
+    Ok(UciToEngine::Go { limits, ponder })
 }
 
-#[derive(Debug)]
+fn game_time(limits: &mut SearchLimits) -> &mut GameTime {
+    limits.game_time.get_or_insert_default()
+}
+
+fn parse_value<T>(argument: &str, value: Option<&str>) -> Result<T, EngineError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value
+        .ok_or_else(|| missing(argument))?
+        .parse()
+        .map_err(|err| invalid(argument, err))
+}
+
+fn missing(argument: &str) -> EngineError {
+    EngineError::MissingArgument(argument.to_string())
+}
+
+fn invalid(argument: &str, reason: impl Display) -> EngineError {
+    EngineError::InvalidArgument {
+        argument: argument.to_string(),
+        reason: reason.to_string(),
+    }
+}
+
+/// Some guis send negative clock times once the engine is out of time.
+fn parse_time(parameter: &str, value: Option<&str>) -> Result<Duration, EngineError> {
+    parse_value(parameter, value).map(Duration::milliseconds)
+}
+
+fn custom_command(words: &[&str]) -> Option<Result<UciToEngine, EngineError>> {
+    let command = match words[0] {
+        "eval" => Ok(UciToEngine::Eval),
+        "d" | "board" => Ok(UciToEngine::PrintBoard),
+        "fen" => Ok(UciToEngine::PrintFen),
+        "options" => Ok(UciToEngine::PrintOptions),
+        "make" => words
+            .get(1)
+            .map(|mv| UciToEngine::PlayMove((*mv).to_string()))
+            .ok_or_else(|| missing("move")),
+        "flip" => Ok(UciToEngine::Flip),
+        "undo" => Ok(UciToEngine::Undo),
+        "randompos" => {
+            let (start, plies) = match words.get(1).copied() {
+                Some("chess960") => (Start::Chess960, words.get(2).copied()),
+                Some("dfrc") => (Start::Dfrc, words.get(2).copied()),
+                plies => (Start::Standard, plies),
+            };
+
+            plies
+                .map(str::parse::<usize>)
+                .transpose()
+                .map(|plies| UciToEngine::RandomPosition { start, plies })
+                .map_err(|err| invalid("number of moves", err))
+        }
+        "moves" => Ok(UciToEngine::Moves),
+        "see" => words
+            .get(1)
+            .map(|mv| UciToEngine::See((*mv).to_string()))
+            .ok_or_else(|| missing("move")),
+        "help" => Ok(UciToEngine::Help),
+        "probe" => Ok(UciToEngine::Probe),
+        "metrics" => Ok(UciToEngine::Metrics),
+        "perft" => {
+            let divide = words.get(1) == Some(&"divide");
+
+            words
+                .get(if divide { 2 } else { 1 })
+                .ok_or_else(|| missing("depth"))
+                .and_then(|depth| depth.parse::<u8>().map_err(|err| invalid("depth", err)))
+                .map(|depth| UciToEngine::Perft { depth, divide })
+        }
+        "bench" => words
+            .get(1)
+            .map(|depth| depth.parse::<u8>())
+            .transpose()
+            .map(UciToEngine::Bench)
+            .map_err(|err| invalid("depth", err)),
+        "games" => words
+            .get(1)
+            .map(|index| index.parse::<usize>())
+            .transpose()
+            .map(UciToEngine::Games)
+            .map_err(|err| invalid("game index", err)),
+        "savestate" => words
+            .get(1)
+            .map(|path| UciToEngine::SaveState((*path).to_string()))
+            .ok_or_else(|| missing("path")),
+        "loadstate" => words
+            .get(1)
+            .map(|path| UciToEngine::LoadState((*path).to_string()))
+            .ok_or_else(|| missing("path")),
+        "sleep" => parse_value::<u64>("sleep", words.get(1).copied()).map(|sleep_time| {
+            std::thread::sleep(core::time::Duration::from_millis(sleep_time));
+
+            UciToEngine::Sleep(sleep_time)
+        }),
+        _ => return None,
+    };
+
+    Some(command)
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct GameTime {
     pub white_time: Duration,
     pub black_time: Duration,
@@ -358,10 +653,111 @@ pub struct GameTime {
     pub moves_to_go: Option<u8>,
 }
 
-pub fn convert_move_from_uci(board: &Board, m: &UciMove) -> Result<Move, MoveParseError> {
-    parse_uci_move(board, &m.to_string())
-}
-
 pub fn convert_move_to_uci(board: &Board, m: Move) -> impl Display {
     display_uci_move(board, m)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn go(line: &str) -> (SearchLimits, bool) {
+        match parse_line(line) {
+            Some(Ok(UciToEngine::Go { limits, ponder })) => (limits, ponder),
+            other => panic!("not a go: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_go() {
+        let (limits, ponder) = go("go wtime 1000 btime -20 winc 10 movestogo 5 nodes 3000\n");
+        let game_time = limits.game_time.unwrap();
+
+        assert!(!ponder);
+        assert_eq!(game_time.white_time, Duration::milliseconds(1000));
+        assert_eq!(game_time.black_time, Duration::milliseconds(-20));
+        assert_eq!(game_time.white_increment, Duration::milliseconds(10));
+        assert_eq!(game_time.black_increment, Duration::zero());
+        assert_eq!(game_time.moves_to_go, Some(5));
+        assert_eq!(limits.nodes, Some(3000));
+
+        let (limits, _) = go("go searchmoves e2e4 d2d4 movetime 50 depth 3 mate 2");
+
+        assert_eq!(limits.search_moves, ["e2e4", "d2d4"]);
+        assert_eq!(limits.move_time, Some(Duration::milliseconds(50)));
+        assert_eq!(limits.depth, Some(3));
+        assert_eq!(limits.mate, Some(2));
+
+        let (limits, ponder) = go("go ponder wtime 1000 btime 1000");
+
+        assert!(ponder);
+        assert!(limits.game_time.is_some());
+
+        let (limits, _) = go("go infinite depth 3 searchmoves e2e4");
+
+        assert!(limits.is_infinite());
+        assert_eq!(limits.search_moves, ["e2e4"]);
+
+        assert!(matches!(parse_line("go depth"), Some(Err(_))));
+        assert!(matches!(parse_line("go nodes many"), Some(Err(_))));
+    }
+
+    #[test]
+    fn test_setoption() {
+        let Some(Ok(UciToEngine::SetOption { name, value })) =
+            parse_line("setoption name Eval Noise value  some  text \n")
+        else {
+            panic!("not a setoption");
+        };
+
+        assert_eq!(name, "Eval Noise");
+        assert_eq!(value.as_deref(), Some("some  text"));
+
+        let Some(Ok(UciToEngine::SetOption { name, value })) = parse_line("setoption name Clear")
+        else {
+            panic!("not a setoption");
+        };
+
+        assert_eq!(name, "Clear");
+        assert_eq!(value, None);
+    }
+
+    #[test]
+    fn test_position() {
+        let Some(Ok(UciToEngine::Position {
+            board,
+            history,
+            moves,
+            ..
+        })) = parse_line("position fen 7k/8/8/8/8/8/8/K7 w - - 0 1 moves a1a2 h8h7")
+        else {
+            panic!("not a position");
+        };
+
+        assert_eq!(board.to_string(), "8/7k/8/8/8/8/K7/8 w - - 2 2");
+        assert_eq!(history.len(), 2);
+        assert_eq!(moves, ["a1a2", "h8h7"]);
+
+        assert!(matches!(
+            parse_line("position startpos moves e2e5"),
+            Some(Err(EngineError::IllegalMove(mv))) if mv == "e2e5"
+        ));
+        assert!(matches!(
+            parse_line("position fen"),
+            Some(Err(EngineError::MissingArgument(_)))
+        ));
+    }
+
+    #[test]
+    fn test_unknown_words() {
+        assert!(matches!(
+            parse_line("joho debug on"),
+            Some(Ok(UciToEngine::Debug(true)))
+        ));
+        assert!(parse_line("   \n").is_none());
+        assert!(matches!(
+            parse_line("joho"),
+            Some(Err(EngineError::UnknownCommand(_)))
+        ));
+    }
+}
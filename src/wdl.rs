@@ -0,0 +1,111 @@
+use crate::evaluate::{Eval, MATE_BOUND};
+use core::fmt::Display;
+use cozy_chess::{Board, Piece};
+
+/// Material, in pawns, above which more no longer changes the model.
+const MAX_MATERIAL: u32 = 78;
+
+/// Out of 1000 games, for the side to move.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Wdl {
+    pub win: u16,
+    pub draw: u16,
+    pub loss: u16,
+}
+
+impl Wdl {
+    /// The same score wins more often with less material left to defend with.
+    #[must_use]
+    pub fn new(score: Eval, board: &Board) -> Self {
+        if score > MATE_BOUND {
+            return Self::decisive(true);
+        }
+
+        if score < -MATE_BOUND {
+            return Self::decisive(false);
+        }
+
+        let material = f64::from(material(board).min(MAX_MATERIAL));
+        let score = f64::from(score);
+
+        let win = win_rate(score, material);
+        let loss = win_rate(-score, material);
+
+        Self {
+            win,
+            draw: 1000u16.saturating_sub(win + loss),
+            loss,
+        }
+    }
+
+    const fn decisive(won: bool) -> Self {
+        Self {
+            win: if won { 1000 } else { 0 },
+            draw: 0,
+            loss: if won { 0 } else { 1000 },
+        }
+    }
+}
+
+impl Display for Wdl {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} {} {}", self.win, self.draw, self.loss)
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn win_rate(score: f64, material: f64) -> u16 {
+    let even = 3.0f64.mul_add(material, 100.0);
+    let spread = material + 50.0;
+
+    (1000.0 / (1.0 + ((even - score) / spread).exp())).round() as u16
+}
+
+fn material(board: &Board) -> u32 {
+    [
+        (Piece::Pawn, 1),
+        (Piece::Knight, 3),
+        (Piece::Bishop, 3),
+        (Piece::Rook, 5),
+        (Piece::Queen, 9),
+    ]
+    .into_iter()
+    .map(|(piece, value)| value * board.pieces(piece).len())
+    .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluate::EVAL_INFINITY;
+
+    #[test]
+    fn test_adds_up() {
+        let board = Board::default();
+
+        for score in (-1000..=1000).step_by(50) {
+            let wdl = Wdl::new(score, &board);
+
+            assert_eq!(wdl.win + wdl.draw + wdl.loss, 1000);
+            assert_eq!(Wdl::new(-score, &board).win, wdl.loss);
+        }
+    }
+
+    #[test]
+    fn test_material() {
+        let endgame = Board::from_fen("8/5k2/8/8/8/2K5/4P3/8 w - - 0 1", false).unwrap();
+        let opening = Board::default();
+
+        assert_eq!(Wdl::new(0, &opening).win, Wdl::new(0, &opening).loss);
+        assert!(Wdl::new(150, &endgame).win > Wdl::new(150, &opening).win);
+        assert!(Wdl::new(0, &opening).draw > 500);
+    }
+
+    #[test]
+    fn test_mate() {
+        let board = Board::default();
+
+        assert_eq!(Wdl::new(EVAL_INFINITY - 3, &board).to_string(), "1000 0 0");
+        assert_eq!(Wdl::new(-EVAL_INFINITY + 4, &board).to_string(), "0 0 1000");
+    }
+}
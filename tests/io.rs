@@ -0,0 +1,70 @@
+use eccat::Engine;
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn test_message_passing_io() {
+    let (input_tx, input_rx) = crossbeam_channel::unbounded();
+    let output = Arc::new(Mutex::new(Vec::new()));
+
+    for command in [
+        "uci",
+        "isready",
+        "position startpos moves e2e4",
+        "go depth 3",
+        "fen",
+        "quit",
+    ] {
+        input_tx.send(command.to_string()).unwrap();
+    }
+
+    let lines = Arc::clone(&output);
+
+    Engine::new()
+        .with_io(input_rx, move |line: &str| {
+            lines.lock().unwrap().push(line.to_string());
+        })
+        .main_loop()
+        .unwrap();
+
+    let output = output.lock().unwrap().clone();
+
+    assert!(output[0].starts_with("eccat v"));
+    assert!(output.iter().any(|line| line == "uciok"));
+    assert!(output.iter().any(|line| line == "readyok"));
+    assert!(output.iter().any(|line| line.starts_with("bestmove ")));
+    assert!(output
+        .iter()
+        .any(|line| line == "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"));
+}
+
+#[test]
+fn test_builder() {
+    let (input_tx, input_rx) = crossbeam_channel::unbounded();
+    let output = Arc::new(Mutex::new(Vec::new()));
+
+    for command in ["options", "go depth 2", "quit"] {
+        input_tx.send(command.to_string()).unwrap();
+    }
+
+    let lines = Arc::clone(&output);
+
+    Engine::builder()
+        .hash(2)
+        .threads(2)
+        .option("Contempt", "15")
+        .input(input_rx)
+        .output(move |line: &str| {
+            lines.lock().unwrap().push(line.to_string());
+        })
+        .build()
+        .unwrap()
+        .main_loop()
+        .unwrap();
+
+    let output = output.lock().unwrap().clone();
+
+    for option in ["Hash = 2", "Threads = 2", "Contempt = 15"] {
+        assert!(output.iter().any(|line| line.trim() == option));
+    }
+    assert!(output.iter().any(|line| line.starts_with("bestmove ")));
+}
@@ -0,0 +1,94 @@
+use cozy_chess::Board;
+use eccat::{Engine, EngineError, SearchLimits};
+
+#[test]
+fn test_search() {
+    let mut engine = Engine::new();
+
+    // mate in one with Qxf7
+    let board = Board::from_fen(
+        "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+        false,
+    )
+    .unwrap();
+
+    let result = engine
+        .search(
+            &board,
+            SearchLimits {
+                depth: Some(4),
+                ..SearchLimits::default()
+            },
+        )
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(result.best_move, "h5f7".parse().unwrap());
+    assert_eq!(result.pv, [result.best_move]);
+    assert_eq!(result.depth, 4);
+    assert!(result.score > 10_000);
+    assert!(result.nodes > 0);
+
+    // the same engine searches again, and reports positions with no moves
+    let mated = Board::from_fen(
+        "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4",
+        false,
+    )
+    .unwrap();
+
+    let limits = SearchLimits {
+        nodes: Some(1000),
+        ..SearchLimits::default()
+    };
+
+    assert_eq!(engine.search(&mated, limits).unwrap(), None);
+}
+
+#[test]
+fn test_search_options() {
+    let mut engine = Engine::new().with_options(vec![
+        ("Hash".to_string(), "1".to_string()),
+        ("Threads".to_string(), "1".to_string()),
+    ]);
+
+    let result = engine
+        .search(
+            &Board::default(),
+            SearchLimits {
+                nodes: Some(5000),
+                ..SearchLimits::default()
+            },
+        )
+        .unwrap()
+        .unwrap();
+
+    assert!(result.depth > 0);
+    assert_eq!(result.pv.first(), Some(&result.best_move));
+}
+
+#[test]
+fn test_search_option_error() {
+    let mut engine = Engine::new().with_options(vec![
+        ("Threads".to_string(), "1000".to_string()),
+        ("Hash".to_string(), "1".to_string()),
+    ]);
+
+    let limits = || SearchLimits {
+        nodes: Some(1000),
+        ..SearchLimits::default()
+    };
+
+    assert!(matches!(
+        engine.search(&Board::default(), limits()),
+        Err(EngineError::OutOfRange {
+            option: "Threads",
+            ..
+        })
+    ));
+
+    // the other options were set, and the engine still searches
+    assert!(engine
+        .search(&Board::default(), limits())
+        .unwrap()
+        .is_some());
+}
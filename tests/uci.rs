@@ -0,0 +1,875 @@
+use core::time::Duration;
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc,
+};
+
+const TIMEOUT: Duration = Duration::from_mins(1);
+
+fn run(commands: &str) -> Vec<String> {
+    run_with_args(&[], commands)
+}
+
+fn run_with_args(args: &[&str], commands: &str) -> Vec<String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_eccat"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(commands.as_bytes())
+        .unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::spawn(move || {
+        let _ = tx.send(child.wait_with_output());
+    });
+
+    let output = rx
+        .recv_timeout(TIMEOUT)
+        .expect("engine did not quit")
+        .unwrap();
+
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(ToString::to_string)
+        .collect()
+}
+
+struct Session {
+    child: Child,
+    stdin: ChildStdin,
+    lines: mpsc::Receiver<String>,
+}
+
+impl Session {
+    fn start() -> Self {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_eccat"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+
+        let stdin = child.stdin.take().unwrap();
+
+        let (tx, lines) = mpsc::channel();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+
+        std::thread::spawn(move || {
+            for line in stdout.lines() {
+                let _ = tx.send(line.unwrap());
+            }
+        });
+
+        Self {
+            child,
+            stdin,
+            lines,
+        }
+    }
+
+    fn send(&mut self, commands: &str) {
+        self.stdin.write_all(commands.as_bytes()).unwrap();
+    }
+
+    fn read_until(&self, prefix: &str) -> Vec<String> {
+        let mut output = Vec::new();
+
+        while !output
+            .last()
+            .is_some_and(|line: &String| line.starts_with(prefix))
+        {
+            output.push(self.lines.recv_timeout(TIMEOUT).expect("engine went quiet"));
+        }
+
+        output
+    }
+
+    fn quit(mut self) {
+        self.send("quit\n");
+        self.child.wait().unwrap();
+    }
+}
+
+/// Only sends `quit` once the search has sent its bestmove.
+fn search(commands: &str) -> Vec<String> {
+    let mut session = Session::start();
+
+    session.send(commands);
+    let output = session.read_until("bestmove");
+
+    session.quit();
+
+    output
+}
+
+fn bestmoves(output: &[String]) -> usize {
+    output
+        .iter()
+        .filter(|line| line.starts_with("bestmove"))
+        .count()
+}
+
+#[test]
+fn test_stop_after_every_go() {
+    let output = run(&format!("{}quit\n", "go infinite\nstop\n".repeat(50)));
+
+    assert_eq!(bestmoves(&output), 50);
+}
+
+#[test]
+fn test_stop_racing_finished_searches() {
+    let output = run(&format!(
+        "{}quit\n",
+        "go movetime 1\nstop\ngo depth 1\nstop\nstop\n".repeat(50)
+    ));
+
+    assert_eq!(bestmoves(&output), 100);
+}
+
+#[test]
+fn test_go_while_searching() {
+    let output = run("go infinite\ngo infinite\ngo depth 3\nstop\ngo depth 1\ngo depth 1\nquit\n");
+
+    assert_eq!(bestmoves(&output), 2);
+}
+
+#[test]
+fn test_quit_during_search() {
+    let output = run("position startpos\ngo infinite\nquit\n");
+
+    assert_eq!(bestmoves(&output), 1);
+}
+
+#[test]
+fn test_quit_on_eof() {
+    // run closes the engine's input once everything is written
+    let output = run("isready\ngo depth 3\n");
+
+    assert!(output.iter().any(|line| line == "readyok"));
+    assert_eq!(bestmoves(&output), 1);
+
+    let output = run("position startpos\ngo infinite\n");
+
+    assert_eq!(bestmoves(&output), 1);
+}
+
+#[test]
+fn test_stop_without_go() {
+    let output = run("stop\nponderhit\nstop\nisready\nquit\n");
+
+    assert_eq!(bestmoves(&output), 0);
+    assert!(output.iter().any(|line| line == "readyok"));
+}
+
+#[test]
+fn test_ponder() {
+    let output = run("go ponder\nponderhit\ngo ponder\nstop\ngo ponder\nquit\n");
+
+    assert_eq!(bestmoves(&output), 3);
+}
+
+#[test]
+fn test_ponder_hit_keeps_the_clock() {
+    let mut session = Session::start();
+
+    session.send("position startpos\ngo ponder wtime 1000 btime 1000\nponderhit\n");
+
+    // the search plays on under the clock without waiting for stop
+    assert_eq!(bestmoves(&session.read_until("bestmove")), 1);
+
+    session.quit();
+}
+
+#[test]
+fn test_position_during_search() {
+    let output = run("go infinite\nposition startpos moves e2e4\nstop\ngo depth 1\nquit\n");
+
+    assert_eq!(bestmoves(&output), 2);
+}
+
+#[test]
+fn test_board_during_search() {
+    let mut session = Session::start();
+
+    // the search has its own copy, so the new position shows at once and
+    // the running search isn't disturbed by it
+    session.send("go infinite\nposition startpos moves e2e4\nboard\n");
+
+    let output = session.read_until("fen:");
+
+    assert!(output
+        .last()
+        .unwrap()
+        .contains("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b"));
+
+    session.send("stop\n");
+
+    assert_eq!(bestmoves(&session.read_until("bestmove")), 1);
+
+    session.quit();
+}
+
+#[test]
+fn test_display() {
+    let output = run("position startpos moves f2f3 e7e5 g2g4 d8h4\nd\nquit\n");
+
+    assert!(output.contains(
+        &"fen:      rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3".to_string()
+    ));
+    assert!(output.contains(&"checkers: h4".to_string()));
+    assert!(output.iter().any(|line| line.starts_with("eval:")));
+}
+
+#[test]
+fn test_fen() {
+    let output = run("position startpos\nmake g1f3\nmake g8f6\nfen\nquit\n");
+
+    assert_eq!(
+        output.last().unwrap(),
+        "rnbqkb1r/pppppppp/5n2/8/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 2 2"
+    );
+}
+
+#[test]
+fn test_flip() {
+    let output = run("position startpos moves e2e4\nflip\nd\nquit\n");
+
+    assert!(output.contains(
+        &"fen:      rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2".to_string()
+    ));
+
+    // the side in check can't pass
+    let output = run("position startpos moves f2f3 e7e5 g2g4 d8h4\nflip\nd\nquit\n");
+
+    assert!(output
+        .iter()
+        .any(|line| line.starts_with("info string error: ")));
+    assert!(output.iter().any(|line| line.ends_with(" w KQkq - 1 3")));
+}
+
+#[test]
+fn test_undo() {
+    let output = run("position startpos\nmake e2e4\nflip\nmake d2d4\nundo\nundo\nd\nquit\n");
+
+    assert!(output
+        .iter()
+        .any(|line| line.ends_with("PPPP1PPP/RNBQKBNR b KQkq e3 0 1")));
+
+    // there's nothing to take back past the last position command
+    let output = run("position startpos moves e2e4\nundo\nd\nquit\n");
+
+    assert!(output
+        .iter()
+        .any(|line| line == "info string error: no move to undo"));
+    assert!(output.iter().any(|line| line.ends_with(" b KQkq e3 0 1")));
+}
+
+#[test]
+fn test_moves() {
+    let output = run("position fen 4k3/8/5n2/3p4/4P3/8/8/3QK3 w - - 0 1\nmoves\nquit\n");
+    let listed = output
+        .iter()
+        .skip_while(|line| !line.ends_with("legal moves"))
+        .collect::<Vec<_>>();
+
+    assert_eq!(listed[0], "20 legal moves");
+    assert_eq!(listed.len(), 21);
+    assert!(listed[1].starts_with("  e4d5   capture, mvv-lva 5, history 0"));
+}
+
+#[test]
+fn test_no_legal_moves() {
+    let output = run("position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1\ngo depth 3\nquit\n");
+
+    assert_eq!(
+        output
+            .iter()
+            .filter(|line| line.starts_with("bestmove"))
+            .collect::<Vec<_>>(),
+        ["bestmove 0000"]
+    );
+}
+
+#[test]
+fn test_command_flood_during_search() {
+    let output = run(&format!(
+        "go infinite\n{}isready\nstop\nquit\n",
+        "setoption name Contempt value 10\nstop\n".repeat(2000)
+    ));
+
+    assert_eq!(bestmoves(&output), 1);
+    assert!(output.iter().any(|line| line == "readyok"));
+}
+
+fn infos(output: &[String]) -> Vec<(u64, u64)> {
+    output
+        .iter()
+        .filter(|line| line.starts_with("info depth") && line.contains(" score "))
+        .map(|line| {
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let value = |name| {
+                let idx = fields.iter().position(|field| *field == name).unwrap();
+                fields[idx + 1].parse::<u64>().unwrap()
+            };
+
+            (value("depth"), value("nodes"))
+        })
+        .collect()
+}
+
+#[test]
+fn test_combined_limits() {
+    let output = search("position startpos\ngo depth 30 nodes 3000\n");
+
+    assert!(infos(&output).iter().all(|&(_, nodes)| nodes <= 3000));
+    assert!(infos(&output).last().unwrap().0 < 30);
+
+    let output = search("position startpos\ngo depth 3 movetime 60000\n");
+
+    assert_eq!(infos(&output).last().unwrap().0, 3);
+}
+
+#[test]
+fn test_permanent_brain() {
+    let mut session = Session::start();
+
+    session.send("setoption name PermanentBrain value true\nposition startpos\n");
+    session.send("go wtime 2000 btime 2000\n");
+
+    let output = session.read_until("bestmove");
+    let pv = output
+        .iter()
+        .rev()
+        .find_map(|line| line.split_once(" pv "))
+        .unwrap()
+        .1
+        .split_whitespace()
+        .take(2)
+        .collect::<Vec<_>>();
+
+    // the predicted reply came, so the search thinking about it answers
+    session.send(&format!("position startpos moves {}\n", pv.join(" ")));
+    session.send("go wtime 2000 btime 2000\n");
+
+    assert_eq!(bestmoves(&session.read_until("bestmove")), 1);
+
+    // thinking ahead never answers a go that wasn't sent
+    session.send("stop\nisready\n");
+
+    assert_eq!(bestmoves(&session.read_until("readyok")), 0);
+
+    session.quit();
+}
+
+#[test]
+fn test_threads() {
+    let output = search("setoption name Threads value 4\nposition startpos\ngo depth 6\n");
+
+    assert_eq!(infos(&output).last().unwrap().0, 6);
+    assert_eq!(bestmoves(&output), 1);
+
+    // the helpers' nodes count towards the limit too, give or take what each
+    // searches between checks
+    let output = search("setoption name Threads value 4\nposition startpos\ngo nodes 300000\n");
+
+    assert!(infos(&output)
+        .iter()
+        .all(|&(_, nodes)| nodes <= 300_000 + 3 * 0x4000));
+
+    let output = search(
+        "setoption name Threads value 4\n\
+         position fen 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1\n\
+         go depth 6 searchmoves g1f1 d1d2\n",
+    );
+
+    assert!(["bestmove g1f1", "bestmove d1d2"].contains(&output.last().unwrap().as_str()));
+}
+
+#[test]
+fn test_go_nodes_is_reproducible() {
+    let commands = "position startpos moves e2e4 c7c5\ngo nodes 20000\n";
+
+    let first = search(commands);
+    let second = search(commands);
+
+    assert!(infos(&first).iter().all(|&(_, nodes)| nodes <= 20000));
+    assert_eq!(infos(&first), infos(&second));
+    assert_eq!(first.last(), second.last());
+}
+
+#[test]
+fn test_go_mate() {
+    let output = search("position fen 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1\ngo mate 1\n");
+
+    assert!(output.iter().any(|line| line.contains("score mate 1 ")));
+    assert_eq!(output.last().unwrap(), "bestmove d1d8");
+
+    // there's no mate to find, so the search gives up once deep enough
+    let output = search("position startpos\ngo mate 2\n");
+
+    assert!(infos(&output).last().unwrap().0 <= 8);
+}
+
+#[test]
+fn test_bench() {
+    let output = run("bench 4\nquit\n");
+    let fields = output
+        .last()
+        .unwrap()
+        .split_whitespace()
+        .collect::<Vec<_>>();
+
+    assert!(matches!(fields[..], [nodes, "nodes", nps, "nps"]
+        if nodes.parse::<u64>().is_ok() && nps.parse::<u64>().is_ok()));
+}
+
+#[test]
+fn test_search_moves() {
+    let output = search("position startpos\ngo depth 5 searchmoves a2a3 h2h4\n");
+
+    assert!(["bestmove a2a3", "bestmove h2h4"].contains(&output.last().unwrap().as_str()));
+
+    // the mate is left out, so some other move has to be played
+    let output = search(
+        "position fen 6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1\ngo depth 3 searchmoves g1f1 d1d2\n",
+    );
+
+    assert!(["bestmove g1f1", "bestmove d1d2"].contains(&output.last().unwrap().as_str()));
+}
+
+#[test]
+fn test_seldepth_counts_quiescence() {
+    let output = search(
+        "position fen r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1\n\
+         go depth 1\n",
+    );
+
+    let seldepth = output
+        .iter()
+        .find_map(|line| line.split_once(" seldepth "))
+        .and_then(|(_, rest)| rest.split_whitespace().next()?.parse::<u8>().ok())
+        .unwrap();
+
+    // a depth 1 search only gets past ply 1 by resolving captures
+    assert!(seldepth > 1);
+}
+
+#[test]
+fn test_move_overhead() {
+    // with the overhead taken off there's almost nothing left on the clock
+    let output = search(
+        "setoption name MoveOverhead value 5000\nposition startpos\ngo wtime 5100 btime 5100\n",
+    );
+
+    assert!(infos(&output).last().unwrap().0 <= 2);
+}
+
+#[test]
+fn test_infinite_waits_for_stop() {
+    let mut session = Session::start();
+
+    // with no legal moves the search is over at once, but go infinite still
+    // only answers after stop
+    session.send("position fen 7k/5Q2/6K1/8/8/8/8/8 b - - 0 1\ngo infinite\n");
+    session.send("isready\n");
+
+    assert_eq!(bestmoves(&session.read_until("readyok")), 0);
+
+    session.send("stop\n");
+
+    assert_eq!(
+        session.read_until("bestmove").last().unwrap(),
+        "bestmove 0000"
+    );
+
+    // the same goes for a ponder search, which ponderhit also releases
+    session.send("go ponder\nisready\n");
+
+    assert_eq!(bestmoves(&session.read_until("readyok")), 0);
+
+    session.send("ponderhit\n");
+
+    assert_eq!(
+        session.read_until("bestmove").last().unwrap(),
+        "bestmove 0000"
+    );
+
+    session.quit();
+}
+
+#[test]
+fn test_only_move() {
+    for go in [
+        "go wtime 60000 btime 60000",
+        "go movetime 60000",
+        "go depth 60",
+        "go nodes 100000000",
+    ] {
+        let output = search(&format!(
+            "position fen k7/8/8/8/8/8/1q6/K7 w - - 0 1\n{go}\n"
+        ));
+
+        assert!(output.contains(&"info string only one legal move".to_string()));
+        assert_eq!(output.last().unwrap(), "bestmove a1b2");
+    }
+
+    // an infinite search still searches the move until it's stopped
+    let mut session = Session::start();
+
+    session.send("position fen k7/8/8/8/8/8/1q6/K7 w - - 0 1\ngo infinite\nisready\n");
+
+    let output = session.read_until("readyok");
+
+    assert!(!output.contains(&"info string only one legal move".to_string()));
+    assert_eq!(bestmoves(&output), 0);
+
+    session.send("stop\n");
+
+    assert_eq!(
+        session.read_until("bestmove").last().unwrap(),
+        "bestmove a1b2"
+    );
+
+    session.quit();
+}
+
+#[test]
+fn test_depth_beyond_max_ply() {
+    let output = search("position fen 8/8/8/4k3/8/8/8/4K3 w - - 0 1\ngo depth 255\n");
+
+    assert_eq!(infos(&output).last().unwrap().0, 127);
+    assert_eq!(bestmoves(&output), 1);
+}
+
+#[test]
+fn test_current_move() {
+    let mut session = Session::start();
+
+    session.send("position startpos\ngo infinite\n");
+
+    // each root move is reported once the search has run for a second
+    let line = loop {
+        let line = session.read_until("info depth").pop().unwrap();
+
+        if line.contains(" currmove ") {
+            break line;
+        }
+    };
+
+    assert!(line.contains(" currmovenumber "));
+
+    session.send("stop\n");
+    session.read_until("bestmove");
+    session.quit();
+}
+
+#[test]
+fn test_show_wdl() {
+    let output = search("position startpos\ngo depth 3\n");
+
+    assert!(!output.iter().any(|line| line.contains(" wdl ")));
+
+    let output = search("setoption name UCI_ShowWDL value true\nposition startpos\ngo depth 3\n");
+
+    let wdl = output
+        .iter()
+        .filter_map(|line| line.split_once(" wdl "))
+        .map(|(_, rest)| {
+            rest.split_whitespace()
+                .take(3)
+                .map(|n| n.parse::<u32>().unwrap())
+                .sum::<u32>()
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(wdl, [1000; 3]);
+}
+
+#[test]
+fn test_score_bounds() {
+    // black's score drops at depth 6, below the window around depth 5's
+    let output = search("position startpos moves e2e4 d7d5 e4d5 d8d5 b1c3\ngo depth 8\n");
+
+    let scores = output
+        .iter()
+        .filter(|line| line.contains(" score "))
+        .collect::<Vec<_>>();
+
+    assert!(scores
+        .iter()
+        .any(|line| line.contains(" upperbound ") || line.contains(" lowerbound ")));
+
+    let last = scores.last().unwrap();
+
+    assert!(last.starts_with("info depth 8 "));
+    assert!(!last.contains("bound"));
+}
+
+#[test]
+fn test_config_file() {
+    let path = std::env::temp_dir().join(format!("eccat-config-{}.toml", std::process::id()));
+
+    std::fs::write(&path, "# defaults\nHash = 32\nMoveOverhead = 50\n").unwrap();
+
+    let output = run_with_args(&["--config", path.to_str().unwrap()], "options\nquit\n");
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.iter().any(|line| line == "  Hash = 32"));
+    assert!(output.iter().any(|line| line == "  MoveOverhead = 50"));
+}
+
+#[test]
+fn test_randompos() {
+    let output = run("randompos 10\nfen\nundo\nquit\n");
+
+    let fens = output
+        .iter()
+        .filter(|line| line.matches('/').count() == 7)
+        .collect::<Vec<_>>();
+
+    // printed by randompos, then by fen
+    assert_eq!(fens.len(), 2);
+    assert_eq!(fens[0], fens[1]);
+    assert!(fens[0].ends_with(" 6"));
+    assert_ne!(
+        fens[0].as_str(),
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+    );
+
+    // there's no move to take back to the start position
+    assert!(output
+        .iter()
+        .any(|line| line == "info string error: no move to undo"));
+}
+
+#[test]
+fn test_serve() {
+    let (mut server, address) = serve_shared_hash();
+
+    // two sessions at once, each with a position of its own
+    let sessions = ["startpos", "startpos moves e2e4"].map(|position| {
+        let mut stream = std::net::TcpStream::connect(&address).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        write!(stream, "position {position}\nfen\ngo depth 3\nquit\n").unwrap();
+
+        stream
+    });
+
+    let outputs = sessions.map(|stream| {
+        BufReader::new(stream)
+            .lines()
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+    });
+
+    let _ = server.kill();
+    let _ = server.wait();
+
+    assert!(outputs[0]
+        .iter()
+        .any(|line| line == "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+    assert!(outputs[1]
+        .iter()
+        .any(|line| line.starts_with("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b")));
+    assert!(outputs
+        .iter()
+        .all(|output| output.iter().any(|line| line.starts_with("bestmove "))));
+}
+
+#[test]
+fn test_serve_concurrent_searches() {
+    let (mut server, address) = serve_shared_hash();
+
+    let connect = || {
+        let stream = std::net::TcpStream::connect(&address).unwrap();
+        stream.set_read_timeout(Some(TIMEOUT)).unwrap();
+
+        let lines = BufReader::new(stream.try_clone().unwrap()).lines();
+
+        (stream, lines.map(Result::unwrap))
+    };
+
+    let (mut searching, mut searching_lines) = connect();
+    let (mut other, mut other_lines) = connect();
+
+    writeln!(searching, "position startpos\ngo infinite").unwrap();
+
+    assert!(searching_lines
+        .by_ref()
+        .any(|line| line.starts_with("info depth ")));
+
+    // a whole search, a new game and a new hash size in another session,
+    // while the first is still searching the same table
+    write!(
+        other,
+        "ucinewgame\nsetoption name Hash value 2\nposition startpos moves e2e4\ngo depth 5\n"
+    )
+    .unwrap();
+
+    let other_output = other_lines
+        .by_ref()
+        .take_while(|line| !line.starts_with("bestmove "))
+        .collect::<Vec<_>>();
+
+    writeln!(other, "quit").unwrap();
+    writeln!(searching, "stop\nquit").unwrap();
+
+    let searching_output = searching_lines.collect::<Vec<_>>();
+
+    let _ = server.kill();
+    let _ = server.wait();
+
+    assert!(other_output
+        .iter()
+        .any(|line| line == "info string the hash table is shared, so it keeps its size"));
+    assert!(other_output
+        .iter()
+        .any(|line| line.starts_with("info depth 5 ")));
+    assert!(searching_output
+        .iter()
+        .any(|line| line.starts_with("bestmove ")));
+}
+
+fn serve_shared_hash() -> (Child, String) {
+    let mut server = Command::new(env!("CARGO_BIN_EXE_eccat"))
+        .args([
+            "serve",
+            "127.0.0.1:0",
+            "--sessions",
+            "2",
+            "--shared-hash",
+            "1",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stdout = BufReader::new(server.stdout.take().unwrap());
+    let mut listening = String::new();
+    stdout.read_line(&mut listening).unwrap();
+
+    let address = listening.trim().strip_prefix("listening on ").unwrap();
+
+    // kept open for the server to report its sessions to
+    server.stdout = Some(stdout.into_inner());
+
+    (server, address.to_string())
+}
+
+#[test]
+fn test_errors_reach_the_gui() {
+    let output = run("setoption name Hash value lots\nmake e2e5\nisready\nquit\n");
+
+    assert!(output
+        .iter()
+        .any(|line| line.starts_with("info string error: invalid value for Hash option")));
+    assert!(output
+        .iter()
+        .any(|line| line.starts_with("info string error: ") && line.contains("illegal")));
+    assert!(output.iter().any(|line| line == "readyok"));
+}
+
+fn mate_score(output: &[String]) -> Option<i32> {
+    output
+        .iter()
+        .rev()
+        .find_map(|line| line.split_once(" score "))
+        .and_then(|(_, score)| score.strip_prefix("mate ")?.split_whitespace().next())
+        .and_then(|moves| moves.parse().ok())
+}
+
+#[test]
+fn test_mate_distance_kept_across_searches() {
+    let mut session = Session::start();
+
+    session.send("position fen 6k1/8/4K3/8/8/8/8/R7 w - - 0 1\ngo depth 12\n");
+
+    assert_eq!(mate_score(&session.read_until("bestmove")), Some(3));
+
+    // the mates stored by the first search are now two plies closer to the
+    // root, and have to be read back that way
+    session.send("position fen 6k1/8/4K3/8/8/8/8/R7 w - - 0 1 moves e6f6 g8h7\ngo depth 12\n");
+
+    assert_eq!(mate_score(&session.read_until("bestmove")), Some(2));
+
+    session.quit();
+}
+
+#[test]
+fn test_deterministic_time_limits() {
+    // a clock limit in deterministic mode is a node budget, so the same
+    // search stops at the same point however fast it runs
+    let nodes_and_pv = || {
+        let output = search(
+            "setoption name Deterministic value true\n\
+             setoption name Threads value 2\n\
+             position startpos moves e2e4\n\
+             go movetime 20\n",
+        );
+
+        let summary = output
+            .iter()
+            .rev()
+            .find(|line| line.contains(" pv "))
+            .unwrap()
+            .clone();
+
+        let field = |name: &str| summary.split_once(name).unwrap().1.to_string();
+        let nodes = field(" nodes ")
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .to_string();
+
+        (nodes, field(" pv "))
+    };
+
+    let first = nodes_and_pv();
+
+    assert_eq!(nodes_and_pv(), first);
+}
+
+#[test]
+fn test_debug_logging() {
+    let stderr = |commands: &str| {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_eccat"))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(commands.as_bytes())
+            .unwrap();
+
+        String::from_utf8(child.wait_with_output().unwrap().stderr).unwrap()
+    };
+
+    // time for the search to finish before quit stops it
+    let commands = "go depth 3\nsleep 500\nquit\n";
+
+    assert!(!stderr(commands).contains("iteration"));
+
+    let logs = stderr(&format!("debug on\n{commands}"));
+
+    assert!(logs.contains("command{command=Go"));
+    assert!(logs.contains("iteration{thread=0 depth=1}"));
+}